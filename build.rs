@@ -0,0 +1,10 @@
+// Generates the gRPC server of proto/coold.proto. protox parses the file in
+// Rust, so building doesn't need protoc installed.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/coold.proto");
+    let descriptors = protox::compile(["proto/coold.proto"], ["proto"])?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)?;
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::log;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const WEBHOOK_ATTEMPTS: u32 = 3;
+// Doubled after every failed attempt
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A condition watched on one or every fan, reported to webhooks once it has
+/// held for `for_secs`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct AlertRule {
+    pub name: String,
+    /// Fan the rule applies to, every fan when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan: Option<String>,
+    pub condition: AlertCondition,
+    /// Seconds the condition has to hold before the alert fires
+    #[serde(default)]
+    pub for_secs: u64,
+    /// URLs the JSON payload is POSTed to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<String>,
+    /// Also pop a desktop notification, for daemons running in a user session
+    #[serde(default)]
+    pub desktop: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Temperature above `temp` °C
+    TempAbove { temp: i32 },
+    /// Fan driven above 0% but its tachometer reads 0 RPM
+    FanStalled,
+    /// Temperature sensor can't be read
+    SensorMissing,
+    /// Fan speed off its rpm_curve for several cycles
+    FanFailing,
+}
+
+/// What a control cycle saw for a fan
+#[derive(Debug, Default)]
+pub struct Observation {
+    /// Filtered temperature, None if no plausible reading was available
+    pub temp: Option<i32>,
+    pub sensor_missing: bool,
+    pub power: Option<u8>,
+    pub rpm: Option<u32>,
+    /// Speed off rpm_curve, None without a curve or a reading
+    pub failing: Option<bool>,
+}
+
+impl AlertCondition {
+    // None when the observation says nothing about the condition
+    fn holds(&self, observation: &Observation) -> Option<bool> {
+        match self {
+            AlertCondition::TempAbove { temp } => observation.temp.map(|current| current > *temp),
+            AlertCondition::FanStalled => match (observation.power, observation.rpm) {
+                (Some(power), Some(rpm)) => Some(power > 0 && rpm == 0),
+                _ => None,
+            },
+            AlertCondition::SensorMissing => Some(observation.sensor_missing),
+            AlertCondition::FanFailing => observation.failing,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertEventKind {
+    Triggered,
+    Resolved,
+    Test,
+}
+
+/// Payload POSTed to the webhooks
+#[derive(Debug, Serialize, Clone)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub fan: Option<String>,
+    pub event: AlertEventKind,
+    pub condition: AlertCondition,
+    pub temperature: Option<i32>,
+    pub power: Option<u8>,
+    pub rpm: Option<u32>,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl AlertEvent {
+    fn new(rule: &AlertRule, fan: Option<&str>, event: AlertEventKind, observation: &Observation) -> Self {
+        Self {
+            rule: rule.name.clone(),
+            fan: fan.map(str::to_string),
+            event,
+            condition: rule.condition.clone(),
+            temperature: observation.temp,
+            power: observation.power,
+            rpm: observation.rpm,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        }
+    }
+}
+
+// A condition currently holding for a (rule, fan) pair
+struct Pending {
+    since: Instant,
+    fired: bool,
+}
+
+/// Tracks how long each rule's condition has held on each fan
+#[derive(Default)]
+pub struct AlertTracker {
+    pending: HashMap<(String, String), Pending>,
+}
+
+impl AlertTracker {
+    /// Feed a fan's observation and return the alerts to send, with their rule
+    pub fn observe<'a>(&mut self, rules: &'a [AlertRule], fan: &str, observation: &Observation) -> Vec<(&'a AlertRule, AlertEvent)> {
+        // Forget rules that were removed or renamed
+        self.pending.retain(|(rule, pending_fan), _| pending_fan != fan || rules.iter().any(|r| &r.name == rule));
+
+        let mut events = Vec::new();
+        for rule in rules.iter().filter(|rule| rule.fan.as_deref().is_none_or(|f| f == fan)) {
+            let key = (rule.name.clone(), fan.to_string());
+            match rule.condition.holds(observation) {
+                Some(true) => {
+                    let pending = self.pending.entry(key).or_insert(Pending { since: Instant::now(), fired: false });
+                    if !pending.fired && pending.since.elapsed() >= Duration::from_secs(rule.for_secs) {
+                        pending.fired = true;
+                        events.push((rule, AlertEvent::new(rule, Some(fan), AlertEventKind::Triggered, observation)));
+                    }
+                }
+                Some(false) if self.pending.remove(&key).is_some_and(|pending| pending.fired) => {
+                    events.push((rule, AlertEvent::new(rule, Some(fan), AlertEventKind::Resolved, observation)));
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+fn post_with_retry(client: &reqwest::blocking::Client, url: &str, event: &AlertEvent) -> Result<(), String> {
+    let mut delay = WEBHOOK_RETRY_DELAY;
+    let mut last_error = String::new();
+    for attempt in 1..=WEBHOOK_ATTEMPTS {
+        match client.post(url).json(event).send().and_then(|response| response.error_for_status()) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = e.to_string(),
+        }
+        if attempt < WEBHOOK_ATTEMPTS {
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+    Err(last_error)
+}
+
+fn webhook_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn describe(event: &AlertEvent) -> String {
+    let fan = event.fan.as_deref().unwrap_or("every fan");
+    let what = match &event.condition {
+        AlertCondition::TempAbove { temp } => format!("{} is above {}°C", fan, temp),
+        AlertCondition::FanStalled => format!("{} stalled", fan),
+        AlertCondition::SensorMissing => format!("sensor of {} is missing", fan),
+        AlertCondition::FanFailing => format!("{} is not running at its expected speed", fan),
+    };
+    match event.event {
+        AlertEventKind::Triggered => what,
+        AlertEventKind::Resolved => format!("Resolved: {}", what),
+        AlertEventKind::Test => format!("Test: {}", what),
+    }
+}
+
+// Desktop notification, sent to the session's notification daemon over D-Bus
+#[cfg(feature = "desktop-notifications")]
+fn notify_desktop(event: &AlertEvent) -> Result<(), String> {
+    use notify_rust::{Notification, Urgency};
+
+    let urgency = if event.event == AlertEventKind::Triggered { Urgency::Critical } else { Urgency::Normal };
+    Notification::new()
+        .appname("coold-rs")
+        .summary(&format!("coold-rs: {}", event.rule))
+        .body(&describe(event))
+        .urgency(urgency)
+        .show()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to send notification: {}", e))
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn notify_desktop(_event: &AlertEvent) -> Result<(), String> {
+    Err("coold-rs was built without the desktop-notifications feature".to_string())
+}
+
+/// Send an alert to the webhooks of its rule in the background, so retries
+/// never hold up fan control
+pub fn send(rule: &AlertRule, event: AlertEvent) {
+    log!("Alert: {} - {:?} on {}", event.rule, event.event, event.fan.as_deref().unwrap_or("-"));
+    if rule.desktop {
+        if let Err(e) = notify_desktop(&event) {
+            log!("Failed to show desktop notification for {}: {}", event.rule, e);
+        }
+    }
+    let webhooks = rule.webhooks.clone();
+    if webhooks.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        let client = match webhook_client() {
+            Ok(client) => client,
+            Err(e) => {
+                log!("Failed to create webhook client: {}", e);
+                return;
+            }
+        };
+        for url in webhooks {
+            if let Err(e) = post_with_retry(&client, &url, &event) {
+                log!("Failed to deliver alert {} to {}: {}", event.rule, url, e);
+            }
+        }
+    });
+}
+
+/// Result of a test-fired webhook, or of the desktop notification (url "desktop")
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookResult {
+    pub rule: String,
+    pub url: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Send a test event to the webhooks and desktop of `rule`, or of every rule, waiting for the results
+pub fn test_fire(rules: &[AlertRule], rule: Option<&str>) -> Vec<WebhookResult> {
+    let client = match webhook_client() {
+        Ok(client) => client,
+        Err(e) => {
+            log!("Failed to create webhook client: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut results = Vec::new();
+    for rule in rules.iter().filter(|r| rule.is_none_or(|name| r.name == name)) {
+        let event = AlertEvent::new(rule, rule.fan.as_deref(), AlertEventKind::Test, &Observation::default());
+        if rule.desktop {
+            let error = notify_desktop(&event).err();
+            results.push(WebhookResult {
+                rule: rule.name.clone(),
+                url: "desktop".to_string(),
+                success: error.is_none(),
+                error,
+            });
+        }
+        for url in &rule.webhooks {
+            let error = post_with_retry(&client, url, &event).err();
+            results.push(WebhookResult {
+                rule: rule.name.clone(),
+                url: url.clone(),
+                success: error.is_none(),
+                error,
+            });
+        }
+    }
+    results
+}
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::daemon::{DaemonState, DeviceMatch, FanBackend, FanConfig, FanStep, LoadStep, Prediction, PwmEnableValues, PwmMode, RiseBoost, RpmPoint, RpmStep, SensorConfig, SensorSource, SpinUp, TempRange, TimeOfDay};
+use crate::expr::PowerExpr;
+use crate::script::ScriptPolicy;
+
+/// Header carrying the ID of each call, taken from the client when it sends a valid one
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Body of every response: whether the call succeeded, what happened, and its result
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<T>,
+}
+
+/// State of a controlled fan, as listed by `/status`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FanStatus {
+    pub name: String,
+    pub temperature: Option<i32>,
+    /// Temperature the curve is followed at, ahead of a rising reading with predict
+    pub predicted_temperature: Option<i32>,
+    pub power: Option<u8>,
+    pub sensor_input: String,
+    pub pwm_input: String,
+    pub steps: Vec<FanStep>,
+    pub control_reasserts: u64,
+    pub override_power: Option<u8>,
+    pub boost_remaining_secs: Option<u64>,
+    pub critical: bool,
+    pub rpm: Option<u32>,
+    pub expected_rpm: Option<u32>,
+    pub target_rpm: Option<u32>,
+    pub failing: bool,
+    pub sensor_offset: Option<f64>,
+    pub sensor_scale: Option<f64>,
+    pub group: Option<String>,
+    pub demand: Option<u8>,
+    pub max_power: Option<u8>,
+    pub capped: bool,
+    pub semi_passive: bool,
+    pub stalled: bool,
+    pub owned: bool,
+    /// The chip drives the fan along its programmed curve
+    pub hardware_curve: bool,
+    pub pwm_enable: Option<u8>,
+    pub last_write_error: Option<String>,
+    pub write_errors: u32,
+    pub read_errors: u32,
+    pub self_test_error: Option<String>,
+    /// Why the fan can't be read, written or controlled
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Health {
+    pub state: DaemonState,
+    pub running: bool,
+    /// Fans whose control loop hasn't completed a cycle for too long
+    pub stalled: Vec<String>,
+    /// Why each degraded fan can't be read or written
+    pub problems: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverrideFanRequest {
+    pub power: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoostQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TestAlertsRequest {
+    pub rule: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateFanRequest {
+    pub steps: Vec<FanStep>,
+    /// Replaces the cooldown steps when given, an empty list removing them
+    #[serde(default)]
+    pub cooldown_steps: Option<Vec<FanStep>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateCurveRequest {
+    pub steps: Vec<FanStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NightModeRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub start: Option<TimeOfDay>,
+    #[serde(default)]
+    pub end: Option<TimeOfDay>,
+    #[serde(default)]
+    pub max_power: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AddFanRequest {
+    #[serde(default)]
+    pub sensor_name: String,
+    #[serde(default)]
+    pub sensor_input: String,
+    #[serde(default)]
+    pub sensor: Option<SensorSource>,
+    #[serde(default)]
+    pub sensor_device: Option<DeviceMatch>,
+    pub pwm_name: String,
+    pub pwm_input: String,
+    #[serde(default)]
+    pub pwm_device: Option<DeviceMatch>,
+    #[serde(default)]
+    pub steps: Vec<FanStep>,
+    #[serde(default)]
+    pub curve: Option<String>,
+    #[serde(default)]
+    pub backend: FanBackend,
+    #[serde(default)]
+    pub pwm_period_ns: Option<u64>,
+    #[serde(default)]
+    pub valid_temp_range: Option<TempRange>,
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    #[serde(default)]
+    pub critical_temp: Option<i32>,
+    #[serde(default)]
+    pub rpm_curve: Vec<RpmPoint>,
+    #[serde(default)]
+    pub rpm_tolerance_pct: Option<u8>,
+    #[serde(default)]
+    pub pwm_mode: Option<PwmMode>,
+    #[serde(default)]
+    pub pwm_enable: Option<PwmEnableValues>,
+    #[serde(default)]
+    pub sensor_offset: Option<f64>,
+    #[serde(default)]
+    pub sensor_scale: Option<f64>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub noise_weight: Option<f64>,
+    #[serde(default)]
+    pub follows: Option<String>,
+    #[serde(default)]
+    pub follow_offset: Option<i32>,
+    #[serde(default)]
+    pub follow_ratio: Option<f64>,
+    #[serde(default)]
+    pub min_power: Option<u8>,
+    #[serde(default)]
+    pub max_power: Option<u8>,
+    #[serde(default)]
+    pub spin_up: Option<SpinUp>,
+    #[serde(default)]
+    pub power_step: Option<u8>,
+    #[serde(default)]
+    pub ambient: Option<SensorConfig>,
+    #[serde(default)]
+    pub load_steps: Vec<LoadStep>,
+    #[serde(default)]
+    pub load_window_secs: Option<u64>,
+    #[serde(default)]
+    pub rise_boost: Option<RiseBoost>,
+    #[serde(default)]
+    pub predict: Option<Prediction>,
+    #[serde(default)]
+    pub cooldown_steps: Vec<FanStep>,
+    #[serde(default)]
+    pub rpm_steps: Vec<RpmStep>,
+    #[serde(default)]
+    pub min_dwell_secs: Option<u64>,
+    #[serde(default)]
+    pub dead_band: Option<u32>,
+    #[serde(default)]
+    pub power: Option<PowerExpr>,
+    #[serde(default)]
+    pub script: Option<ScriptPolicy>,
+    #[serde(default)]
+    pub hardware_curve: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DetectRequest {
+    #[serde(default)]
+    pub settle_secs: Option<u64>,
+}
+
+/// Query parameters of `/hwmon_devices`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DeviceQuery {
+    /// Only this device, by name (e.g. nct6798) or directory (e.g. hwmon2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Query parameters of `/fans/{name}/preview`: a single `temp`, or a range
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PreviewQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<i32>,
+    /// Degrees between the points of a range, 5 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<u32>,
+    /// Evaluate the cooldown_steps, followed while the temperature falls
+    #[serde(default)]
+    pub cooling: bool,
+}
+
+/// Power a fan's curve gives at a temperature
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewPoint {
+    pub temp: i32,
+    pub power: u8,
+}
+
+/// Query parameters of `/logs`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LogsQuery {
+    /// Most recent lines to return, 200 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters of `/audit`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AuditQuery {
+    /// Most recent entries to return, 50 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters narrowing `/fans` and `/status` down to some fans
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FanFilter {
+    /// hwmon device the fan is driven by or reads its temperature from, e.g. nct6798
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Temperature input, with or without its `_input` suffix, e.g. temp1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<String>,
+}
+
+impl FanFilter {
+    /// Whether `fan` is one of the fans asked for
+    pub fn matches(&self, fan: &FanConfig) -> bool {
+        // The fan's own sensor, or each input of a combined one
+        let mut sensors = vec![(fan.sensor_name.as_str(), fan.sensor_input.as_str())];
+        if let Some(source) = &fan.sensor {
+            sensors.extend(source.inputs().into_iter()
+                .map(|input| (input.sensor_name.as_str(), input.sensor_input.as_str())));
+        }
+        
+        let device_matches = self.device.as_ref().is_none_or(|device| {
+            fan.pwm_name == *device || sensors.iter().any(|(name, _)| name == device)
+        });
+        let sensor_matches = self.sensor.as_ref().is_none_or(|sensor| {
+            sensors.iter().any(|(_, input)| {
+                let file = Path::new(input).file_name().and_then(|file| file.to_str()).unwrap_or(input);
+                file == sensor || file.strip_suffix("_input") == Some(sensor.as_str())
+            })
+        });
+        device_matches && sensor_matches
+    }
+}
+
+/// Built-in curve template as listed by `/curve_templates`, an owned [`crate::templates::CurveTemplate`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveTemplateInfo {
+    pub name: String,
+    pub description: String,
+    pub steps: Vec<FanStep>,
+}
+
+/// One API call that changed something, written as a JSON line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time of the call
+    pub timestamp: u64,
+    /// Address the call came from (the forwarded one, behind a reverse proxy)
+    pub client: String,
+    /// Name of the API token used, when tokens are configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// ID the call was answered with, in the `X-Request-Id` header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// JSON body of the call, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<serde_json::Value>,
+    /// HTTP status the daemon answered with
+    pub status: u16,
+}
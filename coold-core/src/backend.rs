@@ -0,0 +1,996 @@
+use std::ffi::CString;
+use std::fs::{self, write};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use glob::glob;
+use crate::{os, recording, simulation};
+use crate::daemon::{FanBackend, FanConfig, PwmEnableValues, PwmMode, SensorSource};
+use crate::log;
+
+/// A source of temperature readings for a fan
+pub trait TempSource {
+    /// Read the current temperature in °C
+    fn read_temp(&self) -> io::Result<i32>;
+}
+
+/// An output that drives a fan
+pub trait PwmSink {
+    /// Set the fan output power (0-100%)
+    fn set_power(&self, power: u8) -> io::Result<()>;
+
+    /// Read back the current output power (0-100%)
+    fn read_power(&self) -> Option<u8>;
+
+    /// Read the fan speed in RPM, if the hardware reports it
+    fn read_rpm(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether the output is currently under manual (coold-rs) control
+    fn is_manual(&self) -> bool;
+
+    /// Raw value of the manual/automatic mode file, if the output has one
+    fn read_enable(&self) -> Option<u8> {
+        None
+    }
+
+    /// Switch between manual control and the firmware's automatic mode
+    fn set_manual(&self, manual: bool) -> io::Result<()>;
+
+    /// Switch the header between DC and PWM drive
+    fn set_mode(&self, _mode: PwmMode) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "PWM mode can't be set on this backend"))
+    }
+
+    /// Whether switching modes has to be retried until the driver reports it
+    fn needs_retry(&self) -> bool {
+        true
+    }
+
+    /// Files written while controlling the fan, handed over when dropping privileges
+    fn control_files(&self) -> Vec<PathBuf>;
+}
+
+/// Build the temperature source configured for a fan, corrected by its offset and scale
+pub fn temp_source(fan: &FanConfig) -> Box<dyn TempSource> {
+    // Inputs of combined sensors are simulated and recorded one by one
+    let combined = fan.sensor.as_ref().is_some_and(|source| !source.inputs().is_empty());
+    let mut source = match simulation::temp_source(fan) {
+        Some(source) if !combined => source,
+        _ => raw_temp_source(fan),
+    };
+    if recording::is_active() && !combined {
+        source = Box::new(RecordedTemp { source, sensor: simulation::sensor_key(fan) });
+    }
+    if fan.sensor_offset.is_none() && fan.sensor_scale.is_none() {
+        return source;
+    }
+    Box::new(CalibratedTemp {
+        source,
+        offset: fan.sensor_offset.unwrap_or(0.0),
+        scale: fan.sensor_scale.unwrap_or(1.0),
+    })
+}
+
+fn raw_temp_source(fan: &FanConfig) -> Box<dyn TempSource> {
+    match &fan.sensor {
+        None => Box::new(HwmonTemp { path: PathBuf::from(&fan.sensor_input) }),
+        Some(SensorSource::ThermalZone { zone }) => Box::new(ThermalZoneTemp { zone: zone.clone() }),
+        Some(SensorSource::Remote { url, json_pointer }) => Box::new(RemoteTemp {
+            url: url.clone(),
+            json_pointer: json_pointer.clone(),
+        }),
+        Some(SensorSource::Sysctl { name }) => Box::new(SysctlTemp { name: name.clone() }),
+        Some(SensorSource::HottestDrive) => Box::new(HottestDriveTemp),
+        Some(SensorSource::Command { cmd, timeout_secs }) => Box::new(CommandTemp {
+            cmd: cmd.clone(),
+            timeout: Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS)),
+        }),
+        Some(SensorSource::Weighted { inputs }) => Box::new(WeightedTemp {
+            inputs: inputs.iter().map(|input| (input.weight, temp_source(&input.sensor.as_fan(fan)))).collect(),
+        }),
+        Some(SensorSource::Max { inputs }) => Box::new(MaxTemp {
+            inputs: inputs.iter().map(|input| temp_source(&input.as_fan(fan))).collect(),
+        }),
+        // Left in place by Config::controlled_fans when the sensor isn't defined
+        Some(SensorSource::Virtual { name }) => Box::new(UndefinedTemp { name: name.clone() }),
+    }
+}
+
+/// pwmN_enable values known to work for a driver, by backend and hwmon device name.
+/// Writing 0 hands the fan to full speed on most Super I/O chips, so prefer their automatic mode.
+pub fn default_enable_values(backend: FanBackend, driver: &str) -> PwmEnableValues {
+    let auto = match backend {
+        // thinkpad_acpi and dell-smm-hwmon only accept 1 (manual) and 2 (automatic)
+        FanBackend::ThinkpadAcpi | FanBackend::DellSmm => 2,
+        _ if driver.starts_with("nct67") => 5, // SmartFan IV
+        _ if driver.starts_with("it8") || driver.starts_with("f718") => 2,
+        _ if matches!(driver, "amdgpu" | "radeon" | "asus_wmi_sensors" | "asus") => 2,
+        // The max31790 has no automatic mode, 0 runs the fan at full speed
+        _ => 0,
+    };
+    PwmEnableValues { manual: 1, auto }
+}
+
+/// Backend suited to the outputs of a hwmon device, by its name
+pub fn default_backend(driver: &str) -> FanBackend {
+    match driver {
+        "emc2301" | "emc2302" | "emc2303" | "emc2305" | "max31790" => FanBackend::I2c,
+        _ => FanBackend::Hwmon,
+    }
+}
+
+fn enable_values(fan: &FanConfig) -> PwmEnableValues {
+    fan.pwm_enable.unwrap_or_else(|| default_enable_values(fan.backend, &fan.pwm_name))
+}
+
+static OBSERVE_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Never write to the fan outputs again, for a daemon trialled next to another controller
+pub fn set_observe_only() {
+    OBSERVE_ONLY.store(true, Ordering::SeqCst);
+}
+
+pub fn is_observe_only() -> bool {
+    OBSERVE_ONLY.load(Ordering::SeqCst)
+}
+
+static UNPRIVILEGED: AtomicBool = AtomicBool::new(false);
+
+/// The daemon gave up root (--user): only the control files handed over at startup
+/// can be written from now on
+pub fn set_unprivileged() {
+    UNPRIVILEGED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_unprivileged() -> bool {
+    UNPRIVILEGED.load(Ordering::SeqCst)
+}
+
+/// Error naming the first control file of `fan` the daemon can't write since it
+/// gave up root; outputs can always be written while running as root
+pub fn check_writable(fan: &FanConfig) -> io::Result<()> {
+    if !is_unprivileged() || is_observe_only() {
+        return Ok(());
+    }
+    for file in hardware_pwm_sink(fan).control_files() {
+        let writable = CString::new(file.as_os_str().as_bytes())
+            // SAFETY: access only reads the NUL-terminated path
+            .is_ok_and(|path| unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0);
+        // A missing file is reported when the fan is taken over
+        if !writable && file.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} can't be written after dropping root (--user), restart the daemon to control it", file.display()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Build the PWM output configured for a fan
+pub fn pwm_sink(fan: &FanConfig) -> Box<dyn PwmSink> {
+    if let Some(sink) = simulation::pwm_sink(fan) {
+        return sink;
+    }
+    let sink = hardware_pwm_sink(fan);
+    if is_observe_only() {
+        return Box::new(ReadOnlyPwm { sink });
+    }
+    sink
+}
+
+fn hardware_pwm_sink(fan: &FanConfig) -> Box<dyn PwmSink> {
+    let pwm_input = PathBuf::from(&fan.pwm_input);
+    let enable = enable_values(fan);
+    match fan.backend {
+        FanBackend::Hwmon => Box::new(HwmonPwm { pwm_input, enable }),
+        FanBackend::ThinkpadAcpi => Box::new(ThinkpadPwm { pwm_input, enable }),
+        FanBackend::DellSmm => Box::new(DellSmmPwm { pwm_input, enable }),
+        FanBackend::Pwmchip => Box::new(PwmchipPwm {
+            channel_dir: pwm_input,
+            period_ns: fan.pwm_period_ns.unwrap_or(DEFAULT_PWM_PERIOD_NS),
+        }),
+        FanBackend::I2c => Box::new(I2cPwm { pwm_input, enable }),
+        FanBackend::AcpiIbm => Box::new(AcpiIbmPwm { node: fan.pwm_input.clone() }),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_value<T: std::str::FromStr>(path: &Path) -> io::Result<T> {
+    let content = fs::read_to_string(path)?;
+    content.trim().parse::<T>()
+        .map_err(|_| invalid_data(format!("Unexpected content in {:?}: {}", path, content.trim())))
+}
+
+const PROC_STAT: &str = "/proc/stat";
+
+/// Cumulative CPU time of the whole system, in clock ticks
+#[derive(Debug, Clone, Copy)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// Utilization (%) between an earlier reading and this one
+    pub fn load_since(&self, earlier: &CpuTimes) -> Option<u8> {
+        let total = self.total.checked_sub(earlier.total).filter(|total| *total > 0)?;
+        let busy = self.busy.saturating_sub(earlier.busy);
+        Some((busy * 100 / total).min(100) as u8)
+    }
+}
+
+/// Read the CPU time of the whole system, from /proc/stat or on FreeBSD from `kern.cp_time`
+pub fn read_cpu_times() -> io::Result<CpuTimes> {
+    if os::IS_FREEBSD {
+        read_cp_time()
+    } else {
+        read_proc_stat()
+    }
+}
+
+// Aggregated "cpu" line of /proc/stat
+fn read_proc_stat() -> io::Result<CpuTimes> {
+    let content = fs::read_to_string(PROC_STAT)?;
+    let line = content.lines().find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| invalid_data(format!("No cpu line in {}", PROC_STAT)))?;
+    // user nice system idle iowait irq softirq steal; guest time is already part of user
+    let ticks: Vec<u64> = line.split_whitespace().skip(1).take(8)
+        .map(|value| value.parse().map_err(|_| invalid_data(format!("Unexpected cpu line in {}: {}", PROC_STAT, line))))
+        .collect::<io::Result<_>>()?;
+    if ticks.len() < 4 {
+        return Err(invalid_data(format!("Unexpected cpu line in {}: {}", PROC_STAT, line)));
+    }
+    let total: u64 = ticks.iter().sum();
+    let idle = ticks[3] + ticks.get(4).copied().unwrap_or(0);
+    Ok(CpuTimes { busy: total - idle, total })
+}
+
+// FreeBSD's kern.cp_time: user nice system interrupt idle
+fn read_cp_time() -> io::Result<CpuTimes> {
+    let line = os::read_sysctl("kern.cp_time")?;
+    let ticks: Vec<u64> = line.split_whitespace()
+        .map(|value| value.parse().map_err(|_| invalid_data(format!("Unexpected kern.cp_time: {}", line))))
+        .collect::<io::Result<_>>()?;
+    if ticks.len() != 5 {
+        return Err(invalid_data(format!("Unexpected kern.cp_time: {}", line)));
+    }
+    let total: u64 = ticks.iter().sum();
+    Ok(CpuTimes { busy: total - ticks[4], total })
+}
+
+// Temperature in millidegrees, as used by hwmon and thermal zones
+fn read_millidegrees(path: &Path) -> io::Result<i32> {
+    read_value::<i32>(path).map(|temp| temp / 1000)
+}
+
+/// Current value of a hwmon tempN_input file, in °C
+pub fn read_hwmon_temp(path: &Path) -> io::Result<i32> {
+    read_millidegrees(path)
+}
+
+// Read the manual/auto mode file and compare it with the manual value
+fn read_enable(path: &Path, manual_value: u8) -> bool {
+    read_value::<u8>(path).is_ok_and(|value| value == manual_value)
+}
+
+/// Records the raw readings of a sensor, before any correction
+pub struct RecordedTemp {
+    source: Box<dyn TempSource>,
+    sensor: String,
+}
+
+impl TempSource for RecordedTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let temp = self.source.read_temp()?;
+        recording::record(&self.sensor, temp);
+        Ok(temp)
+    }
+}
+
+/// Corrects readings of a sensor known to be off (e.g. a board reading 7°C high)
+pub struct CalibratedTemp {
+    source: Box<dyn TempSource>,
+    offset: f64,
+    scale: f64,
+}
+
+impl TempSource for CalibratedTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let temp = self.source.read_temp()?;
+        Ok((temp as f64 * self.scale + self.offset).round() as i32)
+    }
+}
+
+/// Weighted average of several sensors, failing if any of them does
+pub struct WeightedTemp {
+    inputs: Vec<(f64, Box<dyn TempSource>)>,
+}
+
+impl TempSource for WeightedTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for (weight, source) in &self.inputs {
+            sum += weight * source.read_temp()? as f64;
+            weights += weight;
+        }
+        if weights <= 0.0 {
+            return Err(io::Error::other("Weighted sensor without positive weights"));
+        }
+        Ok((sum / weights).round() as i32)
+    }
+}
+
+/// Hottest of several sensors, ignoring the ones that can't be read
+pub struct MaxTemp {
+    inputs: Vec<Box<dyn TempSource>>,
+}
+
+impl TempSource for MaxTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let mut last_error = io::Error::other("Max sensor without inputs");
+        let mut max = None;
+        for source in &self.inputs {
+            match source.read_temp() {
+                Ok(temp) => max = max.max(Some(temp)),
+                Err(e) => last_error = e,
+            }
+        }
+        max.ok_or(last_error)
+    }
+}
+
+/// Virtual sensor missing from the configuration
+pub struct UndefinedTemp {
+    name: String,
+}
+
+impl TempSource for UndefinedTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        Err(io::Error::other(format!("Virtual sensor '{}' is not defined", self.name)))
+    }
+}
+
+/// hwmon tempN_input file
+pub struct HwmonTemp {
+    path: PathBuf,
+}
+
+impl TempSource for HwmonTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        read_millidegrees(&self.path)
+    }
+}
+
+const DEFAULT_SYSFS_ROOT: &str = "/sys";
+
+static SYSFS_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Look devices up under `root` instead of /sys, e.g. a bind-mounted fake tree in a
+/// container. Must be called before any device is accessed.
+pub fn set_sysfs_root(root: PathBuf) {
+    if SYSFS_ROOT.set(root).is_err() {
+        log!("sysfs root already set, ignoring");
+    }
+}
+
+/// Directory of a sysfs device class (hwmon, thermal, pwm) under the sysfs root
+pub fn sysfs_class(class: &str) -> PathBuf {
+    SYSFS_ROOT.get().map_or(Path::new(DEFAULT_SYSFS_ROOT), PathBuf::as_path).join("class").join(class)
+}
+
+/// Kernel thermal zone, matched by directory (`thermal_zone0`) or type (`x86_pkg_temp`)
+pub struct ThermalZoneTemp {
+    zone: String,
+}
+
+impl ThermalZoneTemp {
+    fn zone_dir(&self) -> io::Result<PathBuf> {
+        let thermal_root = sysfs_class("thermal");
+        let direct = thermal_root.join(&self.zone);
+        if direct.is_dir() {
+            return Ok(direct);
+        }
+        let pattern = format!("{}/thermal_zone*/type", thermal_root.display());
+        for type_path in glob(&pattern).unwrap().flatten() {
+            if fs::read_to_string(&type_path).is_ok_and(|t| t.trim() == self.zone) {
+                if let Some(dir) = type_path.parent() {
+                    return Ok(dir.to_path_buf());
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("Thermal zone {} not found", self.zone)))
+    }
+}
+
+impl TempSource for ThermalZoneTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        read_millidegrees(&self.zone_dir()?.join("temp"))
+    }
+}
+
+// hwmon devices of SATA/SAS (drivetemp) and NVMe drives
+const DRIVE_HWMON_NAMES: [&str; 2] = ["drivetemp", "nvme"];
+
+/// Hottest of the drives present at each reading, so drives plugged in later are picked up
+pub struct HottestDriveTemp;
+
+impl TempSource for HottestDriveTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let pattern = format!("{}/hwmon*/name", sysfs_class("hwmon").display());
+        glob(&pattern).into_iter().flatten().flatten()
+            .filter(|name_path| fs::read_to_string(name_path).is_ok_and(|name| DRIVE_HWMON_NAMES.contains(&name.trim())))
+            // temp1 is the drive temperature of drivetemp, and the composite one of NVMe drives
+            .filter_map(|name_path| read_hwmon_temp(&name_path.with_file_name("temp1_input")).ok())
+            .max()
+            .ok_or_else(|| io::Error::other("No drive temperature can be read"))
+    }
+}
+
+/// FreeBSD temperature sysctl, e.g. from acpi_thermal, coretemp or amdtemp
+pub struct SysctlTemp {
+    name: String,
+}
+
+impl TempSource for SysctlTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        os::read_sysctl_temp(&self.name)
+    }
+}
+
+/// Temperature fetched over HTTP, either a plain number in °C or a JSON
+/// document with the value selected by a JSON pointer
+pub struct RemoteTemp {
+    url: String,
+    json_pointer: Option<String>,
+}
+
+impl TempSource for RemoteTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let body = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .build()
+            .and_then(|client| client.get(&self.url).send())
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(io::Error::other)?;
+
+        let value = match &self.json_pointer {
+            Some(pointer) => {
+                let json: serde_json::Value = serde_json::from_str(&body)
+                    .map_err(|e| invalid_data(format!("Invalid JSON from {}: {}", self.url, e)))?;
+                json.pointer(pointer).and_then(|v| v.as_f64())
+            }
+            None => body.trim().parse::<f64>().ok(),
+        };
+        value
+            .map(|temp| temp.round() as i32)
+            .ok_or_else(|| invalid_data(format!("No temperature in response from {}", self.url)))
+    }
+}
+
+const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 5;
+
+/// Shell command run every cycle, whose output contains a temperature in °C
+pub struct CommandTemp {
+    cmd: String,
+    timeout: Duration,
+}
+
+// First number in the output, so "CPU Temp: 45.5 C" style output works too
+fn parse_first_number(output: &str) -> Option<f64> {
+    output
+        .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .find_map(|token| token.parse::<f64>().ok())
+}
+
+impl TempSource for CommandTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.cmd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Don't let a hung command stall the control loop
+        let started = Instant::now();
+        while child.try_wait()?.is_none() {
+            if started.elapsed() > self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("Command timed out: {}", self.cmd)));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!("Command failed ({}): {}", output.status, self.cmd)));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        parse_first_number(&stdout)
+            .map(|temp| temp.round() as i32)
+            .ok_or_else(|| invalid_data(format!("No temperature in output of: {}", self.cmd)))
+    }
+}
+
+/// Output in observe mode: reads go to the hardware, writes are refused
+pub struct ReadOnlyPwm {
+    sink: Box<dyn PwmSink>,
+}
+
+fn observe_only_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "Fan outputs are never written in observe mode")
+}
+
+impl PwmSink for ReadOnlyPwm {
+    fn set_power(&self, _power: u8) -> io::Result<()> {
+        Err(observe_only_error())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        self.sink.read_power()
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        self.sink.read_rpm()
+    }
+
+    fn is_manual(&self) -> bool {
+        self.sink.is_manual()
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        self.sink.read_enable()
+    }
+
+    fn set_manual(&self, _manual: bool) -> io::Result<()> {
+        Err(observe_only_error())
+    }
+
+    fn set_mode(&self, _mode: PwmMode) -> io::Result<()> {
+        Err(observe_only_error())
+    }
+
+    fn needs_retry(&self) -> bool {
+        false
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Raw 0-255 value written to the hwmon pwmN file
+pub struct HwmonPwm {
+    pwm_input: PathBuf,
+    enable: PwmEnableValues,
+}
+
+fn hwmon_enable_path(pwm_input: &Path) -> PathBuf {
+    PathBuf::from(format!("{}_enable", pwm_input.display()))
+}
+
+// hwmon pairs pwmN with the tachometer fanN_input in the same directory
+fn read_hwmon_rpm(pwm_input: &Path) -> Option<u32> {
+    let index = pwm_input.file_name()?.to_str()?.strip_prefix("pwm")?;
+    read_value::<u32>(&pwm_input.with_file_name(format!("fan{}_input", index))).ok()
+}
+
+fn hwmon_mode_path(pwm_input: &Path) -> PathBuf {
+    PathBuf::from(format!("{}_mode", pwm_input.display()))
+}
+
+/// Current drive mode of a hwmon pwmN output, from pwmN_mode (0 = DC, 1 = PWM)
+pub fn read_hwmon_pwm_mode(pwm_input: &Path) -> Option<PwmMode> {
+    match read_value::<u8>(&hwmon_mode_path(pwm_input)).ok()? {
+        0 => Some(PwmMode::Dc),
+        1 => Some(PwmMode::Pwm),
+        _ => None,
+    }
+}
+
+/// Raw 0-255 value of a hwmon pwmN output
+pub fn read_hwmon_pwm_value(pwm_input: &Path) -> Option<u8> {
+    read_value::<u8>(pwm_input).ok()
+}
+
+/// Raw pwmN_enable value of a hwmon output (its meaning depends on the driver)
+pub fn read_hwmon_pwm_enable(pwm_input: &Path) -> Option<u8> {
+    read_value::<u8>(&hwmon_enable_path(pwm_input)).ok()
+}
+
+/// Write a raw 0-255 value to a hwmon pwmN output
+pub fn write_hwmon_pwm_value(pwm_input: &Path, value: u8) -> io::Result<()> {
+    if is_observe_only() {
+        return Err(observe_only_error());
+    }
+    write(pwm_input, value.to_string())
+}
+
+/// Write a raw pwmN_enable value of a hwmon output
+pub fn write_hwmon_pwm_enable(pwm_input: &Path, enable: u8) -> io::Result<()> {
+    if is_observe_only() {
+        return Err(observe_only_error());
+    }
+    write(hwmon_enable_path(pwm_input), enable.to_string())
+}
+
+fn auto_point_path(pwm_input: &Path, point: usize, kind: &str) -> PathBuf {
+    PathBuf::from(format!("{}_auto_point{}_{}", pwm_input.display(), point, kind))
+}
+
+/// Number of pwmN_auto_pointM_temp/_pwm pairs of a hwmon output, 0 without a hardware curve
+pub fn hwmon_auto_points(pwm_input: &Path) -> usize {
+    (1..).take_while(|point| auto_point_path(pwm_input, *point, "temp").exists() && auto_point_path(pwm_input, *point, "pwm").exists())
+        .count()
+}
+
+/// Program the hardware curve of a hwmon output with rising (°C, raw 0-255) points, reading
+/// tempN_input `temp_index` when given. The chip's remaining points continue the last one.
+pub fn write_hwmon_auto_points(pwm_input: &Path, points: &[(i32, u8)], temp_index: Option<u32>) -> io::Result<()> {
+    if is_observe_only() {
+        return Err(observe_only_error());
+    }
+    let available = hwmon_auto_points(pwm_input);
+    let Some(&(last_temp, last_value)) = points.last() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The curve has no steps"));
+    };
+    if available == 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} has no pwm auto points", pwm_input.display())));
+    }
+    if points.len() > available {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The curve has {} steps but {} only {} auto points", points.len(), pwm_input.display(), available),
+        ));
+    }
+    if let Some(index) = temp_index {
+        let temp_sel = PathBuf::from(format!("{}_temp_sel", pwm_input.display()));
+        if temp_sel.exists() {
+            write(temp_sel, index.to_string())?;
+        }
+    }
+    for point in 1..=available {
+        // Drivers may refuse points at the same temperature, the padding ones keep rising by 1°C
+        let (temp, value) = points.get(point - 1).copied()
+            .unwrap_or_else(|| (last_temp + (point - points.len()) as i32, last_value));
+        write(auto_point_path(pwm_input, point, "temp"), (temp * 1000).to_string())?;
+        write(auto_point_path(pwm_input, point, "pwm"), value.to_string())?;
+    }
+    Ok(())
+}
+
+fn read_hwmon_power(pwm_input: &Path) -> Option<u8> {
+    read_value::<u32>(pwm_input).ok().map(|pwm| (pwm * 100 / 255) as u8)
+}
+
+impl PwmSink for HwmonPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        let pwm_value: u32 = power.min(100) as u32 * 255 / 100;
+        write(&self.pwm_input, pwm_value.to_string())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        read_hwmon_power(&self.pwm_input)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        read_hwmon_rpm(&self.pwm_input)
+    }
+
+    fn is_manual(&self) -> bool {
+        read_enable(&hwmon_enable_path(&self.pwm_input), self.enable.manual)
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        read_hwmon_pwm_enable(&self.pwm_input)
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        write(hwmon_enable_path(&self.pwm_input), if manual { self.enable.manual } else { self.enable.auto }.to_string())
+    }
+
+    fn set_mode(&self, mode: PwmMode) -> io::Result<()> {
+        write(hwmon_mode_path(&self.pwm_input), if mode == PwmMode::Dc { "0" } else { "1" })
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        vec![self.pwm_input.clone(), hwmon_enable_path(&self.pwm_input)]
+    }
+}
+
+/// Channel of a standalone i2c PWM controller (emc2305, max31790...), through its hwmon
+/// `pwmN` file. Chips whose driver has no `pwmN_enable` are always under manual control;
+/// with no automatic mode to go back to, their fans are handed back at full speed.
+pub struct I2cPwm {
+    pwm_input: PathBuf,
+    enable: PwmEnableValues,
+}
+
+impl I2cPwm {
+    fn has_enable(&self) -> bool {
+        hwmon_enable_path(&self.pwm_input).exists()
+    }
+}
+
+impl PwmSink for I2cPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        let pwm_value: u32 = power.min(100) as u32 * 255 / 100;
+        write(&self.pwm_input, pwm_value.to_string())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        read_hwmon_power(&self.pwm_input)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        read_hwmon_rpm(&self.pwm_input)
+    }
+
+    fn is_manual(&self) -> bool {
+        if self.has_enable() {
+            read_enable(&hwmon_enable_path(&self.pwm_input), self.enable.manual)
+        } else {
+            self.pwm_input.exists()
+        }
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        read_hwmon_pwm_enable(&self.pwm_input)
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        if self.has_enable() {
+            return write(hwmon_enable_path(&self.pwm_input), if manual { self.enable.manual } else { self.enable.auto }.to_string());
+        }
+        if manual {
+            // Surface a chip that went away, there is nothing else to switch
+            fs::metadata(&self.pwm_input).map(|_| ())
+        } else {
+            self.set_power(100)
+        }
+    }
+
+    fn needs_retry(&self) -> bool {
+        self.has_enable()
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.pwm_input.clone()];
+        if self.has_enable() {
+            files.push(hwmon_enable_path(&self.pwm_input));
+        }
+        files
+    }
+}
+
+const THINKPAD_FAN_PROC: &str = "/proc/acpi/ibm/fan";
+
+// Map a power percentage to a thinkpad_acpi level (0-7, or full-speed at 100%)
+fn thinkpad_level(power: u8) -> String {
+    match power {
+        0 => "0".to_string(),
+        100.. => "full-speed".to_string(),
+        // Round up so any non-zero power keeps the fan spinning
+        _ => (power as u32 * 7).div_ceil(100).to_string(),
+    }
+}
+
+/// ThinkPad EC levels written to /proc/acpi/ibm/fan (needs `fan_control=1`).
+/// On thinkpad_acpi `pwm1_enable=0` means full speed, so automatic mode is 2.
+pub struct ThinkpadPwm {
+    pwm_input: PathBuf,
+    enable: PwmEnableValues,
+}
+
+impl PwmSink for ThinkpadPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        write(THINKPAD_FAN_PROC, format!("level {}", thinkpad_level(power)))
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        read_hwmon_power(&self.pwm_input)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        // "speed:		2890"
+        let content = fs::read_to_string(THINKPAD_FAN_PROC).ok()?;
+        content.lines()
+            .find_map(|line| line.strip_prefix("speed:"))
+            .and_then(|speed| speed.trim().parse().ok())
+    }
+
+    fn is_manual(&self) -> bool {
+        read_enable(&hwmon_enable_path(&self.pwm_input), self.enable.manual)
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        read_hwmon_pwm_enable(&self.pwm_input)
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        // Hand the fan back to the EC; fall through to pwm1_enable if procfs is unavailable
+        if !manual && write(THINKPAD_FAN_PROC, "level auto").is_ok() {
+            return Ok(());
+        }
+        write(hwmon_enable_path(&self.pwm_input), if manual { self.enable.manual } else { self.enable.auto }.to_string())
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(THINKPAD_FAN_PROC), hwmon_enable_path(&self.pwm_input)]
+    }
+}
+
+const DELL_SMM_MAX_LEVEL: u32 = 2;
+
+// Map a power percentage to a dell-smm-hwmon level (0 = off, 1 = low, 2 = high)
+fn dell_smm_level(power: u8) -> u32 {
+    (power.min(100) as u32 * DELL_SMM_MAX_LEVEL).div_ceil(100)
+}
+
+/// dell-smm-hwmon discrete levels. The driver only accepts 1 (manual) and
+/// 2 (automatic) for pwm1_enable, and the BIOS takes control back on its own.
+pub struct DellSmmPwm {
+    pwm_input: PathBuf,
+    enable: PwmEnableValues,
+}
+
+impl PwmSink for DellSmmPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        let pwm_value = dell_smm_level(power) * 255 / DELL_SMM_MAX_LEVEL;
+        write(&self.pwm_input, pwm_value.to_string())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        read_hwmon_power(&self.pwm_input)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        read_hwmon_rpm(&self.pwm_input)
+    }
+
+    fn is_manual(&self) -> bool {
+        read_enable(&hwmon_enable_path(&self.pwm_input), self.enable.manual)
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        read_hwmon_pwm_enable(&self.pwm_input)
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        write(hwmon_enable_path(&self.pwm_input), if manual { self.enable.manual } else { self.enable.auto }.to_string())
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        vec![self.pwm_input.clone(), hwmon_enable_path(&self.pwm_input)]
+    }
+}
+
+// 25 kHz, the standard for 4-pin PC fans
+const DEFAULT_PWM_PERIOD_NS: u64 = 40_000;
+
+/// Channel of a /sys/class/pwm/pwmchipN device (e.g. Raspberry Pi)
+pub struct PwmchipPwm {
+    channel_dir: PathBuf,
+    period_ns: u64,
+}
+
+impl PwmchipPwm {
+    // Export the channel if needed and set its period
+    fn setup_channel(&self) -> io::Result<()> {
+        if !self.channel_dir.exists() {
+            let (Some(chip_dir), Some(channel)) = (
+                self.channel_dir.parent(),
+                self.channel_dir.file_name().and_then(|f| f.to_str()).and_then(|f| f.strip_prefix("pwm")),
+            ) else {
+                return Err(invalid_data(format!("Invalid pwmchip channel path: {:?}", self.channel_dir)));
+            };
+            write(chip_dir.join("export"), channel)?;
+            // Give udev a moment to create the channel attributes
+            thread::sleep(Duration::from_millis(100));
+        }
+        write(self.channel_dir.join("period"), self.period_ns.to_string())
+    }
+}
+
+impl PwmSink for PwmchipPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        let duty = self.period_ns * power.min(100) as u64 / 100;
+        write(self.channel_dir.join("duty_cycle"), duty.to_string())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        let duty = read_value::<u64>(&self.channel_dir.join("duty_cycle")).ok()?;
+        Some((duty * 100 / self.period_ns.max(1)).min(100) as u8)
+    }
+
+    fn is_manual(&self) -> bool {
+        read_enable(&self.channel_dir.join("enable"), 1)
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        read_value::<u8>(&self.channel_dir.join("enable")).ok()
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        if manual {
+            self.setup_channel()?;
+            write(self.channel_dir.join("enable"), "1")
+        } else {
+            // There is no firmware fallback behind a bare PWM channel, so leave the
+            // fan at full speed rather than letting the line float
+            self.set_power(100)
+        }
+    }
+
+    fn needs_retry(&self) -> bool {
+        // Nothing reclaims a pwmchip channel behind our back, a single write is enough
+        false
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        ["duty_cycle", "enable", "period"].iter().map(|f| self.channel_dir.join(f)).collect()
+    }
+}
+
+const ACPI_IBM_MAX_LEVEL: u32 = 7;
+
+/// ThinkPad fan of FreeBSD's acpi_ibm driver, driven through the sysctls of its node
+/// (e.g. `dev.acpi_ibm.0`): `fan` is 1 while the EC controls it and 0 in manual mode,
+/// `fan_level` is 0-7 and `fan_speed` the RPM.
+pub struct AcpiIbmPwm {
+    node: String,
+}
+
+impl AcpiIbmPwm {
+    fn sysctl(&self, leaf: &str) -> String {
+        format!("{}.{}", self.node, leaf)
+    }
+}
+
+impl PwmSink for AcpiIbmPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        // Round up so any non-zero power keeps the fan spinning
+        let level = (power.min(100) as u32 * ACPI_IBM_MAX_LEVEL).div_ceil(100);
+        os::write_sysctl(&self.sysctl("fan_level"), &level.to_string())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        let level = os::read_sysctl_value::<u32>(&self.sysctl("fan_level")).ok()?;
+        Some((level.min(ACPI_IBM_MAX_LEVEL) * 100 / ACPI_IBM_MAX_LEVEL) as u8)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        os::read_sysctl_value(&self.sysctl("fan_speed")).ok()
+    }
+
+    fn is_manual(&self) -> bool {
+        self.read_enable() == Some(0)
+    }
+
+    fn read_enable(&self) -> Option<u8> {
+        os::read_sysctl_value(&self.sysctl("fan")).ok()
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        os::write_sysctl(&self.sysctl("fan"), if manual { "0" } else { "1" })
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        // sysctls have no owner to hand over, setting them needs root
+        Vec::new()
+    }
+}
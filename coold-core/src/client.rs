@@ -0,0 +1,328 @@
+use reqwest::header::{HeaderMap, ETAG, IF_MATCH};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use crate::alert::WebhookResult;
+use crate::api_types::{
+    AddFanRequest, ApiResponse, AuditEntry, AuditQuery, BoostQuery, CurveTemplateInfo, DetectRequest, DeviceQuery,
+    FanFilter, FanStatus, Health, LogsQuery, NightModeRequest, OverrideFanRequest, PreviewPoint, PreviewQuery, TestAlertsRequest,
+    UpdateCurveRequest, UpdateFanRequest, REQUEST_ID_HEADER,
+};
+use crate::daemon::{Config, FanConfig, FanStep, HwmonDeviceDetail, HwmonDeviceInfo, HwmonPwmInfo, HwmonSensorReading, RescanReport, Stats};
+use crate::detect::DetectedOutput;
+use crate::diff::ConfigDiff;
+use crate::fancontrol::FancontrolExport;
+
+/// Why a call to the daemon failed
+pub enum ClientError {
+    /// The daemon couldn't be reached, or didn't answer with an API response
+    Transport(reqwest::Error),
+    /// The daemon answered with an error status
+    Http {
+        status: StatusCode,
+        /// ID the daemon logged the call with
+        request_id: Option<String>,
+        message: String,
+    },
+    /// The daemon answered but the call didn't succeed
+    Failed(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "{}", e),
+            ClientError::Http { status, request_id: Some(id), message } => write!(f, "HTTP {} (request {}): {}", status, id, message),
+            ClientError::Http { status, request_id: None, message } => write!(f, "HTTP {}: {}", status, message),
+            ClientError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Same as Display, so an error returned from main reads like the daemon's message
+impl fmt::Debug for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Transport(e)
+    }
+}
+
+// Path segment with everything but unreserved characters percent-encoded
+fn encode(segment: &str) -> String {
+    segment.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Client of the REST API of a daemon
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    /// Client of the API at `api_url` (e.g. http://127.0.0.1:8080/api/v1), sending `token` as bearer token
+    pub fn new(api_url: impl Into<String>, token: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    fn request(&self, method: Method, endpoint: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.api_url, endpoint));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    // Response to a call answered with a success status, along with its headers
+    async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<(ApiResponse<T>, HeaderMap), ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        if !status.is_success() {
+            // The daemon logs the same ID, to find the call in its logs
+            let request_id = headers.get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let text = response.text().await?;
+            // Errors from a proxy in front of the daemon aren't API responses, show them as is
+            let message = serde_json::from_str::<ApiResponse<Value>>(&text)
+                .map(|response| response.message)
+                .unwrap_or(text);
+            return Err(ClientError::Http { status, request_id, message });
+        }
+        Ok((response.json().await?, headers))
+    }
+
+    // Data of a successful call
+    async fn data<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ClientError> {
+        match Self::send(request).await? {
+            (ApiResponse { success: true, data: Some(data), .. }, _) => Ok(data),
+            (ApiResponse { message, .. }, _) => Err(ClientError::Failed(message)),
+        }
+    }
+
+    // Message of a successful call, for calls answering with no data of interest
+    async fn action(request: RequestBuilder) -> Result<String, ClientError> {
+        match Self::send::<Value>(request).await? {
+            (ApiResponse { success: true, message, .. }, _) => Ok(message),
+            (ApiResponse { message, .. }, _) => Err(ClientError::Failed(message)),
+        }
+    }
+
+    async fn post_action(&self, endpoint: &str) -> Result<String, ClientError> {
+        Self::action(self.request(Method::POST, endpoint)).await
+    }
+
+    /// State of the controlled fans matching `filter`
+    pub async fn status(&self, filter: &FanFilter) -> Result<Vec<FanStatus>, ClientError> {
+        Self::data(self.request(Method::GET, "/status").query(filter)).await
+    }
+
+    /// Daemon state, with the problems of the degraded fans
+    pub async fn health(&self) -> Result<Health, ClientError> {
+        Self::data(self.request(Method::GET, "/health")).await
+    }
+
+    /// Statistics of every fan since daemon start or the last reset
+    pub async fn stats(&self) -> Result<Stats, ClientError> {
+        Self::data(self.request(Method::GET, "/stats")).await
+    }
+
+    pub async fn reset_stats(&self) -> Result<String, ClientError> {
+        self.post_action("/stats/reset").await
+    }
+
+    /// Send a test event to the webhooks of `rule`, or of every rule. The response is
+    /// returned as is, it lists the webhooks reached even when some of them failed
+    pub async fn test_alerts(&self, rule: Option<&str>) -> Result<ApiResponse<Vec<WebhookResult>>, ClientError> {
+        let body = TestAlertsRequest { rule: rule.map(str::to_string) };
+        Ok(Self::send(self.request(Method::POST, "/alerts/test").json(&body)).await?.0)
+    }
+
+    /// Running configuration, with the ETag to pass to `update_config`
+    pub async fn config(&self) -> Result<(Config, String), ClientError> {
+        Self::versioned(self.request(Method::GET, "/config")).await
+    }
+
+    /// What replacing the configuration with `config` would change, with the ETag of the
+    /// configuration it was compared to
+    pub async fn diff_config(&self, config: &Config) -> Result<(ConfigDiff, String), ClientError> {
+        Self::versioned(self.request(Method::POST, "/config/diff").json(config)).await
+    }
+
+    // Data of a successful call along with its ETag, "*" if the daemon didn't send one
+    async fn versioned<T: DeserializeOwned>(request: RequestBuilder) -> Result<(T, String), ClientError> {
+        let (response, headers) = Self::send(request).await?;
+        let etag = headers.get(ETAG).and_then(|value| value.to_str().ok()).unwrap_or("*").to_string();
+        match response {
+            ApiResponse { success: true, data: Some(data), .. } => Ok((data, etag)),
+            ApiResponse { message, .. } => Err(ClientError::Failed(message)),
+        }
+    }
+
+    /// Replace the configuration, unless it changed since `etag` was returned ("*" to replace it anyway)
+    pub async fn update_config(&self, config: &Config, etag: &str) -> Result<String, ClientError> {
+        Self::action(self.request(Method::PUT, "/config").header(IF_MATCH, etag).json(config)).await
+    }
+
+    /// The running configuration as an /etc/fancontrol file
+    pub async fn fancontrol(&self) -> Result<FancontrolExport, ClientError> {
+        Self::data(self.request(Method::GET, "/config/fancontrol")).await
+    }
+
+    /// Write the configuration to disk now instead of after its quiet period
+    pub async fn save_config(&self) -> Result<String, ClientError> {
+        self.post_action("/config/save").await
+    }
+
+    /// Fans of `config.fan` matching `filter`, by name
+    pub async fn fans(&self, filter: &FanFilter) -> Result<HashMap<String, FanConfig>, ClientError> {
+        Self::data(self.request(Method::GET, "/fans").query(filter)).await
+    }
+
+    pub async fn fan(&self, name: &str) -> Result<FanConfig, ClientError> {
+        Self::data(self.request(Method::GET, &format!("/fans/{}", encode(name)))).await
+    }
+
+    pub async fn update_fan(&self, name: &str, update: &UpdateFanRequest) -> Result<String, ClientError> {
+        Self::action(self.request(Method::PUT, &format!("/fans/{}", encode(name))).json(update)).await
+    }
+
+    pub async fn add_fan(&self, fan: &AddFanRequest) -> Result<String, ClientError> {
+        Self::action(self.request(Method::POST, "/fans").json(fan)).await
+    }
+
+    pub async fn remove_fan(&self, name: &str) -> Result<String, ClientError> {
+        Self::action(self.request(Method::DELETE, &format!("/fans/{}", encode(name)))).await
+    }
+
+    /// Run a fan at `power` instead of its curve, until cleared
+    pub async fn override_fan(&self, name: &str, power: u8) -> Result<String, ClientError> {
+        let body = OverrideFanRequest { power };
+        Self::action(self.request(Method::PUT, &format!("/fans/{}/override", encode(name))).json(&body)).await
+    }
+
+    pub async fn clear_override(&self, name: &str) -> Result<String, ClientError> {
+        Self::action(self.request(Method::DELETE, &format!("/fans/{}/override", encode(name)))).await
+    }
+
+    /// Run a fan at 100% for `seconds`, or the daemon's default
+    pub async fn boost(&self, name: &str, seconds: Option<u64>) -> Result<String, ClientError> {
+        let query = BoostQuery { seconds };
+        Self::action(self.request(Method::POST, &format!("/fans/{}/boost", encode(name))).query(&query)).await
+    }
+
+    /// Power a fan's curve gives at the temperatures of `query`
+    pub async fn preview(&self, name: &str, query: &PreviewQuery) -> Result<Vec<PreviewPoint>, ClientError> {
+        Self::data(self.request(Method::GET, &format!("/fans/{}/preview", encode(name))).query(query)).await
+    }
+
+    /// Named curves, by name
+    pub async fn curves(&self) -> Result<HashMap<String, Vec<FanStep>>, ClientError> {
+        Self::data(self.request(Method::GET, "/curves")).await
+    }
+
+    pub async fn curve(&self, name: &str) -> Result<Vec<FanStep>, ClientError> {
+        Self::data(self.request(Method::GET, &format!("/curves/{}", encode(name)))).await
+    }
+
+    /// Create or replace a named curve
+    pub async fn set_curve(&self, name: &str, steps: Vec<FanStep>) -> Result<String, ClientError> {
+        let body = UpdateCurveRequest { steps };
+        Self::action(self.request(Method::PUT, &format!("/curves/{}", encode(name))).json(&body)).await
+    }
+
+    pub async fn delete_curve(&self, name: &str) -> Result<String, ClientError> {
+        Self::action(self.request(Method::DELETE, &format!("/curves/{}", encode(name)))).await
+    }
+
+    pub async fn curve_templates(&self) -> Result<Vec<CurveTemplateInfo>, ClientError> {
+        Self::data(self.request(Method::GET, "/curve_templates")).await
+    }
+
+    pub async fn lift_power_cap(&self) -> Result<String, ClientError> {
+        self.post_action("/power_cap/lift").await
+    }
+
+    pub async fn restore_power_cap(&self) -> Result<String, ClientError> {
+        self.post_action("/power_cap/restore").await
+    }
+
+    pub async fn set_night_mode(&self, night_mode: &NightModeRequest) -> Result<String, ClientError> {
+        Self::action(self.request(Method::PUT, "/night_mode").json(night_mode)).await
+    }
+
+    pub async fn pause(&self) -> Result<String, ClientError> {
+        self.post_action("/pause").await
+    }
+
+    pub async fn resume(&self) -> Result<String, ClientError> {
+        self.post_action("/resume").await
+    }
+
+    pub async fn stop(&self) -> Result<String, ClientError> {
+        self.post_action("/stop").await
+    }
+
+    pub async fn start(&self) -> Result<String, ClientError> {
+        self.post_action("/start").await
+    }
+
+    /// hwmon devices with their current readings, only those named `device` if given
+    pub async fn hwmon_devices(&self, device: Option<&str>) -> Result<Vec<HwmonDeviceInfo>, ClientError> {
+        let query = DeviceQuery { device: device.map(str::to_string) };
+        Self::data(self.request(Method::GET, "/hwmon_devices").query(&query)).await
+    }
+
+    /// Look the fans' devices up again, after a driver was loaded or a device plugged in
+    pub async fn rescan(&self) -> Result<RescanReport, ClientError> {
+        Self::data(self.request(Method::POST, "/hwmon_devices/rescan")).await
+    }
+
+    /// Everything about the device named `name` or in directory `name` (e.g. hwmon2)
+    pub async fn hwmon_device(&self, name: &str) -> Result<HwmonDeviceDetail, ClientError> {
+        Self::data(self.request(Method::GET, &format!("/hwmon_devices/{}", encode(name)))).await
+    }
+
+    pub async fn sensors(&self) -> Result<Vec<HwmonSensorReading>, ClientError> {
+        Self::data(self.request(Method::GET, "/sensors")).await
+    }
+
+    pub async fn pwms(&self) -> Result<Vec<HwmonPwmInfo>, ClientError> {
+        Self::data(self.request(Method::GET, "/pwms")).await
+    }
+
+    /// Vary each PWM output to find the fan it drives, waiting `settle_secs` at each step
+    pub async fn detect(&self, settle_secs: Option<u64>) -> Result<Vec<DetectedOutput>, ClientError> {
+        Self::data(self.request(Method::POST, "/detect").json(&DetectRequest { settle_secs })).await
+    }
+
+    /// Most recent API calls that changed something, `limit` of them or the daemon's default
+    pub async fn audit(&self, limit: Option<usize>) -> Result<Vec<AuditEntry>, ClientError> {
+        Self::data(self.request(Method::GET, "/audit").query(&AuditQuery { limit })).await
+    }
+
+    /// Most recent lines the daemon logged, `limit` of them or the daemon's default
+    pub async fn logs(&self, limit: Option<usize>) -> Result<Vec<String>, ClientError> {
+        Self::data(self.request(Method::GET, "/logs").query(&LogsQuery { limit })).await
+    }
+}
@@ -0,0 +1,3626 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject, StringValidation};
+use schemars::visit::{visit_schema_object, Visitor};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use glob::glob;
+use crate::alert::{self, AlertRule, AlertTracker, Observation};
+use crate::backend::{self, sysfs_class, CpuTimes};
+use crate::diff::{self, ConfigDiff};
+use crate::fancontrol::{self, FancontrolExport};
+use crate::simulation;
+use crate::expr::PowerExpr;
+use crate::hooks::{self, FanError, Hooks};
+use crate::script::{ScriptPolicy, ScriptState};
+use crate::trip::{TripPoint, TripTracker};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::panic;
+use crate::log;
+use crate::logging;
+use serde_json::json;
+
+pub const CONFIG_PATH: &str = "config.json";
+
+// How often each fan's curve is evaluated
+const CONTROL_INTERVAL: Duration = Duration::from_secs(5);
+// How often added fans and suspend/resume are checked for
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+// Configuration changes are saved once no other change came in for this long
+const PERSIST_QUIET_PERIOD: Duration = Duration::from_secs(2);
+const PERSIST_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// A critical fan has to cool down this far below critical_temp before the emergency ends
+const CRITICAL_HYSTERESIS: i32 = 5;
+const DEFAULT_RPM_TOLERANCE_PCT: u8 = 30;
+// Consecutive cycles off the expected speed before a fan is reported as failing
+const RPM_FAILURE_CYCLES: u32 = 3;
+// rpm_steps: speeds this close (in %) to the target are left alone, others are corrected by
+// RPM_TARGET_GAIN power points per 100% off, by at most RPM_TARGET_MAX_STEP points a cycle
+const RPM_TARGET_TOLERANCE_PCT: f64 = 3.0;
+const RPM_TARGET_GAIN: f64 = 50.0;
+const RPM_TARGET_MAX_STEP: f64 = 10.0;
+
+const FULL_SPEED_CURVE: [FanStep; 1] = [FanStep { temp: 0, power: 100 }];
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Config {
+    pub fan: HashMap<String, FanConfig>,
+    /// Groups of PWM outputs driven by one sensor and curve
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub zones: HashMap<String, ZoneConfig>,
+    /// Named curves, shared by the fans and zones referring to them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub curves: HashMap<String, Vec<FanStep>>,
+    /// Conditions reported to webhooks and desktop notifications
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<AlertRule>,
+    /// What to do when a fan reaches its critical_temp, on top of forcing every fan to 100%
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_action: Option<CriticalAction>,
+    /// Power cap applied to every fan between two times of day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub night_mode: Option<NightMode>,
+    /// Named sensors, e.g. weighted combinations, referred to with a "virtual" sensor source
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sensors: HashMap<String, SensorSource>,
+    /// Seconds after startup during which sensors are read but the fans are left to the
+    /// firmware, while hwmon drivers settle after boot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_delay_secs: Option<u64>,
+    /// When a fan's control loop is considered stuck, and what to do about it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<WatchdogConfig>,
+    /// Temperatures at which commands are run, e.g. to throttle a workload
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trip_points: Vec<TripPoint>,
+    /// Settings every fan and zone inherits unless it sets its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<FanDefaults>,
+    /// Commands run on daemon events: start, stop, profile changes and fan errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
+    /// Fans stopped while every temperature stays low, for a silent idle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub semi_passive: Option<SemiPassive>,
+    /// Groups share their cooling out quietest fans first, by noise_weight, instead of
+    /// running every member at the highest demand
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub noise_budget: bool,
+}
+
+/// Stops `fans` once the temperature of every fan has stayed below `stop_below` for
+/// `idle_secs`, until one of them reaches `wake_above`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct SemiPassive {
+    pub fans: Vec<String>,
+    pub stop_below: i32,
+    pub wake_above: i32,
+    #[serde(default = "default_idle_secs")]
+    pub idle_secs: u64,
+}
+
+fn default_idle_secs() -> u64 {
+    300
+}
+
+/// Tuning shared by every fan, each fan or zone setting takes precedence
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct FanDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub min_power: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub max_power: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 50))]
+    pub power_step: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_dwell_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_band: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_temp: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_temp_range: Option<TempRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub rpm_tolerance_pct: Option<u8>,
+}
+
+impl FanDefaults {
+    // Fill in the settings a fan leaves unset
+    fn apply(&self, fan: &mut FanConfig) {
+        fan.interval_secs = fan.interval_secs.or(self.interval_secs);
+        fan.min_power = fan.min_power.or(self.min_power);
+        fan.max_power = fan.max_power.or(self.max_power);
+        fan.power_step = fan.power_step.or(self.power_step);
+        fan.min_dwell_secs = fan.min_dwell_secs.or(self.min_dwell_secs);
+        fan.dead_band = fan.dead_band.or(self.dead_band);
+        fan.critical_temp = fan.critical_temp.or(self.critical_temp);
+        fan.valid_temp_range = fan.valid_temp_range.or(self.valid_temp_range);
+        fan.rpm_tolerance_pct = fan.rpm_tolerance_pct.or(self.rpm_tolerance_pct);
+    }
+}
+
+/// A fan whose control loop hasn't completed a cycle for `stall_cycles` intervals
+/// (hung sysfs read, deadlock) is reported as stalled
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct WatchdogConfig {
+    #[serde(default = "default_stall_cycles")]
+    pub stall_cycles: u32,
+    /// Hand stalled fans back to the firmware until their loop recovers
+    #[serde(default)]
+    pub release_fans: bool,
+}
+
+fn default_stall_cycles() -> u32 {
+    3
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self { stall_cycles: default_stall_cycles(), release_fans: false }
+    }
+}
+
+/// Several PWM outputs driven by one sensor and curve, e.g. identical case fans.
+/// Each output is controlled as a fan named `<zone>.<n>` (1-based).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ZoneConfig {
+    #[serde(default)]
+    pub sensor_name: String,
+    #[serde(default)]
+    pub sensor_input: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<SensorSource>,
+    /// tempN_label sensor_input was given as, see FanConfig::sensor_label
+    #[serde(skip)]
+    pub sensor_label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_device: Option<DeviceMatch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_offset: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_scale: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_temp_range: Option<TempRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<FanStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cooldown_steps: Vec<FanStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_temp: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ambient: Option<SensorConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub load_steps: Vec<LoadStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_window_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rise_boost: Option<RiseBoost>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predict: Option<Prediction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_dwell_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_band: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerExpr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<ScriptPolicy>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hardware_curve: bool,
+    pub outputs: Vec<ZoneOutput>,
+}
+
+/// A PWM output of a zone
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ZoneOutput {
+    pub pwm_name: String,
+    pub pwm_input: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_device: Option<DeviceMatch>,
+    #[serde(default)]
+    pub backend: FanBackend,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_period_ns: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_mode: Option<PwmMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_enable: Option<PwmEnableValues>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rpm_curve: Vec<RpmPoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub rpm_tolerance_pct: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub min_power: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub max_power: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spin_up: Option<SpinUp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 50))]
+    pub power_step: Option<u8>,
+}
+
+impl ZoneConfig {
+    // Fan controlling one output of the zone
+    fn output_fan(&self, output: &ZoneOutput) -> FanConfig {
+        FanConfig {
+            sensor_name: self.sensor_name.clone(),
+            sensor_input: self.sensor_input.clone(),
+            sensor: self.sensor.clone(),
+            sensor_label: self.sensor_label.clone(),
+            sensor_device: self.sensor_device.clone(),
+            sensor_offset: self.sensor_offset,
+            sensor_scale: self.sensor_scale,
+            pwm_name: output.pwm_name.clone(),
+            pwm_input: output.pwm_input.clone(),
+            pwm_device: output.pwm_device.clone(),
+            steps: self.steps.clone(),
+            curve: self.curve.clone(),
+            cooldown_steps: self.cooldown_steps.clone(),
+            backend: output.backend,
+            pwm_period_ns: output.pwm_period_ns,
+            valid_temp_range: self.valid_temp_range,
+            interval_secs: self.interval_secs,
+            critical_temp: self.critical_temp,
+            rpm_curve: output.rpm_curve.clone(),
+            rpm_tolerance_pct: output.rpm_tolerance_pct,
+            rpm_steps: Vec::new(),
+            pwm_mode: output.pwm_mode,
+            pwm_enable: output.pwm_enable,
+            group: self.group.clone(),
+            follows: None,
+            follow_offset: None,
+            follow_ratio: None,
+            min_power: output.min_power,
+            max_power: output.max_power,
+            spin_up: output.spin_up,
+            noise_weight: output.noise_weight,
+            power_step: output.power_step,
+            ambient: self.ambient.clone(),
+            load_steps: self.load_steps.clone(),
+            load_window_secs: self.load_window_secs,
+            rise_boost: self.rise_boost,
+            predict: self.predict,
+            min_dwell_secs: self.min_dwell_secs,
+            dead_band: self.dead_band,
+            power: self.power.clone(),
+            script: self.script.clone(),
+            hardware_curve: self.hardware_curve,
+        }
+    }
+
+    // Take back the device paths of a fan built by output_fan
+    fn update_paths(&mut self, index: usize, fan: &FanConfig) {
+        self.sensor_input = fan.sensor_input.clone();
+        self.sensor_label = fan.sensor_label.clone();
+        self.ambient = fan.ambient.clone();
+        self.outputs[index].pwm_input = fan.pwm_input.clone();
+    }
+}
+
+impl Config {
+    /// Every fan under control: the configured fans plus one per zone output, with
+    /// named curves and defaults filled in
+    pub fn controlled_fans(&self) -> HashMap<String, FanConfig> {
+        let mut fans = self.fan.clone();
+        for (zone_name, zone) in &self.zones {
+            for (index, output) in zone.outputs.iter().enumerate() {
+                fans.insert(format!("{}.{}", zone_name, index + 1), zone.output_fan(output));
+            }
+        }
+        for fan in fans.values_mut() {
+            if let Some(defaults) = &self.defaults {
+                defaults.apply(fan);
+            }
+            if let Some(curve) = &fan.curve {
+                // An unknown curve is rejected by the API, but don't let a hand-edited
+                // config.json stop the fan
+                fan.steps = self.curves.get(curve).cloned().unwrap_or_else(|| FULL_SPEED_CURVE.to_vec());
+            }
+            let ambient_source = fan.ambient.as_mut().and_then(|ambient| ambient.sensor.as_mut());
+            for source in fan.sensor.iter_mut().chain(ambient_source) {
+                if let SensorSource::Virtual { name } = source {
+                    // Unknown sensors are left as is and fail to read
+                    if let Some(sensor) = self.sensors.get(name) {
+                        *source = sensor.clone();
+                    }
+                }
+            }
+        }
+        fans
+    }
+
+    /// Curves referred to by fans or zones but not defined
+    pub fn missing_curves(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self.fan.values().filter_map(|fan| fan.curve.clone())
+            .chain(self.zones.values().filter_map(|zone| zone.curve.clone()))
+            .filter(|curve| !self.curves.contains_key(curve))
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Virtual sensors referred to by fans, zones or ambient sensors but not defined
+    pub fn missing_sensors(&self) -> Vec<String> {
+        let fans = self.fan.values().map(|fan| (&fan.sensor, &fan.ambient));
+        let zones = self.zones.values().map(|zone| (&zone.sensor, &zone.ambient));
+        let mut missing: Vec<String> = fans.chain(zones)
+            .flat_map(|(sensor, ambient)| [sensor.as_ref(), ambient.as_ref().and_then(|ambient| ambient.sensor.as_ref())])
+            .flatten()
+            .filter_map(|source| match source {
+                SensorSource::Virtual { name } if !self.sensors.contains_key(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Whether any fan or zone uses the named curve
+    pub fn uses_curve(&self, name: &str) -> bool {
+        self.fan.values().any(|fan| fan.curve.as_deref() == Some(name))
+            || self.zones.values().any(|zone| zone.curve.as_deref() == Some(name))
+    }
+
+    /// Fans following a fan that isn't controlled, or themselves
+    pub fn missing_leaders(&self) -> Vec<String> {
+        let fans = self.controlled_fans();
+        let mut missing: Vec<String> = fans.iter()
+            .filter_map(|(name, fan)| fan.follows.as_ref().filter(|leader| *leader == name || !fans.contains_key(*leader)))
+            .cloned()
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Whether any fan follows the named fan
+    pub fn is_followed(&self, name: &str) -> bool {
+        self.fan.values().any(|fan| fan.follows.as_deref() == Some(name))
+    }
+
+    // Zone and output index behind a `<zone>.<n>` fan name
+    fn zone_output(&self, name: &str) -> Option<(String, usize)> {
+        let (zone_name, number) = name.rsplit_once('.')?;
+        let index = number.parse::<usize>().ok()?.checked_sub(1)?;
+        let zone = self.zones.get(zone_name)?;
+        (index < zone.outputs.len()).then(|| (zone_name.to_string(), index))
+    }
+
+    // Replace a fan with `to` if it is still `from`; returns whether it was replaced
+    fn move_fan(&mut self, name: &str, from: &FanConfig, to: &FanConfig) -> bool {
+        if let Some(current) = self.fan.get_mut(name) {
+            if current != from {
+                return false;
+            }
+            *current = to.clone();
+            return true;
+        }
+        let Some((zone_name, index)) = self.zone_output(name) else {
+            return false;
+        };
+        let zone = self.zones.get_mut(&zone_name).unwrap();
+        if zone.output_fan(&zone.outputs[index]) != *from {
+            return false;
+        }
+        zone.update_paths(index, to);
+        true
+    }
+}
+
+// Apply `update` to every fan, zone outputs included, keeping only the device paths of zone outputs
+fn update_fan_paths(config: &mut Config, mut update: impl FnMut(&mut FanConfig)) {
+    config.fan.values_mut().for_each(&mut update);
+    for zone in config.zones.values_mut() {
+        for index in 0..zone.outputs.len() {
+            let mut fan = zone.output_fan(&zone.outputs[index]);
+            update(&mut fan);
+            zone.update_paths(index, &fan);
+        }
+    }
+}
+
+/// Last line of defense when a fan's temperature reaches its critical_temp
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CriticalAction {
+    /// Only force every fan to 100%
+    #[default]
+    FullSpeed,
+    /// Also run a shell command, with COOLD_FAN and COOLD_TEMP set
+    Command { cmd: String },
+    /// Also power the system off through logind
+    Shutdown,
+}
+
+/// Global power cap between `start` and `end` (local time), e.g. 23:00 to 07:00
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct NightMode {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+    #[schemars(range(max = 100))]
+    pub max_power: u8,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl NightMode {
+    /// Cap to apply right now, None outside the window or when disabled
+    pub fn current_cap(&self) -> Option<u8> {
+        if !self.enabled {
+            return None;
+        }
+        let now = TimeOfDay::now();
+        let inside = if self.start <= self.end {
+            now >= self.start && now < self.end
+        } else {
+            // Window spanning midnight
+            now >= self.start || now < self.end
+        };
+        inside.then_some(self.max_power)
+    }
+}
+
+/// Time of day as "HH:MM"
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    minutes: u16,
+}
+
+impl TimeOfDay {
+    fn now() -> Self {
+        // SAFETY: tm is plain data, time accepts a null pointer and localtime_r fills tm in
+        let tm = unsafe {
+            let mut tm: libc::tm = std::mem::zeroed();
+            let now = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&now, &mut tm);
+            tm
+        };
+        Self { minutes: (tm.tm_hour * 60 + tm.tm_min) as u16 }
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid time of day '{}', expected HH:MM", value);
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours: u16 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(Self { minutes: hours * 60 + minutes })
+    }
+}
+
+impl JsonSchema for TimeOfDay {
+    fn schema_name() -> String {
+        "TimeOfDay".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^([01][0-9]|2[0-3]):[0-5][0-9]$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        format!("{:02}:{:02}", time.minutes / 60, time.minutes % 60)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct FanConfig {
+    #[serde(default)]
+    pub sensor_name: String,
+    #[serde(default)]
+    pub sensor_input: String,
+    /// Non-hwmon temperature source; sensor_name/sensor_input are used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<SensorSource>,
+    /// tempN_label sensor_input was given as, kept once resolved to the tempN_input file
+    /// so the label rather than the index is saved
+    #[serde(skip)]
+    pub sensor_label: Option<String>,
+    /// Which of several devices named sensor_name to read, by bus path or serial
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_device: Option<DeviceMatch>,
+    /// Correction of the sensor readings: reading * sensor_scale + sensor_offset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_offset: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_scale: Option<f64>,
+    pub pwm_name: String,
+    pub pwm_input: String,
+    /// Which of several devices named pwm_name to drive, by bus path or serial
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_device: Option<DeviceMatch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<FanStep>,
+    /// Named curve from `curves`, used instead of steps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
+    /// Steps followed instead while the temperature is falling, e.g. for a slow spin-down
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cooldown_steps: Vec<FanStep>,
+    #[serde(default)]
+    pub backend: FanBackend,
+    /// PWM period in nanoseconds, only used by the pwmchip backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_period_ns: Option<u64>,
+    /// Readings outside this range are discarded as sensor glitches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_temp_range: Option<TempRange>,
+    /// Seconds between two control cycles of this fan, defaults to 5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    /// Temperature at which every fan is forced to 100% and the critical_action runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_temp: Option<i32>,
+    /// Measured speed of the fan at given powers, used to detect failing fans
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rpm_curve: Vec<RpmPoint>,
+    /// How far (in %) the speed may deviate from rpm_curve, defaults to 30
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub rpm_tolerance_pct: Option<u8>,
+    /// Speeds to hold by temperature, used instead of steps: the power is corrected every
+    /// cycle from the tachometer until the fan spins at them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rpm_steps: Vec<RpmStep>,
+    /// Drive mode of the header, set when taking control; left as is when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_mode: Option<PwmMode>,
+    /// pwmN_enable values for manual and automatic control, detected from the driver when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_enable: Option<PwmEnableValues>,
+    /// Fans of a group all run at the highest power any of them asks for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// How loud the fan is compared to the rest of its group, 1 by default; with
+    /// noise_budget, quieter fans are ramped up first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_weight: Option<f64>,
+    /// Fan whose power this one tracks, instead of reading a sensor and following a curve
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follows: Option<String>,
+    /// Power of the followed fan is turned into: power * follow_ratio + follow_offset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_offset: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_ratio: Option<f64>,
+    /// Lowest power the fan reliably spins at; nothing drives it below this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub min_power: Option<u8>,
+    /// Highest power the fan is driven at, unless the cap is lifted or a fan is critical
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(max = 100))]
+    pub max_power: Option<u8>,
+    /// Power held for a moment when starting the fan from standstill, for fans that don't
+    /// start at their lowest running power
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spin_up: Option<SpinUp>,
+    /// Power is rounded to multiples of this many %, or 100, so it moves in audible steps
+    /// rather than continuously
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 50))]
+    pub power_step: Option<u8>,
+    /// Ambient sensor; when set, steps are keyed on the sensor's temperature minus this one's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ambient: Option<SensorConfig>,
+    /// Power by CPU utilization, the fan runs at the higher of this and its temperature curve
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub load_steps: Vec<LoadStep>,
+    /// Seconds the CPU utilization is averaged over, defaults to 30
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_window_secs: Option<u64>,
+    /// Extra power while the temperature climbs quickly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rise_boost: Option<RiseBoost>,
+    /// Follow the curve at the temperature the recent trend leads to, ahead of the reading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predict: Option<Prediction>,
+    /// Seconds the power asked by the curve is held once it changed, against toggling on noisy sensors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_dwell_secs: Option<u64>,
+    /// °C the temperature may move away from the one the curve was last evaluated at
+    /// without changing the power
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_band: Option<u32>,
+    /// Power as an expression of the temperature, e.g. "clamp((temp - 30) * 2, 20, 100)",
+    /// used instead of steps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerExpr>,
+    /// Script computing the power from every sensor reading, in place of the steps, which
+    /// are still followed when it fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<ScriptPolicy>,
+    /// Program the steps into the chip's pwmN_auto_point* curve and let it drive the fan,
+    /// so the curve keeps working while the daemon isn't running
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hardware_curve: bool,
+}
+
+/// Power added to the curve when the temperature rose by at least `rate` °C since
+/// the previous cycle, to get ahead of bursts (e.g. a compile starting)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct RiseBoost {
+    pub rate: i32,
+    #[schemars(range(max = 100))]
+    pub power: u8,
+}
+
+/// Extrapolation of the temperature `cycles` cycles ahead, from the trend of the last
+/// `samples` readings. Only a rising trend is followed, by at most `max_rise` °C.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct Prediction {
+    #[serde(default = "default_prediction_samples")]
+    #[schemars(range(min = 2))]
+    pub samples: usize,
+    #[serde(default = "default_prediction_cycles")]
+    pub cycles: u32,
+    #[serde(default = "default_prediction_max_rise")]
+    #[schemars(range(min = 0))]
+    pub max_rise: i32,
+}
+
+fn default_prediction_samples() -> usize {
+    4
+}
+
+fn default_prediction_cycles() -> u32 {
+    1
+}
+
+fn default_prediction_max_rise() -> i32 {
+    10
+}
+
+/// Power applied for `secs` seconds when a stopped fan is started, before settling to
+/// the power asked for
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct SpinUp {
+    #[schemars(range(max = 100))]
+    pub power: u8,
+    #[serde(default = "default_spin_up_secs")]
+    #[schemars(range(max = 10))]
+    pub secs: u64,
+}
+
+// The control loop is blocked during the kick, longer kicks are cut to this
+const MAX_SPIN_UP_SECS: u64 = 10;
+
+fn default_spin_up_secs() -> u64 {
+    2
+}
+
+impl FanConfig {
+    /// hwmon fan driven by a hwmon sensor along `steps`, everything else left at its default
+    pub fn hwmon(sensor_name: &str, sensor_input: &str, pwm_name: &str, pwm_input: &str, steps: Vec<FanStep>) -> Self {
+        FanConfig {
+            sensor_name: sensor_name.to_string(),
+            sensor_input: sensor_input.to_string(),
+            sensor: None,
+            sensor_device: None,
+            sensor_label: None,
+            sensor_offset: None,
+            sensor_scale: None,
+            pwm_name: pwm_name.to_string(),
+            pwm_input: pwm_input.to_string(),
+            pwm_device: None,
+            steps,
+            curve: None,
+            cooldown_steps: Vec::new(),
+            backend: FanBackend::Hwmon,
+            pwm_period_ns: None,
+            valid_temp_range: None,
+            interval_secs: None,
+            critical_temp: None,
+            rpm_curve: Vec::new(),
+            rpm_tolerance_pct: None,
+            rpm_steps: Vec::new(),
+            pwm_mode: None,
+            pwm_enable: None,
+            group: None,
+            noise_weight: None,
+            follows: None,
+            follow_offset: None,
+            follow_ratio: None,
+            min_power: None,
+            max_power: None,
+            spin_up: None,
+            power_step: None,
+            ambient: None,
+            load_steps: Vec::new(),
+            load_window_secs: None,
+            rise_boost: None,
+            predict: None,
+            min_dwell_secs: None,
+            dead_band: None,
+            power: None,
+            script: None,
+            hardware_curve: false,
+        }
+    }
+
+    // Whether the temperature comes from the hwmon sensor_name/sensor_input
+    pub fn reads_hwmon_sensor(&self) -> bool {
+        self.sensor.is_none() && self.follows.is_none()
+    }
+
+    // Whether pwm_input is the path of a device rather than the bare file name of the
+    // configuration, which would be written relative to the working directory
+    fn pwm_resolved(&self) -> bool {
+        self.backend.is_sysctl() || simulation::is_active() || Path::new(&self.pwm_input).is_absolute()
+    }
+
+    // Power actually written to the fan, within its limits and `cap`
+    fn limit_power(&self, power: u8, cap: Option<u8>) -> u8 {
+        let power = self.quantize_power(power);
+        let power = cap.map_or(power, |cap| power.min(cap));
+        self.min_power.map_or(power, |min| power.max(min.min(100)))
+    }
+
+    // Nearest multiple of power_step, or 100 when closer. Speed targets correct the power
+    // by a few % a cycle, which rounding would undo, so they aren't quantized.
+    fn quantize_power(&self, power: u8) -> u8 {
+        let Some(step) = self.power_step.filter(|step| *step > 1 && self.rpm_steps.is_empty()) else {
+            return power;
+        };
+        let power = power.min(100);
+        let rounded = (power + step / 2) / step * step;
+        if 100 - power < power.abs_diff(rounded) {
+            100
+        } else {
+            rounded.min(100)
+        }
+    }
+
+    /// Power the fan runs at for `temp` when only its curve, critical_temp, min_power and
+    /// max_power are involved: no CPU load, rise boost, group, override or night mode
+    pub fn curve_power(&self, temp: i32, cooling: bool) -> u8 {
+        if self.critical_temp.is_some_and(|limit| temp >= limit) {
+            return 100;
+        }
+        self.limit_power(self.curve_demand(temp, cooling), self.max_power)
+    }
+
+    // Power the curve asks for at `temp`: the cool-down steps while cooling if any, else
+    // the power expression or the steps
+    fn curve_demand(&self, temp: i32, cooling: bool) -> u8 {
+        match &self.power {
+            Some(power) if !cooling || self.cooldown_steps.is_empty() => power.eval(temp),
+            _ => get_fan_power(self.active_steps(cooling), temp),
+        }
+    }
+
+    // Whether the fan has a curve of its own to follow
+    fn has_curve(&self) -> bool {
+        self.power.is_some() || !self.steps.is_empty() || !self.rpm_steps.is_empty()
+    }
+
+    // Curve followed while the temperature is rising (or steady), or falling
+    fn active_steps(&self, cooling: bool) -> &[FanStep] {
+        if cooling && !self.cooldown_steps.is_empty() {
+            &self.cooldown_steps
+        } else {
+            &self.steps
+        }
+    }
+
+    // Power to run at when the followed fan runs at `power`
+    fn follow_power(&self, power: u8) -> u8 {
+        let power = power as f64 * self.follow_ratio.unwrap_or(1.0) + self.follow_offset.unwrap_or(0) as f64;
+        power.round().clamp(0.0, 100.0) as u8
+    }
+}
+
+/// Values written to pwmN_enable to take manual control and to hand the fan back
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct PwmEnableValues {
+    #[schemars(range(max = 255))]
+    pub manual: u8,
+    #[schemars(range(max = 255))]
+    pub auto: u8,
+}
+
+/// Drive mode of a fan header (hwmon pwmN_mode)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PwmMode {
+    /// Voltage control, for 3-pin fans
+    Dc,
+    /// PWM signal, for 4-pin fans
+    Pwm,
+}
+
+impl PwmMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PwmMode::Dc => "dc",
+            PwmMode::Pwm => "pwm",
+        }
+    }
+}
+
+/// Fan speed measured at a given power
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct RpmPoint {
+    #[schemars(range(max = 100))]
+    pub power: u8,
+    pub rpm: u32,
+}
+
+/// Fan speed to hold at a temperature
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct RpmStep {
+    pub temp: i32,
+    pub rpm: u32,
+}
+
+/// Inclusive temperature range in °C
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct TempRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Default for TempRange {
+    // Wide enough for any real component, narrow enough to catch 255°C and -273°C glitches
+    fn default() -> Self {
+        Self { min: -40, max: 150 }
+    }
+}
+
+/// How the fan output is driven
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FanBackend {
+    /// Raw 0-255 value written to the hwmon pwm file
+    #[default]
+    Hwmon,
+    /// ThinkPad EC levels written to /proc/acpi/ibm/fan
+    ThinkpadAcpi,
+    /// dell-smm-hwmon discrete levels (off/low/high)
+    DellSmm,
+    /// Generic PWM channel under /sys/class/pwm/pwmchipN (e.g. Raspberry Pi)
+    Pwmchip,
+    /// Channel of a standalone i2c PWM controller such as the emc2305 or max31790
+    I2c,
+    /// FreeBSD acpi_ibm ThinkPad fan, through the sysctls of its node (e.g. dev.acpi_ibm.0)
+    AcpiIbm,
+}
+
+impl FanBackend {
+    /// Whether the output is driven through sysctls, named in full by `pwm_input`, rather than sysfs files
+    pub fn is_sysctl(self) -> bool {
+        self == FanBackend::AcpiIbm
+    }
+}
+
+/// A temperature sensor other than a fan's own, e.g. the ambient one of delta-T curves
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct SensorConfig {
+    #[serde(default)]
+    pub sensor_name: String,
+    #[serde(default)]
+    pub sensor_input: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<SensorSource>,
+    /// tempN_label sensor_input was given as, see FanConfig::sensor_label
+    #[serde(skip)]
+    pub sensor_label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_device: Option<DeviceMatch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_offset: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_scale: Option<f64>,
+}
+
+impl SensorConfig {
+    /// `fan` reading this sensor instead of its own, as temperature sources are built from fans
+    pub fn as_fan(&self, fan: &FanConfig) -> FanConfig {
+        FanConfig {
+            sensor_name: self.sensor_name.clone(),
+            sensor_input: self.sensor_input.clone(),
+            sensor: self.sensor.clone(),
+            sensor_label: self.sensor_label.clone(),
+            sensor_device: self.sensor_device.clone(),
+            sensor_offset: self.sensor_offset,
+            sensor_scale: self.sensor_scale,
+            follows: None,
+            ..fan.clone()
+        }
+    }
+
+    fn resolve_path(&mut self) -> bool {
+        if let Some(source) = &mut self.sensor {
+            return source.resolve_paths();
+        }
+        match find_sysfs_path(&self.sensor_name, &hwmon_name_pattern(), self.sensor_device.as_ref()) {
+            Some(path) => {
+                let (file, label) = resolve_sensor_input(&path, &self.sensor_input, self.sensor_label.take());
+                self.sensor_input = path.join(file).to_str().unwrap().to_string();
+                self.sensor_label = label;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_resolved(&self) -> bool {
+        match &self.sensor {
+            Some(source) => source.is_resolved(),
+            None => Path::new(&self.sensor_input).is_absolute(),
+        }
+    }
+
+    fn strip_path(&mut self) {
+        match &mut self.sensor {
+            Some(source) => source.strip_paths(),
+            None => self.sensor_input = self.sensor_label.take().unwrap_or_else(|| sysfs_file_name(&self.sensor_input)),
+        }
+    }
+}
+
+/// Where a fan reads its temperature from, when not a hwmon tempN_input
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensorSource {
+    /// Kernel thermal zone, by directory name or type
+    ThermalZone { zone: String },
+    /// FreeBSD temperature sysctl, e.g. hw.acpi.thermal.tz0.temperature
+    Sysctl { name: String },
+    /// Hottest of the drivetemp and NVMe drives currently present
+    HottestDrive,
+    /// HTTP endpoint returning a temperature in °C
+    Remote {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        json_pointer: Option<String>,
+    },
+    /// Shell command printing a temperature in °C on stdout
+    Command {
+        cmd: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+    },
+    /// Weighted average of several sensors, e.g. 0.7 * CPU + 0.3 * NVMe
+    Weighted { inputs: Vec<WeightedSensor> },
+    /// Hottest of several sensors
+    Max { inputs: Vec<SensorConfig> },
+    /// Sensor defined in the top-level `sensors` map
+    Virtual { name: String },
+}
+
+/// Input of a weighted sensor
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct WeightedSensor {
+    pub weight: f64,
+    #[serde(flatten)]
+    pub sensor: SensorConfig,
+}
+
+impl SensorSource {
+    /// Sensors a combined source is computed from
+    pub fn inputs(&self) -> Vec<&SensorConfig> {
+        match self {
+            SensorSource::Weighted { inputs } => inputs.iter().map(|input| &input.sensor).collect(),
+            SensorSource::Max { inputs } => inputs.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut SensorConfig> {
+        match self {
+            SensorSource::Weighted { inputs } => inputs.iter_mut().map(|input| &mut input.sensor).collect(),
+            SensorSource::Max { inputs } => inputs.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn resolve_paths(&mut self) -> bool {
+        self.inputs_mut().into_iter().all(SensorConfig::resolve_path)
+    }
+
+    fn is_resolved(&self) -> bool {
+        self.inputs().into_iter().all(SensorConfig::is_resolved)
+    }
+
+    fn strip_paths(&mut self) {
+        self.inputs_mut().into_iter().for_each(SensorConfig::strip_path);
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct FanStep {
+    pub temp: i32,
+    #[schemars(range(max = 100))]
+    pub power: u8, // 0-100%
+}
+
+/// Power asked for at a CPU utilization (0-100%)
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct LoadStep {
+    #[schemars(range(max = 100))]
+    pub load: u8,
+    #[schemars(range(max = 100))]
+    pub power: u8,
+}
+
+/// Runtime information about a fan, maintained by the control loop
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FanState {
+    /// Times manual mode had to be reasserted after the firmware took the fan back
+    pub control_reasserts: u64,
+    /// Fixed power the fan is overridden to, instead of following its curve
+    pub override_power: Option<u8>,
+    /// End of the boost running the fan at 100%
+    #[serde(skip)]
+    pub boost_until: Option<Instant>,
+    /// Temperature reached critical_temp; every fan runs at 100% while any fan is critical
+    pub critical: bool,
+    /// Last tachometer reading
+    pub rpm: Option<u32>,
+    /// Speed expected from rpm_curve at the power the fan was running at
+    pub expected_rpm: Option<u32>,
+    /// Speed rpm_steps ask for at the current temperature
+    pub target_rpm: Option<u32>,
+    /// Speed has been off the expected one for several cycles (failing bearing, unplugged header)
+    pub failing: bool,
+    /// Group the fan was last controlled in
+    pub group: Option<String>,
+    /// noise_weight the fan was last controlled with
+    pub noise_weight: Option<f64>,
+    /// Power the fan itself asks for, before matching the rest of its group
+    pub demand: Option<u8>,
+    /// Power the fan was last set to (or would have been, while left to the firmware),
+    /// tracked by the fans following it
+    pub power: Option<u8>,
+    /// Power was held down to max_power on the last cycle
+    pub capped: bool,
+    /// End of the last control cycle, or start of the control task before the first one
+    #[serde(skip)]
+    pub last_cycle: Option<Instant>,
+    /// The control loop hasn't completed a cycle for too long, set by the watchdog
+    pub stalled: bool,
+    /// The daemon took manual control of the fan and hasn't released it
+    pub owned: bool,
+    /// Raw pwm_enable value read on the last cycle
+    pub pwm_enable: Option<u8>,
+    /// Error of the last power write, None when it succeeded
+    pub last_write_error: Option<String>,
+    /// Power writes that failed in a row
+    pub write_errors: u32,
+    /// Sensor reads that failed in a row
+    pub read_errors: u32,
+    /// Filtered temperature of the last cycle, None while released or without a sensor
+    pub temperature: Option<i32>,
+    /// Temperature the curve was followed at instead, when predict leads it ahead
+    pub predicted_temperature: Option<i32>,
+    /// Stopped by semi_passive on the last cycle
+    pub semi_passive: bool,
+    /// Why the startup self-test failed, None if it passed or didn't run
+    pub self_test_error: Option<String>,
+    /// The chip drives the fan along the curve programmed into it
+    pub hardware_curve: bool,
+}
+
+impl FanState {
+    /// Why the fan can't be read, written or controlled, empty when it works
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.stalled {
+            problems.push("control loop stuck".to_string());
+        }
+        if self.read_errors > 0 {
+            problems.push(format!("sensor can't be read for {} cycles", self.read_errors));
+        }
+        if self.write_errors > 0 {
+            problems.push(format!("power can't be set: {}", self.last_write_error.as_deref().unwrap_or("unknown error")));
+        }
+        problems
+    }
+}
+
+/// Overall state of the daemon
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DaemonState {
+    /// Every fan is read and written
+    Running,
+    /// Some fans can't be read, written or are stuck, see FanState::problems
+    Degraded,
+    /// The control loop is shutting down
+    Stopped,
+}
+
+impl DaemonState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DaemonState::Running => "running",
+            DaemonState::Degraded => "degraded",
+            DaemonState::Stopped => "stopped",
+        }
+    }
+}
+
+/// Temperature and power statistics of a fan, since daemon start or the last reset
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FanStats {
+    pub samples: u64,
+    pub min_temp: Option<i32>,
+    pub max_temp: Option<i32>,
+    pub avg_temp: f64,
+    pub min_power: Option<u8>,
+    pub max_power: Option<u8>,
+    pub avg_power: f64,
+    /// Seconds spent at 100% power
+    pub secs_at_full_power: u64,
+    /// Control cycles completed, and how long they took
+    pub cycles: u64,
+    pub last_cycle_ms: f64,
+    pub avg_cycle_ms: f64,
+    pub max_cycle_ms: f64,
+    /// Sensor reads and power writes that failed
+    pub read_failures: u64,
+    pub write_failures: u64,
+    // Time and power of the previous sample, to account time spent at full power
+    #[serde(skip)]
+    last_sample: Option<(Instant, u8)>,
+    #[serde(skip)]
+    full_power_time: Duration,
+}
+
+impl FanStats {
+    fn record(&mut self, temp: i32, power: u8) {
+        let now = Instant::now();
+        if let Some((at, 100)) = self.last_sample {
+            self.full_power_time += now.duration_since(at);
+            self.secs_at_full_power = self.full_power_time.as_secs();
+        }
+        self.last_sample = Some((now, power));
+
+        self.samples += 1;
+        self.min_temp = Some(self.min_temp.map_or(temp, |min| min.min(temp)));
+        self.max_temp = Some(self.max_temp.map_or(temp, |max| max.max(temp)));
+        self.min_power = Some(self.min_power.map_or(power, |min| min.min(power)));
+        self.max_power = Some(self.max_power.map_or(power, |max| max.max(power)));
+        // Running averages, so nothing grows with the daemon's uptime
+        let samples = self.samples as f64;
+        self.avg_temp += (temp as f64 - self.avg_temp) / samples;
+        self.avg_power += (power as f64 - self.avg_power) / samples;
+    }
+
+    fn record_cycle(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        self.cycles += 1;
+        self.last_cycle_ms = ms;
+        self.max_cycle_ms = self.max_cycle_ms.max(ms);
+        self.avg_cycle_ms += (ms - self.avg_cycle_ms) / self.cycles as f64;
+    }
+}
+
+/// Fans whose devices were found elsewhere by a rescan, and those still missing
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RescanReport {
+    pub moved: Vec<String>,
+    pub missing: Vec<String>,
+    /// Outputs found at a path the daemon can't write since it gave up root, whose
+    /// fans are left at their previous devices
+    #[serde(default)]
+    pub unwritable: Vec<String>,
+}
+
+/// Statistics of every fan and how long they have been collected for
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Stats {
+    pub collected_secs: u64,
+    pub fans: HashMap<String, FanStats>,
+}
+
+// Statistics storage, reset as a whole
+struct StatsRecorder {
+    since: Instant,
+    fans: HashMap<String, FanStats>,
+}
+
+/// Commands processed by the control loop
+#[derive(Debug)]
+pub enum ControlCommand {
+    /// Replace the whole configuration
+    UpdateConfig(Box<Config>),
+    /// Run a fan at a fixed power, or return it to its curve with None
+    Override { fan: String, power: Option<u8> },
+    /// Run a fan at 100% for a while, then return it to its curve
+    Boost { fan: String, duration: Duration },
+    /// Hand every fan back to the firmware (true) or take them back (false)
+    Pause(bool),
+    /// Let fans exceed max_power and the night mode cap (true) or cap them again (false)
+    LiftPowerCap(bool),
+    /// Stop the control loop
+    Stop,
+    /// A fan's device moved (e.g. hwmon renumbering), sent by its control task
+    FanMoved { name: String, from: Box<FanConfig>, to: Box<FanConfig> },
+}
+
+/// A fan whose output couldn't be taken over when applying a configuration
+#[derive(Debug, Serialize, Clone)]
+pub struct FanApplyFailure {
+    pub fan: String,
+    pub error: String,
+}
+
+/// Why a configuration was not applied
+#[derive(Debug)]
+pub enum ApplyError {
+    /// Modified in the meantime, now at this revision
+    Modified(u64),
+    /// Some fans' outputs couldn't be taken over, the previous configuration was kept
+    FansFailed(Vec<FanApplyFailure>),
+}
+
+// A command, with the ID of the API call that sent it if any
+type TracedCommand = (ControlCommand, Option<String>);
+
+// What a fan control task is told to do, published by the control loop
+#[derive(Debug, Clone, PartialEq)]
+struct FanAssignment {
+    fan: Option<FanConfig>,
+    override_power: Option<u8>,
+    paused: bool,
+    power_cap_lifted: bool,
+    // Cap of the night mode while it's on
+    night_cap: Option<u8>,
+    // Read the sensors but leave the fan to the firmware (startup delay, observe mode)
+    hands_off: bool,
+}
+
+// State a fan's control task carries from one cycle to the next
+#[derive(Default)]
+struct FanLoopState {
+    // Configuration the fan is currently controlled with, None while released
+    controlled: Option<FanConfig>,
+    // Last plausible readings, fed to the median filter
+    recent_temps: VecDeque<i32>,
+    // Power set on the previous cycle, which the fan has had time to spin to
+    last_power: Option<u8>,
+    // Consecutive cycles the speed was off rpm_curve
+    rpm_deviations: u32,
+    // CPU times read over the last load_window_secs, oldest first
+    cpu_samples: VecDeque<(Instant, CpuTimes)>,
+    // Filtered temperature of the previous cycle
+    last_temp: Option<i32>,
+    // Last filtered temperatures, oldest first, extrapolated by predict
+    trend_temps: VecDeque<i32>,
+    // Whether the temperature was last seen falling, selecting cooldown_steps
+    cooling: bool,
+    // Power the curve last changed to and when, held for min_dwell_secs
+    dwell: Option<(u8, Instant)>,
+    // Temperature the curve was last evaluated at, kept while within dead_band
+    operating_temp: Option<i32>,
+    // Power of the last successful write
+    written_power: Option<u8>,
+    // The fan's curve is programmed into the chip, which drives the fan
+    hardware_curve: bool,
+    // Lua state of the fan's script, loaded on its first run
+    script: Option<ScriptState>,
+}
+
+struct FanTask {
+    handle: JoinHandle<()>,
+    assignment: watch::Sender<FanAssignment>,
+}
+
+#[derive(Clone)]
+pub struct FanController {
+    config: Arc<RwLock<Config>>,
+    fan_states: Arc<RwLock<HashMap<String, FanState>>>,
+    stats: Arc<Mutex<StatsRecorder>>,
+    alerts: Arc<Mutex<AlertTracker>>,
+    trips: Arc<Mutex<TripTracker>>,
+    semi_passive: Arc<Mutex<SemiPassiveState>>,
+    config_revision: Arc<AtomicU64>,
+    persistence: Arc<Mutex<Persistence>>,
+    running: Arc<AtomicBool>,
+    // Whether the fans are left to the firmware, paused or in the startup delay
+    hands_off: Arc<AtomicBool>,
+    // Number of control cycles run, for clients following the status
+    cycles: Arc<watch::Sender<u64>>,
+    commands: mpsc::UnboundedSender<TracedCommand>,
+    command_receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<TracedCommand>>>>,
+    started: Instant,
+    _safe_state: Arc<SafeStateGuard>,
+}
+
+/// Everything the daemon knows, dumped on SIGUSR1 when the API can't be reached.
+/// Parts locked by a stuck thread are left out rather than waited on.
+#[derive(Debug, Serialize)]
+pub struct StateDump {
+    pub uptime_secs: u64,
+    pub running: bool,
+    pub config_revision: u64,
+    /// Live configuration, with the device paths resolved
+    pub config: Option<Config>,
+    pub fans: Option<HashMap<String, FanState>>,
+    pub stats: Option<HashMap<String, FanStats>>,
+}
+
+// Whether the semi_passive fans are stopped, and since when every temperature has been low
+#[derive(Default)]
+struct SemiPassiveState {
+    low_since: Option<Instant>,
+    stopped: bool,
+}
+
+// Tracks what has been written to disk, so bursts of API changes are coalesced
+// into a single write
+struct Persistence {
+    saved_revision: u64,
+    last_modified: Instant,
+}
+
+/// Returns every configured fan to a safe state once the last controller handle
+/// is dropped, including while unwinding from a panic
+struct SafeStateGuard {
+    config: Arc<RwLock<Config>>,
+}
+
+impl Drop for SafeStateGuard {
+    fn drop(&mut self) {
+        restore_safe_state(&self.config);
+    }
+}
+
+impl FanController {
+    pub fn new(config: Config) -> Self {
+        let starting = config.startup_delay_secs.is_some_and(|secs| secs > 0);
+        let config = Arc::new(RwLock::new(config));
+        let (commands, command_receiver) = mpsc::unbounded_channel();
+        Self {
+            config: config.clone(),
+            fan_states: Arc::new(RwLock::new(HashMap::new())),
+            stats: Arc::new(Mutex::new(StatsRecorder { since: Instant::now(), fans: HashMap::new() })),
+            alerts: Arc::new(Mutex::new(AlertTracker::default())),
+            trips: Arc::new(Mutex::new(TripTracker::default())),
+            semi_passive: Arc::new(Mutex::new(SemiPassiveState::default())),
+            config_revision: Arc::new(AtomicU64::new(1)),
+            persistence: Arc::new(Mutex::new(Persistence { saved_revision: 1, last_modified: Instant::now() })),
+            running: Arc::new(AtomicBool::new(true)),
+            hands_off: Arc::new(AtomicBool::new(starting)),
+            cycles: Arc::new(watch::channel(0).0),
+            commands,
+            command_receiver: Arc::new(Mutex::new(Some(command_receiver))),
+            started: Instant::now(),
+            _safe_state: Arc::new(SafeStateGuard { config }),
+        }
+    }
+
+    /// Install a panic hook that hands every fan back to the firmware before the
+    /// process goes down, so a crash can't leave a fan stuck at a low speed
+    pub fn install_panic_hook(&self) {
+        let config = self.config.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            log!("Panic detected, returning fans to a safe state...");
+            restore_safe_state(&config);
+        }));
+    }
+
+    pub fn get_running(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    /// Send a command to the control loop
+    pub fn send(&self, command: ControlCommand) {
+        // Fails only once the control loop is gone, when there is nothing left to control
+        let _ = self.commands.send((command, None));
+    }
+
+    /// Send a command on behalf of an API call, logged with its request ID
+    pub fn send_from(&self, command: ControlCommand, request_id: &str) {
+        let _ = self.commands.send((command, Some(request_id.to_string())));
+    }
+
+    pub fn stop(&self) {
+        self.send(ControlCommand::Stop);
+    }
+
+    // The configuration is only ever replaced as a whole, so a panic while the lock
+    // was held can't have left it half-updated; recover from poisoning instead of
+    // taking the API and the control loop down with it
+    fn read_config(&self) -> RwLockReadGuard<'_, Config> {
+        self.config.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_config(&self) -> RwLockWriteGuard<'_, Config> {
+        self.config.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get_config(&self) -> Config {
+      let config_guard = self.read_config();
+      config_for_save(&config_guard)
+    }
+
+    /// /etc/fancontrol equivalent of the running configuration
+    pub fn export_fancontrol(&self) -> FancontrolExport {
+        let config_guard = self.read_config();
+        // fancontrol has a single interval, the shortest keeps every fan as responsive
+        let interval = config_guard.controlled_fans().values()
+            .map(|fan| control_interval(fan).as_secs())
+            .min()
+            .unwrap_or(CONTROL_INTERVAL.as_secs());
+        fancontrol::export(&config_guard, interval)
+    }
+
+    /// What replacing the configuration with `candidate` would change, along with the
+    /// revision it was compared against
+    pub fn diff_config(&self, candidate: &Config) -> (ConfigDiff, u64) {
+        let config_guard = self.read_config();
+        let diff = diff::diff_configs(&config_for_save(&config_guard), &config_for_save(candidate));
+        (diff, self.config_revision.load(Ordering::SeqCst))
+    }
+
+    /// Every hwmon PWM output, with the fan configured on it if any
+    pub fn get_pwm_outputs(&self) -> Vec<HwmonPwmInfo> {
+        let owners: HashMap<PathBuf, String> = self.read_config().controlled_fans().into_iter()
+            .map(|(name, fan)| (PathBuf::from(fan.pwm_input), name))
+            .collect();
+        let fan_states = self.get_fan_states();
+
+        enumerate_hwmon_devices().into_iter()
+            .flat_map(|device| {
+                let hwmon_dir = PathBuf::from(&device.hwmon_path);
+                device.pwms.into_iter().map(move |pwm| (device.name.clone(), hwmon_dir.clone(), pwm))
+            })
+            .map(|(name, hwmon_dir, pwm)| {
+                let pwm_input = hwmon_dir.join(&pwm);
+                let fan = owners.get(&pwm_input).cloned();
+                let controlled = fan.as_ref()
+                    .and_then(|fan| fan_states.get(fan))
+                    .is_some_and(|state| state.owned);
+                HwmonPwmInfo {
+                    name,
+                    hwmon_path: hwmon_dir.to_string_lossy().to_string(),
+                    value: backend::read_hwmon_pwm_value(&pwm_input),
+                    enable: backend::read_hwmon_pwm_enable(&pwm_input),
+                    mode: backend::read_hwmon_pwm_mode(&pwm_input),
+                    pwm,
+                    fan,
+                    controlled,
+                }
+            })
+            .collect()
+    }
+
+    /// One hwmon device, by name (e.g. nct6798) or directory (e.g. hwmon2)
+    pub fn get_hwmon_device(&self, name: &str) -> Option<HwmonDeviceDetail> {
+        let device = enumerate_hwmon_devices().into_iter().find(|device| device.is_named(name))?;
+        let hwmon_dir = PathBuf::from(&device.hwmon_path);
+        let driver = fs::read_link(hwmon_dir.join("device").join("driver")).ok()
+            .and_then(|driver| Some(driver.file_name()?.to_string_lossy().to_string()));
+
+        Some(HwmonDeviceDetail {
+            sensors: read_hwmon_sensors().into_iter().filter(|sensor| sensor.hwmon_path == device.hwmon_path).collect(),
+            pwms: self.get_pwm_outputs().into_iter().filter(|pwm| pwm.hwmon_path == device.hwmon_path).collect(),
+            fans: read_hwmon_fan_inputs(&hwmon_dir),
+            name: device.name,
+            hwmon_path: device.hwmon_path,
+            driver,
+        })
+    }
+
+    pub fn get_fan_states(&self) -> HashMap<String, FanState> {
+        self.fan_states.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Snapshot of the whole daemon state, never blocking on a lock
+    pub fn dump_state(&self) -> StateDump {
+        StateDump {
+            uptime_secs: self.started.elapsed().as_secs(),
+            running: self.running.load(Ordering::SeqCst),
+            config_revision: self.config_revision.load(Ordering::SeqCst),
+            config: try_read_lock(&self.config).map(|config| config.clone()),
+            fans: try_read_lock(&self.fan_states).map(|states| states.clone()),
+            stats: match self.stats.try_lock() {
+                Ok(stats) => Some(stats.fans.clone()),
+                Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner().fans.clone()),
+                Err(TryLockError::WouldBlock) => None,
+            },
+        }
+    }
+
+    fn update_fan_state(&self, name: &str, update: impl FnOnce(&mut FanState)) {
+        let mut states = self.fan_states.write().unwrap_or_else(PoisonError::into_inner);
+        update(states.entry(name.to_string()).or_default());
+    }
+
+    fn lock_stats(&self) -> MutexGuard<'_, StatsRecorder> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get_stats(&self) -> Stats {
+        let stats = self.lock_stats();
+        Stats { collected_secs: stats.since.elapsed().as_secs(), fans: stats.fans.clone() }
+    }
+
+    pub fn reset_stats(&self) {
+        let mut stats = self.lock_stats();
+        stats.since = Instant::now();
+        stats.fans.clear();
+    }
+
+    fn record_stats(&self, name: &str, temp: i32, power: u8) {
+        self.lock_stats().fans.entry(name.to_string()).or_default().record(temp, power);
+    }
+
+    fn update_fan_stats(&self, name: &str, update: impl FnOnce(&mut FanStats)) {
+        update(self.lock_stats().fans.entry(name.to_string()).or_default());
+    }
+
+    // Compare the tachometer with the fan's rpm_curve at the power it was running at.
+    // Returns whether the fan is failing, None without a curve or a reading.
+    fn check_rpm(&self, name: &str, fan: &FanConfig, loop_state: &mut FanLoopState, rpm: Option<u32>) -> Option<bool> {
+        let expected = loop_state.last_power.and_then(|power| expected_rpm(&fan.rpm_curve, power));
+        let failing = match (rpm, expected) {
+            (Some(rpm), Some(expected)) => {
+                let tolerance = expected * fan.rpm_tolerance_pct.unwrap_or(DEFAULT_RPM_TOLERANCE_PCT) as u32 / 100;
+                if rpm.abs_diff(expected) > tolerance {
+                    loop_state.rpm_deviations += 1;
+                } else {
+                    loop_state.rpm_deviations = 0;
+                }
+                Some(loop_state.rpm_deviations >= RPM_FAILURE_CYCLES)
+            }
+            _ => None,
+        };
+
+        let mut newly_failing = false;
+        self.update_fan_state(name, |state| {
+            newly_failing = failing == Some(true) && !state.failing;
+            state.rpm = rpm;
+            state.expected_rpm = expected;
+            state.failing = failing.unwrap_or(false);
+        });
+        if newly_failing {
+            let message = format!("Running at {} RPM instead of the expected {} RPM", rpm.unwrap_or(0), expected.unwrap_or(0));
+            log!("Fan: {} - {}, fan may be failing", name, message);
+            self.report_fan_error(name, FanError::Failing, &message);
+        }
+        failing
+    }
+
+    // Power asked by the fan's script, or by its curve without a script or when it fails
+    fn policy_power(&self, name: &str, fan: &FanConfig, loop_state: &mut FanLoopState, temp: i32) -> u8 {
+        if let Some(script) = &fan.script {
+            match script.power(&mut loop_state.script, name, temp, loop_state.last_power) {
+                Ok(power) => return power,
+                // Nothing to fall back on, better too loud than too hot
+                Err(e) if !fan.has_curve() => {
+                    log!("Fan: {} - Script failed, running at 100%: {}", name, e);
+                    return 100;
+                }
+                Err(e) => log!("Fan: {} - Script failed, following the steps: {}", name, e),
+            }
+        }
+        if !fan.rpm_steps.is_empty() {
+            return self.rpm_target_power(name, fan, loop_state, self.curve_temp(name, fan, temp));
+        }
+        fan.curve_demand(self.curve_temp(name, fan, temp), loop_state.cooling)
+    }
+
+    // Power bringing the fan to the speed its rpm_steps give at `temp`: the power of the
+    // previous cycle, nudged by how far the tachometer is from that speed
+    fn rpm_target_power(&self, name: &str, fan: &FanConfig, loop_state: &FanLoopState, temp: i32) -> u8 {
+        let target = target_rpm(&fan.rpm_steps, temp);
+        self.update_fan_state(name, |state| state.target_rpm = Some(target));
+        if target == 0 {
+            return 0;
+        }
+        let sink = backend::pwm_sink(fan);
+        // Without a reading to correct, start from the power rpm_curve measured for the target
+        let estimate = || power_for_rpm(&fan.rpm_curve, target).or(loop_state.last_power).unwrap_or(100);
+        let Some(rpm) = sink.read_rpm() else {
+            log!("Fan: {} - No tachometer reading to target {} RPM", name, target);
+            return estimate();
+        };
+        let Some(power) = loop_state.last_power else {
+            return estimate();
+        };
+
+        log!("Fan: {} - Speed: {} RPM - Target: {} RPM", name, rpm, target);
+        let error = target as f64 - rpm as f64;
+        if error.abs() <= target as f64 * RPM_TARGET_TOLERANCE_PCT / 100.0 {
+            return power;
+        }
+        let step = (error / target as f64 * RPM_TARGET_GAIN).clamp(-RPM_TARGET_MAX_STEP, RPM_TARGET_MAX_STEP);
+        // Always move by at least 1%, or a small error would never be corrected
+        let step = if step.abs() < 1.0 { step.signum() } else { step.round() };
+        (power as f64 + step).clamp(0.0, 100.0) as u8
+    }
+
+    // Temperature the steps are keyed on: the sensor's, or its delta to the ambient sensor
+    fn curve_temp(&self, name: &str, fan: &FanConfig, temp: i32) -> i32 {
+        let Some(ambient) = &fan.ambient else {
+            return temp;
+        };
+        match backend::temp_source(&ambient.as_fan(fan)).read_temp() {
+            Ok(ambient_temp) => {
+                log!("Fan: {} - Ambient: {}°C - Delta: {}°C", name, ambient_temp, temp - ambient_temp);
+                temp - ambient_temp
+            }
+            Err(e) => {
+                // The absolute temperature is always above the delta, so this errs on the side of cooling
+                log!("Fan: {} - Failed to read ambient sensor, using the absolute temperature: {}", name, e);
+                temp
+            }
+        }
+    }
+
+    // Whether any fan is above its critical_temp
+    fn emergency(&self) -> bool {
+        self.fan_states.read().unwrap_or_else(PoisonError::into_inner).values().any(|state| state.critical)
+    }
+
+    // Power asked for by a fan following `leader`, None until the leader has run a cycle
+    fn followed_power(&self, name: &str, fan: &FanConfig, leader: &str, override_power: Option<u8>) -> Option<u8> {
+        // No sensor of its own to turn critical, but still part of an emergency
+        if self.emergency() {
+            return Some(100);
+        }
+        let states = self.fan_states.read().unwrap_or_else(PoisonError::into_inner);
+        if override_power.is_some() {
+            return override_power;
+        }
+        let power = states.get(leader).and_then(|state| state.power);
+        if power.is_none() {
+            log!("Fan: {} - Waiting for {} to be controlled", name, leader);
+        }
+        power.map(|power| fan.follow_power(power))
+    }
+
+    // Publish the power a fan asks for and return the one it has to run at: the
+    // highest demand of its group, so every member spins at the same speed, or with
+    // noise_budget its share of the group's cooling
+    fn group_power(&self, name: &str, fan: &FanConfig, demand: u8) -> u8 {
+        let noise_budget = self.read_config().noise_budget && !self.emergency();
+        let mut states = self.fan_states.write().unwrap_or_else(PoisonError::into_inner);
+        let state = states.entry(name.to_string()).or_default();
+        state.group = fan.group.clone();
+        state.noise_weight = fan.noise_weight;
+        state.demand = Some(demand);
+
+        let Some(group) = &fan.group else {
+            return demand;
+        };
+        let members: Vec<(&String, &FanState)> = states.iter()
+            .filter(|(_, state)| state.group.as_ref() == Some(group) && state.demand.is_some())
+            .collect();
+        let highest = members.iter().filter_map(|(_, state)| state.demand).max().unwrap_or(demand);
+        if !noise_budget {
+            return highest;
+        }
+        let weights: Vec<(&str, f64)> = members.iter()
+            .map(|(member, state)| (member.as_str(), state.noise_weight.unwrap_or(1.0)))
+            .collect();
+        noise_budget_power(name, highest, &weights)
+    }
+
+    // Whether `name` is one of the semi_passive fans and they are stopped, after updating
+    // their state from the temperatures of every fan
+    fn semi_passive_stopped(&self, name: &str) -> bool {
+        let Some(semi_passive) = self.read_config().semi_passive.clone() else {
+            return false;
+        };
+        let (hottest, unreadable) = {
+            let states = self.fan_states.read().unwrap_or_else(PoisonError::into_inner);
+            (states.values().filter_map(|state| state.temperature).max(), states.values().any(|state| state.read_errors > 0))
+        };
+        let mut state = self.semi_passive.lock().unwrap_or_else(PoisonError::into_inner);
+        match hottest {
+            // An unreadable sensor could be hiding anything, so it wakes the fans too
+            Some(hottest) if !unreadable && hottest < semi_passive.stop_below => {
+                let low_since = *state.low_since.get_or_insert_with(Instant::now);
+                if !state.stopped && low_since.elapsed() >= Duration::from_secs(semi_passive.idle_secs) {
+                    log!("Semi-passive: every temperature below {}°C for {}s, stopping {}", semi_passive.stop_below, semi_passive.idle_secs, semi_passive.fans.join(", "));
+                    state.stopped = true;
+                }
+            }
+            Some(hottest) if !unreadable && hottest < semi_passive.wake_above && state.stopped => {}
+            _ => {
+                state.low_since = None;
+                if state.stopped {
+                    match hottest {
+                        Some(hottest) if !unreadable => log!("Semi-passive: {}°C reached, resuming {}", hottest, semi_passive.fans.join(", ")),
+                        _ => log!("Semi-passive: a sensor can't be read, resuming {}", semi_passive.fans.join(", ")),
+                    }
+                    state.stopped = false;
+                }
+            }
+        }
+        state.stopped && semi_passive.fans.iter().any(|fan| fan == name)
+    }
+
+    // Track whether a fan is above its critical_temp, starting the emergency when the
+    // first fan gets there. Returns whether any fan is critical.
+    fn check_critical(&self, name: &str, fan: &FanConfig, temp: i32) -> bool {
+        let (starts_emergency, emergency) = {
+            let mut states = self.fan_states.write().unwrap_or_else(PoisonError::into_inner);
+            let was_critical = states.get(name).is_some_and(|state| state.critical);
+            let critical = fan.critical_temp.is_some_and(|limit| {
+                temp >= limit || (was_critical && temp > limit - CRITICAL_HYSTERESIS)
+            });
+            let others_critical = states.iter().any(|(other, state)| other != name && state.critical);
+            states.entry(name.to_string()).or_default().critical = critical;
+
+            if critical && !was_critical {
+                log!("Fan: {} - CRITICAL temperature {}°C, forcing every fan to 100%", name, temp);
+            } else if was_critical && !critical {
+                log!("Fan: {} - Temperature back below critical at {}°C", name, temp);
+            }
+            (critical && !was_critical && !others_critical, critical || others_critical)
+        };
+
+        if starts_emergency {
+            self.start_emergency(name, temp);
+        }
+        emergency
+    }
+
+    fn start_emergency(&self, name: &str, temp: i32) {
+        let (fans, action) = {
+            let config = self.read_config();
+            (config.controlled_fans(), config.critical_action.clone().unwrap_or_default())
+        };
+
+        // Don't wait for the other fans' next cycle
+        for (fan_name, fan) in &fans {
+            if backend::pwm_sink(fan).set_power(100).is_err() {
+                log!("Failed to force fan {} to 100%", fan_name);
+            }
+        }
+
+        match action {
+            CriticalAction::FullSpeed => {}
+            CriticalAction::Command { cmd } => {
+                log!("Running critical action: {}", cmd);
+                let result = Command::new("sh")
+                    .arg("-c")
+                    .arg(&cmd)
+                    .env("COOLD_FAN", name)
+                    .env("COOLD_TEMP", temp.to_string())
+                    .spawn();
+                if let Err(e) = result {
+                    log!("Failed to run critical action: {}", e);
+                }
+            }
+            CriticalAction::Shutdown => {
+                log!("Critical temperature reached, powering off");
+                let result = Command::new("busctl")
+                    .args(["call", "org.freedesktop.login1", "/org/freedesktop/login1",
+                        "org.freedesktop.login1.Manager", "PowerOff", "b", "false"])
+                    .status();
+                if !result.is_ok_and(|status| status.success()) {
+                    log!("Failed to power off through logind, trying systemctl");
+                    if let Err(e) = Command::new("systemctl").arg("poweroff").status() {
+                        log!("Failed to power off: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the on_stop hook, waiting for it so it isn't killed with the daemon
+    pub fn run_stop_hook(&self) {
+        let command = self.read_config().hooks.as_ref().and_then(|hooks| hooks.on_stop.clone());
+        hooks::run_and_wait("stop", command.as_deref(), &[]);
+    }
+
+    // Run the on_fan_error hook, called when an error starts rather than on every cycle it lasts
+    fn report_fan_error(&self, name: &str, error: FanError, message: &str) {
+        let command = self.read_config().hooks.as_ref().and_then(|hooks| hooks.on_fan_error.clone());
+        logging::event("fan_error", json!({ "fan": name, "error": error.as_str(), "message": message }));
+        let env = [("COOLD_FAN", name.to_string()), ("COOLD_ERROR", error.as_str().to_string()), ("COOLD_MESSAGE", message.to_string())];
+        hooks::run("fan_error", command.as_deref(), &env);
+    }
+
+    // Evaluate the alert rules and trip points against what a control cycle saw
+    fn check_alerts(&self, name: &str, observation: &Observation) {
+        let (rules, trip_points) = {
+            let config = self.read_config();
+            (config.alerts.clone(), config.trip_points.clone())
+        };
+        if let (Some(temp), false) = (observation.temp, trip_points.is_empty()) {
+            self.trips.lock().unwrap_or_else(PoisonError::into_inner).observe(&trip_points, name, temp);
+        }
+        if rules.is_empty() {
+            return;
+        }
+        let events = self.alerts.lock().unwrap_or_else(PoisonError::into_inner).observe(&rules, name, observation);
+        for (rule, event) in events {
+            alert::send(rule, event);
+        }
+    }
+
+    /// Send a test event to the webhooks of one rule, or of every rule
+    pub fn test_alerts(&self, rule: Option<&str>) -> Vec<alert::WebhookResult> {
+        let rules = self.read_config().alerts.clone();
+        alert::test_fire(&rules, rule)
+    }
+
+    fn lock_persistence(&self) -> MutexGuard<'_, Persistence> {
+        self.persistence.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write the configuration to disk if it changed since the last save.
+    /// Returns whether anything was written.
+    pub fn save_config_now(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        // A simulated configuration must not overwrite the real one
+        if simulation::is_active() {
+            return Ok(false);
+        }
+        let (config, revision) = self.get_config_with_revision();
+        // Held during the write so concurrent saves can't go out of order
+        let mut persistence = self.lock_persistence();
+        if revision <= persistence.saved_revision {
+            return Ok(false);
+        }
+        save_config(&config)?;
+        persistence.saved_revision = revision;
+        Ok(true)
+    }
+
+    /// Save configuration changes once they have been quiet for a while, so a
+    /// script making many API calls doesn't rewrite the file every time
+    pub async fn run_persistence(self) {
+        loop {
+            time::sleep(PERSIST_CHECK_INTERVAL).await;
+            let due = {
+                let persistence = self.lock_persistence();
+                persistence.saved_revision != self.config_revision.load(Ordering::SeqCst)
+                    && persistence.last_modified.elapsed() >= PERSIST_QUIET_PERIOD
+            };
+            if due {
+                let controller = self.clone();
+                match task::spawn_blocking(move || controller.save_config_now().map_err(|e| e.to_string())).await {
+                    Ok(Ok(_)) => log!("Configuration saved"),
+                    Ok(Err(e)) => log!("Failed to save configuration: {}", e),
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+
+    /// Get the configuration along with its revision, which changes on every update
+    pub fn get_config_with_revision(&self) -> (Config, u64) {
+        let config_guard = self.read_config();
+        (config_for_save(&config_guard), self.config_revision.load(Ordering::SeqCst))
+    }
+
+    /// Replace the configuration, if it is still at `expected_revision` when one is given.
+    /// The output of every new or changed fan is taken over first; if any of them fails,
+    /// the outputs taken over are handed back and the running configuration is kept.
+    /// While the fans are left to the firmware (paused or startup delay) the outputs are
+    /// only checked, the fan tasks take them over once control resumes.
+    pub fn apply_config(&self, expected_revision: Option<u64>, new_config: Config) -> Result<(), ApplyError> {
+        let (revision, running, mut candidate) = {
+            let config_guard = self.read_config();
+            let revision = self.config_revision.load(Ordering::SeqCst);
+            if expected_revision.is_some_and(|expected| expected != revision) {
+                return Err(ApplyError::Modified(revision));
+            }
+            (revision, config_guard.controlled_fans(), new_config)
+        };
+        resolve_unresolved_paths(&mut candidate);
+        let changed: Vec<(String, FanConfig)> = candidate.controlled_fans().into_iter()
+            .filter(|(name, fan)| running.get(name) != Some(fan))
+            .collect();
+
+        // The outputs are written without holding the lock, so the API isn't blocked
+        // on slow sysfs writes. Nothing is written in observe mode.
+        let mut taken_over = Vec::new();
+        if !backend::is_observe_only() {
+            let hands_off = self.hands_off.load(Ordering::SeqCst);
+            let mut failures = Vec::new();
+            for (name, fan) in &changed {
+                let sink = backend::pwm_sink(fan);
+                let result = backend::check_writable(fan).map_err(|e| e.to_string()).and_then(|_| match sink.read_power() {
+                    Some(_) if hands_off => Ok(()),
+                    Some(_) => sink.set_manual(true).map_err(|e| format!("Failed to take manual control: {}", e)),
+                    None => Err(format!("PWM output {} not readable", fan.pwm_input)),
+                });
+                match result {
+                    Ok(()) if !hands_off => taken_over.push(fan),
+                    Ok(()) => {}
+                    Err(error) => failures.push(FanApplyFailure { fan: name.clone(), error }),
+                }
+            }
+            if !failures.is_empty() {
+                hand_back(&taken_over, &running);
+                log!("Configuration rolled back, failed to take over {}",
+                    failures.iter().map(|failure| failure.fan.as_str()).collect::<Vec<_>>().join(", "));
+                return Err(ApplyError::FansFailed(failures));
+            }
+        }
+
+        let mut config_guard = self.write_config();
+        // Changed while the outputs were being taken over, don't overwrite that change
+        let current = self.config_revision.load(Ordering::SeqCst);
+        if current != revision {
+            drop(config_guard);
+            hand_back(&taken_over, &self.read_config().controlled_fans());
+            return Err(ApplyError::Modified(current));
+        }
+        self.apply_modification(&mut config_guard, |config| *config = candidate);
+        Ok(())
+    }
+
+    /// Set the steps of a fan, and its cooldown steps when given.
+    /// Returns whether the fan exists.
+    pub fn update_fan_steps(&self, name: &str, steps: Vec<FanStep>, cooldown_steps: Option<Vec<FanStep>>) -> bool {
+        self.modify_config(|config| {
+            config.fan.get_mut(name)
+                .map(|fan| {
+                    // Explicit steps detach the fan from its named curve
+                    fan.steps = steps;
+                    fan.curve = None;
+                    if let Some(cooldown_steps) = cooldown_steps {
+                        fan.cooldown_steps = cooldown_steps;
+                    }
+                })
+                .is_some()
+        })
+    }
+
+    /// Remove a fan. Returns whether it existed, None while other fans follow it.
+    pub fn remove_fan(&self, name: &str) -> Option<bool> {
+        self.modify_config(|config| {
+            (!config.is_followed(name)).then(|| config.fan.remove(name).is_some())
+        })
+    }
+
+    /// Apply a change to the configuration atomically: the write lock is held for
+    /// the whole read-modify-write, so concurrent changes can't overwrite each other
+    pub fn modify_config<R>(&self, modify: impl FnOnce(&mut Config) -> R) -> R {
+        let mut config_guard = self.write_config();
+        self.apply_modification(&mut config_guard, modify)
+    }
+
+    // Must be called with the write lock held, `config` being the locked configuration
+    fn apply_modification<R>(&self, config: &mut Config, modify: impl FnOnce(&mut Config) -> R) -> R {
+        let before = config.clone();
+        let result = modify(config);
+        // Rejected requests leave the config as it was, don't bump the revision for them
+        if *config == before {
+            return result;
+        }
+        self.config_revision.fetch_add(1, Ordering::SeqCst);
+        self.lock_persistence().last_modified = Instant::now();
+        // Fans coming from the API only carry file names, resolve them to sysfs paths
+        resolve_unresolved_paths(config);
+        // Sent under the lock so the control loop sees changes in the same order
+        self.send(ControlCommand::UpdateConfig(Box::new(config.clone())));
+        result
+    }
+
+    /// Look every fan's devices up again, e.g. after loading a kernel module or plugging
+    /// in a USB sensor, and control the fans found elsewhere at their new paths
+    pub fn rescan_devices(&self) -> RescanReport {
+        let mut config_guard = self.write_config();
+        let mut rescanned = config_guard.clone();
+        let mut unwritable = Vec::new();
+        update_fan_paths(&mut rescanned, |fan| {
+            let previous = fan.clone();
+            resolve_fan_paths(fan);
+            if fan.pwm_input != previous.pwm_input {
+                if let Err(e) = backend::check_writable(fan) {
+                    log!("Rescan found {} at {}, keeping {}: {}", fan.pwm_name, fan.pwm_input, previous.pwm_input, e);
+                    unwritable.push(fan.pwm_input.clone());
+                    *fan = previous;
+                }
+            }
+        });
+        for source in rescanned.sensors.values_mut() {
+            source.resolve_paths();
+        }
+
+        let running = config_guard.controlled_fans();
+        let mut report = RescanReport { unwritable, ..Default::default() };
+        for (name, fan) in rescanned.controlled_fans() {
+            if running.get(&name) != Some(&fan) {
+                log!("Fan: {} - Rescan found it at {} and {}", name, fan.sensor_input, fan.pwm_input);
+                report.moved.push(name.clone());
+            }
+            let sensor_missing = fan.reads_hwmon_sensor() && !Path::new(&fan.sensor_input).exists();
+            let pwm_missing = !fan.backend.is_sysctl() && !Path::new(&fan.pwm_input).exists();
+            if sensor_missing || pwm_missing {
+                report.missing.push(name);
+            }
+        }
+        report.moved.sort();
+        report.missing.sort();
+
+        let sensors_changed = rescanned.sensors != config_guard.sensors;
+        if !report.moved.is_empty() || sensors_changed {
+            self.apply_modification(&mut config_guard, |config| *config = rescanned);
+        }
+        report
+    }
+
+    /// Run the control loop: one task per fan, driven by the commands received
+    /// from the API, plus supervision of suspend/resume
+    pub async fn run(&self) {
+        log!("Starting fan control daemon...");
+
+        let Some(mut commands) = self.command_receiver.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+            log!("Control loop already running");
+            return;
+        };
+        let mut config = self.read_config().clone();
+        let mut overrides: HashMap<String, u8> = HashMap::new();
+        let mut boosts: HashMap<String, Instant> = HashMap::new();
+        let mut paused = false;
+        let mut power_cap_lifted = false;
+        let mut night_cap = None;
+        let mut tasks: HashMap<String, FanTask> = HashMap::new();
+        let mut suspend_detector = SuspendDetector::new();
+        let started = Instant::now();
+        let mut starting = config.startup_delay_secs.is_some_and(|secs| secs > 0);
+        if starting {
+            log!("Leaving the fans to the firmware for {}s after startup", config.startup_delay_secs.unwrap_or_default());
+        }
+        let mut profile = hooks::profile(paused, power_cap_lifted, night_cap);
+        let mut fans: Vec<String> = config.controlled_fans().into_keys().collect();
+        fans.sort();
+        let command = config.hooks.as_ref().and_then(|hooks| hooks.on_start.as_deref());
+        hooks::run("start", command, &[("COOLD_PROFILE", profile.to_string()), ("COOLD_FANS", fans.join(" "))]);
+
+        while self.running.load(Ordering::SeqCst) {
+            tokio::select! {
+                Some((command, request_id)) = commands.recv() => {
+                    let origin = request_id.map(|id| format!(" (request {})", id)).unwrap_or_default();
+                    match command {
+                        ControlCommand::UpdateConfig(new_config) => {
+                            config = *new_config;
+                            let fans = config.controlled_fans();
+                            overrides.retain(|name, _| fans.contains_key(name));
+                            boosts.retain(|name, _| fans.contains_key(name));
+                        }
+                        ControlCommand::Override { fan, power } => {
+                            match power {
+                                Some(power) => {
+                                    log!("Fan: {} - Overridden to {}%{}", fan, power, origin);
+                                    overrides.insert(fan.clone(), power.min(100));
+                                }
+                                None => {
+                                    log!("Fan: {} - Override cleared{}", fan, origin);
+                                    overrides.remove(&fan);
+                                }
+                            }
+                            self.update_fan_state(&fan, |state| state.override_power = power);
+                        }
+                        ControlCommand::Boost { fan, duration } => {
+                            log!("Fan: {} - Boosted to 100% for {}s{}", fan, duration.as_secs(), origin);
+                            let until = Instant::now() + duration;
+                            boosts.insert(fan.clone(), until);
+                            self.update_fan_state(&fan, |state| state.boost_until = Some(until));
+                        }
+                        ControlCommand::Pause(pause) => {
+                            log!("{} fan control{}", if pause { "Pausing" } else { "Resuming" }, origin);
+                            paused = pause;
+                        }
+                        ControlCommand::LiftPowerCap(lift) => {
+                            log!("{} power caps{}", if lift { "Lifting" } else { "Restoring" }, origin);
+                            power_cap_lifted = lift;
+                        }
+                        ControlCommand::Stop => self.running.store(false, Ordering::SeqCst),
+                        ControlCommand::FanMoved { name, from, to } => {
+                            let mut config_guard = self.write_config();
+                            // Only update the entry if it hasn't been changed in the meantime
+                            if config_guard.move_fan(&name, &from, &to) {
+                                config.move_fan(&name, &from, &to);
+                            }
+                        }
+                    }
+                }
+                _ = time::sleep(SUPERVISOR_INTERVAL) => {
+                    if starting && config.startup_delay_secs.is_none_or(|secs| started.elapsed() >= Duration::from_secs(secs)) {
+                        log!("Startup delay over, taking control of the fans");
+                        starting = false;
+                    }
+                    boosts.retain(|fan, until| {
+                        let active = *until > Instant::now();
+                        if !active {
+                            log!("Fan: {} - Boost over", fan);
+                            self.update_fan_state(fan, |state| state.boost_until = None);
+                        }
+                        active
+                    });
+                    // Many boards reset pwm*_enable on resume, take the fans back right away
+                    if let Some(suspended) = suspend_detector.check() {
+                        if !paused {
+                            log!("Resumed after {}s of suspend, reinitializing fans", suspended.as_secs());
+                            let controller = self.clone();
+                            let _ = task::spawn_blocking(move || controller.init_fans()).await;
+                        }
+                    }
+                }
+            }
+
+            // Also after commands, a configuration update can switch night mode
+            let cap = config.night_mode.as_ref().and_then(NightMode::current_cap);
+            if cap != night_cap {
+                match cap {
+                    Some(cap) => log!("Night mode started, capping every fan at {}%", cap),
+                    None => log!("Night mode ended"),
+                }
+                night_cap = cap;
+            }
+
+            let current = hooks::profile(paused, power_cap_lifted, night_cap);
+            if current != profile {
+                log!("Profile changed from {} to {}", profile, current);
+                let command = config.hooks.as_ref().and_then(|hooks| hooks.on_profile_change.as_deref());
+                hooks::run("profile_change", command, &[("COOLD_PROFILE", current.to_string()), ("COOLD_PREVIOUS_PROFILE", profile.to_string())]);
+                profile = current;
+            }
+
+            // A boost takes precedence over an override, which comes back once it's over
+            let mut forced = overrides.clone();
+            forced.extend(boosts.keys().map(|fan| (fan.clone(), 100)));
+            let shared = FanAssignment {
+                fan: None,
+                override_power: None,
+                paused,
+                power_cap_lifted,
+                night_cap,
+                hands_off: starting || backend::is_observe_only(),
+            };
+            self.hands_off.store(paused || shared.hands_off, Ordering::SeqCst);
+            self.publish_assignments(&mut tasks, &config, &forced, &shared);
+        }
+
+        for task in tasks.values() {
+            task.handle.abort();
+        }
+        let controller = self.clone();
+        let _ = task::spawn_blocking(move || controller.cleanup_fans()).await;
+        log!("Shutdown complete.");
+    }
+
+    // Tell every fan task what to do, starting tasks for new fans. Tasks of removed
+    // fans release their fan and end on their own. `shared` holds what every fan is told.
+    fn publish_assignments(&self, tasks: &mut HashMap<String, FanTask>, config: &Config, overrides: &HashMap<String, u8>, shared: &FanAssignment) {
+        tasks.retain(|_, task| !task.handle.is_finished());
+        let fans = config.controlled_fans();
+        for (name, task) in tasks.iter() {
+            let assignment = FanAssignment {
+                fan: fans.get(name).cloned(),
+                override_power: overrides.get(name).copied(),
+                ..shared.clone()
+            };
+            task.assignment.send_if_modified(|current| {
+                let modified = *current != assignment;
+                *current = assignment;
+                modified
+            });
+        }
+        for (name, fan) in &fans {
+            if !tasks.contains_key(name) {
+                let (assignment, receiver) = watch::channel(FanAssignment {
+                    fan: Some(fan.clone()),
+                    override_power: overrides.get(name).copied(),
+                    ..shared.clone()
+                });
+                self.update_fan_state(name, |state| state.last_cycle = Some(Instant::now()));
+                let handle = tokio::spawn(self.clone().control_fan(name.clone(), receiver));
+                tasks.insert(name.clone(), FanTask { handle, assignment });
+            }
+        }
+    }
+
+    // Control loop of a single fan, so a slow or hung sensor only delays its own fan
+    async fn control_fan(self, name: String, mut assignment: watch::Receiver<FanAssignment>) {
+        let mut loop_state = FanLoopState::default();
+
+        while self.running.load(Ordering::SeqCst) {
+            let assigned = assignment.borrow_and_update().clone();
+            let removed = assigned.fan.is_none();
+            let interval = assigned.fan.as_ref().map_or(CONTROL_INTERVAL, control_interval);
+            let controller = self.clone();
+            let fan_name = name.clone();
+            let started = Instant::now();
+            // Sensor and sysfs I/O may block, keep it off the async workers
+            let cycle = task::spawn_blocking(move || controller.control_cycle(&fan_name, loop_state, assigned));
+            match cycle.await {
+                Ok(state) => loop_state = state,
+                Err(_) => break,
+            }
+            if !removed {
+                self.update_fan_stats(&name, |stats| stats.record_cycle(started.elapsed()));
+            }
+            self.cycle_completed(&name);
+            if removed {
+                break;
+            }
+
+            // A new assignment (configuration, override, pause, power cap, night mode) is
+            // applied right away rather than on the next tick
+            tokio::select! {
+                _ = time::sleep(interval) => {}
+                changed = assignment.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn cycle_completed(&self, name: &str) {
+        let mut recovered = false;
+        self.update_fan_state(name, |state| {
+            state.last_cycle = Some(Instant::now());
+            recovered = std::mem::take(&mut state.stalled);
+        });
+        if recovered {
+            log!("Fan: {} - Control loop recovered", name);
+        }
+        self.cycles.send_modify(|cycles| *cycles += 1);
+    }
+
+    /// Changes after each control cycle of any fan
+    pub fn watch_cycles(&self) -> watch::Receiver<u64> {
+        self.cycles.subscribe()
+    }
+
+    /// Watch the fan control loops from a thread of its own, so it keeps running if the
+    /// async runtime gets stuck too
+    pub fn start_watchdog(&self) {
+        let controller = self.clone();
+        thread::spawn(move || {
+            while controller.running.load(Ordering::SeqCst) {
+                thread::sleep(WATCHDOG_INTERVAL);
+                controller.check_stalls();
+            }
+        });
+    }
+
+    fn check_stalls(&self) {
+        let (fans, watchdog) = {
+            let config = self.read_config();
+            (config.controlled_fans(), config.watchdog.clone().unwrap_or_default())
+        };
+        for (name, fan) in &fans {
+            let limit = control_interval(fan) * watchdog.stall_cycles.max(1);
+            let mut newly_stalled = None;
+            self.update_fan_state(name, |state| {
+                let Some(last_cycle) = state.last_cycle else {
+                    return;
+                };
+                if !state.stalled && last_cycle.elapsed() > limit {
+                    state.stalled = true;
+                    newly_stalled = Some(last_cycle.elapsed());
+                }
+            });
+            let Some(stalled_for) = newly_stalled else {
+                continue;
+            };
+            log!("WATCHDOG: Fan: {} - Control loop stuck for {}s (hung sensor or device?)", name, stalled_for.as_secs());
+            self.report_fan_error(name, FanError::Stalled, &format!("Control loop stuck for {}s", stalled_for.as_secs()));
+            if watchdog.release_fans && !backend::is_observe_only() {
+                log!("WATCHDOG: Fan: {} - Handing the fan back to the firmware", name);
+                if backend::pwm_sink(fan).set_manual(false).is_err() {
+                    log!("WATCHDOG: Fan: {} - Failed to return to automatic mode", name);
+                }
+            }
+        }
+    }
+
+    /// Fans whose control loop is stuck, see the watchdog
+    pub fn stalled_fans(&self) -> Vec<String> {
+        let mut stalled: Vec<String> = self.get_fan_states().into_iter()
+            .filter(|(_, state)| state.stalled)
+            .map(|(name, _)| name)
+            .collect();
+        stalled.sort();
+        stalled
+    }
+
+    /// Problems of every fan having some, by fan name
+    pub fn fan_problems(&self) -> BTreeMap<String, Vec<String>> {
+        self.get_fan_states().into_iter()
+            .map(|(name, state)| (name, state.problems()))
+            .filter(|(_, problems)| !problems.is_empty())
+            .collect()
+    }
+
+    pub fn daemon_state(&self) -> DaemonState {
+        if !self.running.load(Ordering::SeqCst) {
+            DaemonState::Stopped
+        } else if self.fan_problems().is_empty() {
+            DaemonState::Running
+        } else {
+            DaemonState::Degraded
+        }
+    }
+
+    // One control cycle of a fan, carrying its loop state over to the next cycle
+    fn control_cycle(&self, name: &str, mut loop_state: FanLoopState, assignment: FanAssignment) -> FanLoopState {
+        let previous = loop_state.controlled.take();
+        let (Some(mut fan), false) = (assignment.fan, assignment.paused) else {
+            self.update_fan_state(name, |state| {
+                state.critical = false;
+                state.demand = None;
+                state.power = None;
+                state.owned = false;
+                state.temperature = None;
+                state.predicted_temperature = None;
+            });
+            if let Some(previous) = previous {
+                log!("Fan: {} - Releasing control", name);
+                set_pwm_enable_with_retry(&previous, false);
+            }
+            return loop_state;
+        };
+
+        // Take the fan over when first seen or when its hardware mapping changed
+        if !assignment.hands_off && previous.as_ref().map(hw_key) != Some(hw_key(&fan)) {
+            if let Some(previous) = &previous {
+                set_pwm_enable_with_retry(previous, false);
+            }
+            loop_state = FanLoopState::default();
+            init_fan(name, &fan);
+            if fan.hardware_curve {
+                loop_state.hardware_curve = program_hardware_curve(name, &fan);
+            }
+        }
+
+        let mut device_missing = false;
+        let mut observation = Observation::default();
+        let mut predicted = None;
+        // Temperature, if the fan has a sensor, and the power the fan asks for
+        let target = match &fan.follows {
+            Some(leader) => Ok(self.followed_power(name, &fan, leader, assignment.override_power).map(|power| (None, power))),
+            None => backend::temp_source(&fan).read_temp().map(|raw_temp| {
+                filter_temp(name, &fan, &mut loop_state.recent_temps, raw_temp).map(|temp| {
+                    let previous_temp = loop_state.last_temp.replace(temp);
+                    // A steady temperature keeps the curve it was on
+                    match previous_temp {
+                        Some(previous) if temp < previous => loop_state.cooling = true,
+                        Some(previous) if temp > previous => loop_state.cooling = false,
+                        _ => {}
+                    }
+                    let power = if self.check_critical(name, &fan, temp) {
+                        100
+                    } else {
+                        match assignment.override_power {
+                            Some(power) => power,
+                            None => {
+                                let curve_temp = predict_temp(name, &fan, &mut loop_state.trend_temps, temp);
+                                predicted = (curve_temp != temp).then_some(curve_temp);
+                                let curve_temp = dead_band_temp(&fan, &mut loop_state.operating_temp, curve_temp);
+                                let power = self.policy_power(name, &fan, &mut loop_state, curve_temp);
+                                dwell_power(name, &fan, &mut loop_state.dwell, power)
+                                    .max(load_power(name, &fan, &mut loop_state.cpu_samples))
+                                    .saturating_add(rise_boost(name, &fan, previous_temp, temp))
+                                    .min(100)
+                            }
+                        }
+                    };
+                    (Some(temp), power)
+                })
+            }),
+        };
+        match target {
+            Ok(Some((temp, demand))) => {
+                // An emergency overrides the noise limits
+                let cap = if assignment.power_cap_lifted || self.emergency() {
+                    None
+                } else {
+                    fan.max_power.into_iter().chain(assignment.night_cap).min()
+                };
+                self.update_fan_state(name, |state| {
+                    state.temperature = temp;
+                    state.predicted_temperature = predicted;
+                });
+                let wanted = self.group_power(name, &fan, demand);
+                let power = fan.limit_power(wanted, cap);
+                let sink = backend::pwm_sink(&fan);
+                // In an emergency the daemon takes the fan back from its hardware curve
+                let hardware = loop_state.hardware_curve && !self.emergency();
+                if hardware && sink.is_manual() {
+                    log!("Fan: {} - Handing the fan back to its hardware curve", name);
+                    set_pwm_enable_with_retry(&fan, false);
+                }
+                if assignment.hands_off || hardware {
+                    // The chip runs a hardware curve by itself, report what it set
+                    let power = if hardware { sink.read_power().unwrap_or(power) } else { power };
+                    log_cycle(name, temp, power, Some(if hardware { "hardware curve" } else { "left to the firmware" }));
+                    if let Some(temp) = temp {
+                        self.record_stats(name, temp, power);
+                    }
+                    observation = Observation { temp, sensor_missing: false, power: None, rpm: sink.read_rpm(), failing: None };
+                    self.update_fan_state(name, |state| {
+                        state.power = Some(power);
+                        state.owned = false;
+                        state.hardware_curve = hardware;
+                        state.pwm_enable = sink.read_enable();
+                        state.read_errors = 0;
+                    });
+                    self.check_alerts(name, &observation);
+                    return loop_state;
+                }
+                // Stopping goes below min_power, but never against an override or an emergency
+                let stopped = self.semi_passive_stopped(name) && assignment.override_power.is_none() && !self.emergency();
+                let power = if stopped { 0 } else { power };
+                log_cycle(name, temp, power, stopped.then_some("semi-passive"));
+                if let Some(temp) = temp {
+                    self.record_stats(name, temp, power);
+                }
+                if loop_state.hardware_curve && !sink.is_manual() {
+                    log!("Fan: {} - Emergency, taking the fan over from its hardware curve", name);
+                    set_pwm_enable_with_retry(&fan, true);
+                }
+                // Some EC firmwares and BIOSes take the fan back; put it in manual mode again if so
+                if !sink.is_manual() {
+                    log!("Fan: {} - Firmware reclaimed control, reasserting manual mode", name);
+                    self.update_fan_state(name, |state| state.control_reasserts += 1);
+                    loop_state.written_power = None;
+                    if sink.set_manual(true).is_err() {
+                        log!("Failed to enable PWM for {}", fan.pwm_input);
+                    }
+                }
+                // Read before changing the power, the fan has had a whole cycle to reach the previous one
+                let rpm = sink.read_rpm();
+                let failing = self.check_rpm(name, &fan, &mut loop_state, rpm);
+                // A fan starting from standstill may not spin up at its running power, kick it first
+                let starting = power > 0 && loop_state.last_power.map_or(rpm == Some(0), |last| last == 0);
+                if let Some(spin_up) = fan.spin_up.filter(|spin_up| starting && spin_up.power > power) {
+                    let secs = spin_up.secs.min(MAX_SPIN_UP_SECS);
+                    log!("Fan: {} - Starting from standstill at {}% for {}s", name, spin_up.power, secs);
+                    if sink.set_power(spin_up.power.min(100)).is_ok() {
+                        thread::sleep(Duration::from_secs(secs));
+                    }
+                }
+                // Within the dead band nothing changed, spare the device a write
+                let written = if fan.dead_band.is_some() && loop_state.written_power == Some(power) {
+                    Ok(())
+                } else {
+                    sink.set_power(power)
+                };
+                if written.is_ok() && loop_state.written_power != Some(power) {
+                    logging::event("write", json!({ "fan": name, "power": power }));
+                }
+                loop_state.written_power = written.as_ref().ok().map(|_| power);
+                if let Err(e) = &written {
+                    log!("Failed to set fan power to {}%", power);
+                    logging::event("write_error", json!({ "fan": name, "power": power, "error": e.to_string() }));
+                    self.update_fan_stats(name, |stats| stats.write_failures += 1);
+                    device_missing = e.kind() == ErrorKind::NotFound;
+                }
+                let pwm_enable = sink.read_enable();
+                let mut write_error = None;
+                self.update_fan_state(name, |state| {
+                    state.power = Some(power);
+                    state.capped = power < wanted && cap == Some(power);
+                    state.semi_passive = stopped;
+                    state.owned = true;
+                    state.hardware_curve = false;
+                    state.pwm_enable = pwm_enable;
+                    state.read_errors = 0;
+                    match written {
+                        Ok(()) => {
+                            state.last_write_error = None;
+                            state.write_errors = 0;
+                        }
+                        Err(e) => {
+                            if state.write_errors == 0 {
+                                write_error = Some(e.to_string());
+                            }
+                            state.last_write_error = Some(e.to_string());
+                            state.write_errors += 1;
+                        }
+                    }
+                });
+                if let Some(error) = write_error {
+                    self.report_fan_error(name, FanError::Write, &format!("Failed to set the power to {}%: {}", power, error));
+                }
+                loop_state.last_power = Some(power);
+                observation = Observation { temp, sensor_missing: false, power: Some(power), rpm, failing };
+            }
+            Ok(None) => {}
+            Err(e) => {
+                device_missing = e.kind() == ErrorKind::NotFound;
+                observation.sensor_missing = true;
+                let mut first_error = false;
+                self.update_fan_state(name, |state| {
+                    first_error = state.read_errors == 0;
+                    state.read_errors += 1;
+                });
+                self.update_fan_stats(name, |stats| stats.read_failures += 1);
+                if first_error {
+                    self.report_fan_error(name, FanError::Sensor, &format!("Failed to read the temperature: {}", e));
+                }
+            }
+        }
+        self.check_alerts(name, &observation);
+
+        if device_missing {
+            if let Some(resolved) = self.reresolve_fan(name, &fan) {
+                fan = resolved;
+            }
+        }
+        loop_state.controlled = Some(fan);
+        loop_state
+    }
+
+    // The hwmon device of a fan went away (module reload, renumbering): look it up
+    // again and take control of it at its new location
+    fn reresolve_fan(&self, name: &str, fan: &FanConfig) -> Option<FanConfig> {
+        let mut resolved = fan.clone();
+        if !resolve_fan_paths(&mut resolved) || hw_key(&resolved) == hw_key(fan) {
+            return None;
+        }
+        if let Err(e) = backend::check_writable(&resolved) {
+            log!("Fan: {} - Device moved to {}, which can't be controlled: {}", name, resolved.pwm_input, e);
+            return None;
+        }
+        log!("Fan: {} - Device moved, now using {} and {}", name, resolved.sensor_input, resolved.pwm_input);
+
+        self.send(ControlCommand::FanMoved {
+            name: name.to_string(),
+            from: Box::new(fan.clone()),
+            to: Box::new(resolved.clone()),
+        });
+        set_pwm_enable_with_retry(&resolved, true);
+        Some(resolved)
+    }
+
+    pub fn cleanup_fans(&self) {
+        let fans = self.read_config().controlled_fans();
+        for fan in fans.values() {
+            set_pwm_enable_with_retry(fan, false);
+        }
+    }
+
+
+    pub fn init_fans(&self) {
+        let fans = self.read_config().controlled_fans();
+        for (name, fan) in &fans {
+            init_fan(name, fan);
+        }
+    }
+
+    /// Pulse every fan with a tachometer between full and low power and check that its
+    /// speed follows, catching PWM outputs and tachometers that don't belong together.
+    /// Fans are tested together and handed back to the firmware afterwards, the control
+    /// loop takes them over again. Failures are flagged in the fan states and reported to
+    /// on_fan_error; returns the number of failed fans.
+    pub fn self_test(&self) -> usize {
+        if backend::is_observe_only() {
+            log!("Self-test skipped, fans aren't written in observe mode");
+            return 0;
+        }
+        let mut fans: Vec<(String, FanConfig)> = self.read_config().controlled_fans().into_iter()
+            .filter(|(name, fan)| {
+                let has_tach = backend::pwm_sink(fan).read_rpm().is_some();
+                if !has_tach {
+                    log!("Self-test: {} - No tachometer, skipped", name);
+                }
+                has_tach
+            })
+            .collect();
+        if fans.is_empty() {
+            return 0;
+        }
+        fans.sort_by(|(a, _), (b, _)| a.cmp(b));
+        log!("Self-test of {} fans, this takes {} seconds...", fans.len(), 2 * SELF_TEST_SETTLE.as_secs());
+
+        for (_, fan) in &fans {
+            set_pwm_enable_with_retry(fan, true);
+        }
+        let high_rpms = pulse_fans(&fans, 100);
+        let low_rpms = pulse_fans(&fans, SELF_TEST_LOW_POWER);
+
+        let mut failed = 0;
+        for (((name, fan), high), low) in fans.iter().zip(high_rpms).zip(low_rpms) {
+            let error = match (high, low) {
+                (None, _) | (_, None) => Some("the tachometer can't be read".to_string()),
+                (Some(0), _) => Some("the fan doesn't spin at 100%".to_string()),
+                // Less than 10% slower: the tachometer belongs to another fan, or the output drives another header
+                (Some(high), Some(low)) if low * 10 > high * 9 => Some(format!(
+                    "the speed doesn't follow the PWM output ({} RPM at 100%, {} RPM at {}%)", high, low, SELF_TEST_LOW_POWER,
+                )),
+                (Some(high), Some(low)) => {
+                    log!("Self-test: {} - Passed ({} RPM at 100%, {} RPM at {}%)", name, high, low, SELF_TEST_LOW_POWER);
+                    None
+                }
+            };
+            if let Some(error) = &error {
+                log!("Self-test: {} - FAILED: {}", name, error);
+                self.report_fan_error(name, FanError::SelfTest, &format!("Self-test failed: {}", error));
+                failed += 1;
+            }
+            self.update_fan_state(name, |state| state.self_test_error = error);
+            set_pwm_enable_with_retry(fan, false);
+        }
+        failed
+    }
+}
+
+// Long enough for a fan to settle at a new speed
+const SELF_TEST_SETTLE: Duration = Duration::from_secs(4);
+const SELF_TEST_LOW_POWER: u8 = 30;
+
+// Set every fan to the same power and read their speed once settled
+fn pulse_fans(fans: &[(String, FanConfig)], power: u8) -> Vec<Option<u32>> {
+    for (name, fan) in fans {
+        if let Err(e) = backend::pwm_sink(fan).set_power(power) {
+            log!("Self-test: {} - Failed to set power: {}", name, e);
+        }
+    }
+    thread::sleep(SELF_TEST_SETTLE);
+    fans.iter().map(|(_, fan)| backend::pwm_sink(fan).read_rpm()).collect()
+}
+
+// Temperature and power of a control cycle, with what kept the fan from following its
+// curve if anything
+fn log_cycle(name: &str, temp: Option<i32>, power: u8, note: Option<&str>) {
+    if logging::is_json() {
+        logging::event("cycle", json!({ "fan": name, "temp": temp, "power": power, "note": note }));
+        return;
+    }
+    let note = note.map(|note| format!(" ({})", note)).unwrap_or_default();
+    match temp {
+        Some(temp) => log!("Fan: {} - Temp: {}°C - Power: {}%{}", name, temp, power, note),
+        None => log!("Fan: {} - Power: {}%{}", name, power, note),
+    }
+}
+
+// Slow sensors (e.g. a drive enclosure updating every 30s) don't need to be polled as often as a CPU
+fn control_interval(fan: &FanConfig) -> Duration {
+    fan.interval_secs.map_or(CONTROL_INTERVAL, |secs| Duration::from_secs(secs.max(1)))
+}
+
+const DEFAULT_LOAD_WINDOW: Duration = Duration::from_secs(30);
+
+// Power asked for by the CPU utilization averaged over the fan's load window, so a
+// sustained load ramps the fan up before the temperature catches up
+fn load_power(name: &str, fan: &FanConfig, samples: &mut VecDeque<(Instant, CpuTimes)>) -> u8 {
+    if fan.load_steps.is_empty() {
+        return 0;
+    }
+    let times = match backend::read_cpu_times() {
+        Ok(times) => times,
+        Err(e) => {
+            log!("Fan: {} - Failed to read CPU load: {}", name, e);
+            return 0;
+        }
+    };
+    let now = Instant::now();
+    let window = fan.load_window_secs.map_or(DEFAULT_LOAD_WINDOW, Duration::from_secs);
+    // Keep the newest sample that is at least a window old as the reference
+    while samples.get(1).is_some_and(|(at, _)| now.duration_since(*at) >= window) {
+        samples.pop_front();
+    }
+    let load = samples.front().and_then(|(_, earlier)| times.load_since(earlier));
+    samples.push_back((now, times));
+
+    let Some(load) = load else {
+        return 0;
+    };
+    let steps: Vec<FanStep> = fan.load_steps.iter()
+        .map(|step| FanStep { temp: step.load as i32, power: step.power })
+        .collect();
+    let power = get_fan_power(&steps, load as i32);
+    log!("Fan: {} - CPU load: {}% - Load power: {}%", name, load, power);
+    power
+}
+
+// Temperature the recent trend leads to `cycles` cycles ahead, from the least-squares
+// slope of the last readings; the reading itself while steady or falling
+fn predict_temp(name: &str, fan: &FanConfig, trend_temps: &mut VecDeque<i32>, temp: i32) -> i32 {
+    let Some(predict) = fan.predict else {
+        return temp;
+    };
+    trend_temps.push_back(temp);
+    while trend_temps.len() > predict.samples.max(2) {
+        trend_temps.pop_front();
+    }
+    if trend_temps.len() < 2 {
+        return temp;
+    }
+    let count = trend_temps.len() as f64;
+    let mean_x = (count - 1.0) / 2.0;
+    let mean_y = trend_temps.iter().sum::<i32>() as f64 / count;
+    let (covariance, variance) = trend_temps.iter().enumerate().fold((0.0, 0.0), |(covariance, variance), (x, y)| {
+        let dx = x as f64 - mean_x;
+        (covariance + dx * (*y as f64 - mean_y), variance + dx * dx)
+    });
+    let slope = covariance / variance;
+    let rise = ((slope * predict.cycles as f64).round() as i32).clamp(0, predict.max_rise.max(0));
+    if rise > 0 {
+        log!("Fan: {} - Rising {:.1}°C a cycle, following the curve at {}°C", name, slope, temp + rise);
+    }
+    temp + rise
+}
+
+// Temperature to evaluate the curve at: the previous one while the reading stays within
+// dead_band of it, so jitter doesn't move the power
+fn dead_band_temp(fan: &FanConfig, operating_temp: &mut Option<i32>, temp: i32) -> i32 {
+    match (fan.dead_band, *operating_temp) {
+        (Some(band), Some(operating)) if temp.abs_diff(operating) <= band => operating,
+        _ => *operating_temp.insert(temp),
+    }
+}
+
+// Keep the power the curve changed to for min_dwell_secs, ignoring changes in between
+fn dwell_power(name: &str, fan: &FanConfig, dwell: &mut Option<(u8, Instant)>, power: u8) -> u8 {
+    let Some(min_dwell) = fan.min_dwell_secs.filter(|secs| *secs > 0).map(Duration::from_secs) else {
+        return power;
+    };
+    match *dwell {
+        Some((held, _)) if held == power => power,
+        Some((held, since)) if since.elapsed() < min_dwell => {
+            log!("Fan: {} - Holding {}% for {}s more instead of {}%", name, held, (min_dwell - since.elapsed()).as_secs_f64().ceil(), power);
+            held
+        }
+        _ => {
+            *dwell = Some((power, Instant::now()));
+            power
+        }
+    }
+}
+
+// Power of `name` when the cooling of every member at `highest` is shared out quietest
+// first: each tier of equally loud fans is filled up to 100% before the next one spins
+// up, and fans of a tier split what's left evenly
+fn noise_budget_power(name: &str, highest: u8, weights: &[(&str, f64)]) -> u8 {
+    let Some(&(_, weight)) = weights.iter().find(|(member, _)| *member == name) else {
+        return highest;
+    };
+    let mut remaining = highest as f64 * weights.len() as f64;
+    let mut tiers: Vec<f64> = weights.iter().map(|(_, weight)| *weight).collect();
+    tiers.sort_by(f64::total_cmp);
+    tiers.dedup();
+    for tier in tiers {
+        let count = weights.iter().filter(|(_, weight)| *weight == tier).count() as f64;
+        let share = remaining.min(100.0 * count);
+        if tier == weight {
+            return (share / count).round() as u8;
+        }
+        remaining -= share;
+    }
+    highest
+}
+
+// Power added while the temperature rises faster than the fan's rise_boost rate
+fn rise_boost(name: &str, fan: &FanConfig, previous_temp: Option<i32>, temp: i32) -> u8 {
+    let (Some(boost), Some(previous_temp)) = (fan.rise_boost, previous_temp) else {
+        return 0;
+    };
+    if temp - previous_temp < boost.rate.max(1) {
+        return 0;
+    }
+    log!("Fan: {} - Temperature rose {}°C in a cycle, boosting by {}%", name, temp - previous_temp, boost.power);
+    boost.power
+}
+
+const MEDIAN_WINDOW: usize = 3;
+
+// Drop implausible readings (e.g. 255°C or -273°C from a glitching sensor) and
+// smooth the rest with a median over the last few readings, so a single spike
+// can't slam the fan to full speed
+fn filter_temp(name: &str, fan: &FanConfig, recent_temps: &mut VecDeque<i32>, temp: i32) -> Option<i32> {
+    let range = fan.valid_temp_range.unwrap_or_default();
+    if temp < range.min || temp > range.max {
+        log!("Fan: {} - Ignoring implausible reading of {}°C (valid range {}..={})", name, temp, range.min, range.max);
+        return None;
+    }
+
+    recent_temps.push_back(temp);
+    if recent_temps.len() > MEDIAN_WINDOW {
+        recent_temps.pop_front();
+    }
+    let mut sorted: Vec<i32> = recent_temps.iter().copied().collect();
+    sorted.sort();
+    // With an even count this picks the upper value, erring on the side of cooling
+    Some(sorted[sorted.len() / 2])
+}
+
+fn init_fan(name: &str, fan: &FanConfig) {
+    log!("Fan: {}", name);
+    log!("  Sensor input: {}", fan.sensor_input);
+    log!("  PWM input: {}", fan.pwm_input);
+    log!("  Steps: {:?}", fan.steps);
+    if backend::is_observe_only() {
+        return;
+    }
+
+    if let Some(mode) = fan.pwm_mode {
+        log!("  PWM mode: {:?}", mode);
+        if let Err(e) = backend::pwm_sink(fan).set_mode(mode) {
+            log!("Failed to set PWM mode of {}: {}", name, e);
+        }
+    }
+
+    set_pwm_enable_with_retry(fan, true);
+}
+
+// Read guard of a lock, unless a thread holds it for writing
+fn try_read_lock<T>(lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+    match lock.try_read() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+// Hand outputs taken over for a configuration that wasn't applied back to the firmware,
+// unless the running configuration controls them
+fn hand_back(taken_over: &[&FanConfig], running: &HashMap<String, FanConfig>) {
+    for fan in taken_over {
+        let controlled = running.values().any(|running| running.pwm_input == fan.pwm_input);
+        if !controlled && backend::pwm_sink(fan).set_manual(false).is_err() {
+            log!("Failed to hand {} back to the firmware", fan.pwm_input);
+        }
+    }
+}
+
+// Hand every fan back to automatic mode, or run it at full speed if that fails
+fn restore_safe_state(config: &RwLock<Config>) {
+    // Nothing was ever written in observe mode
+    if backend::is_observe_only() {
+        return;
+    }
+    // The panicking thread may be holding the lock, don't wait on it
+    let config_guard = match config.try_read() {
+        Ok(guard) => guard,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            log!("Configuration locked, unable to return fans to a safe state");
+            return;
+        }
+    };
+    // Outputs never found were never written either
+    for (name, fan) in config_guard.controlled_fans().iter().filter(|(_, fan)| fan.pwm_resolved()) {
+        let sink = backend::pwm_sink(fan);
+        if sink.set_manual(false).is_err() && sink.set_power(100).is_err() {
+            log!("Failed to return fan {} to a safe state", name);
+        }
+    }
+}
+
+/// Detects system suspend by comparing CLOCK_BOOTTIME, which keeps counting while
+/// suspended, with CLOCK_MONOTONIC, which doesn't
+struct SuspendDetector {
+    last_gap: Duration,
+}
+
+impl SuspendDetector {
+    // Small drifts between the two clocks are not a suspend
+    const THRESHOLD: Duration = Duration::from_secs(2);
+
+    fn new() -> Self {
+        Self { last_gap: Self::clock_gap() }
+    }
+
+    fn read_clock(clock: libc::clockid_t) -> Duration {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: ts is a valid timespec for clock_gettime to fill in
+        unsafe { libc::clock_gettime(clock, &mut ts) };
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    fn clock_gap() -> Duration {
+        Self::read_clock(libc::CLOCK_BOOTTIME).saturating_sub(Self::read_clock(libc::CLOCK_MONOTONIC))
+    }
+
+    /// Returns how long the system was suspended since the last check, if it was
+    fn check(&mut self) -> Option<Duration> {
+        let gap = Self::clock_gap();
+        let suspended = gap.saturating_sub(self.last_gap);
+        self.last_gap = gap;
+        (suspended > Self::THRESHOLD).then_some(suspended)
+    }
+}
+
+type HwKey = (String, String, String, String, Option<PwmMode>, Option<Vec<(i32, u8)>>);
+
+// Hardware mapping of a single fan, and the header mode and hardware curve only applied
+// when taking control
+fn hw_key(fan: &FanConfig) -> HwKey {
+    (
+        fan.sensor_name.clone(),
+        fan.sensor_input.clone(),
+        fan.pwm_name.clone(),
+        fan.pwm_input.clone(),
+        fan.pwm_mode,
+        fan.hardware_curve.then(|| hardware_curve_points(fan)),
+    )
+}
+
+// Steps of a hardware curve as rising (°C, raw 0-255) auto points, within the power limits
+fn hardware_curve_points(fan: &FanConfig) -> Vec<(i32, u8)> {
+    let mut steps = fan.steps.clone();
+    steps.sort_by_key(|step| step.temp);
+    steps.iter()
+        .map(|step| (step.temp, (fan.limit_power(step.power, fan.max_power) as u32 * 255 / 100) as u8))
+        .collect()
+}
+
+// Program the fan's curve into the chip and hand the output over to it; false if the
+// chip can't take it, and the daemon keeps controlling the fan
+fn program_hardware_curve(name: &str, fan: &FanConfig) -> bool {
+    if fan.backend != FanBackend::Hwmon || fan.follows.is_some() || !fan.rpm_steps.is_empty() {
+        log!("Fan: {} - Only hwmon fans following their own steps can have a hardware curve", name);
+        return false;
+    }
+    // The chip reads its own sensors, by tempN_input index
+    let temp_index = (fan.sensor.is_none() && fan.sensor_name == fan.pwm_name)
+        .then(|| sysfs_file_name(&fan.sensor_input).strip_prefix("temp")?.strip_suffix("_input")?.parse().ok())
+        .flatten();
+    if temp_index.is_none() {
+        log!("Fan: {} - The sensor isn't one of the chip's, the hardware curve follows the source set in {}_temp_sel", name, fan.pwm_input);
+    }
+    let pwm_input = Path::new(&fan.pwm_input);
+    let result = backend::write_hwmon_auto_points(pwm_input, &hardware_curve_points(fan), temp_index)
+        .and_then(|_| backend::pwm_sink(fan).set_manual(false));
+    match result {
+        Ok(()) => {
+            log!("Fan: {} - Hardware curve programmed, the chip drives the fan", name);
+            true
+        }
+        Err(e) => {
+            log!("Fan: {} - Failed to program the hardware curve, controlling the fan instead: {}", name, e);
+            false
+        }
+    }
+}
+
+/// Which of several hwmon devices with the same name to use (two identical GPUs or NVMe
+/// drives), found through the `device` symlink of the hwmon directory
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceMatch {
+    /// PCI address or platform device, e.g. "0000:03:00.0" or "nct6775.656": one or more
+    /// trailing components of the path the device or one of its parents is at
+    Path(String),
+    /// Serial number of the device, e.g. of an NVMe drive
+    Serial(String),
+}
+
+impl DeviceMatch {
+    /// Whether the device of the hwmon directory `hwmon_dir` is this one
+    pub fn matches(&self, hwmon_dir: &Path) -> bool {
+        match self {
+            DeviceMatch::Path(wanted) => hwmon_device_path(hwmon_dir)
+                .is_some_and(|path| path.ancestors().any(|ancestor| ancestor.ends_with(wanted))),
+            DeviceMatch::Serial(wanted) => hwmon_device_serial(hwmon_dir).is_some_and(|serial| &serial == wanted),
+        }
+    }
+}
+
+/// Path the hwmon directory's device is at in the device tree, e.g.
+/// /sys/devices/pci0000:00/0000:00:01.1/0000:03:00.0
+pub fn hwmon_device_path(hwmon_dir: &Path) -> Option<PathBuf> {
+    fs::canonicalize(hwmon_dir.join("device")).ok()
+}
+
+/// Serial number of the hwmon directory's device, which NVMe drives (and a few
+/// others) report in `serial`, on the device itself or its parent
+pub fn hwmon_device_serial(hwmon_dir: &Path) -> Option<String> {
+    ["device/serial", "device/device/serial"].iter()
+        .filter_map(|file| fs::read_to_string(hwmon_dir.join(file)).ok())
+        .map(|serial| serial.trim().to_string())
+        .find(|serial| !serial.is_empty())
+}
+
+/// Directory of the device named `name` among the `name` files matching `pattern`,
+/// the one `device` designates when several have this name
+pub fn find_sysfs_path(name: &str, pattern: &str, device: Option<&DeviceMatch>) -> Option<PathBuf> {
+    log!("Searching for {} with pattern: {}", name, pattern);
+
+    for entry in glob(pattern).unwrap() {
+        if let Ok(path) = entry {
+            if let Ok(content) = fs::read_to_string(&path) {
+                let trimmed_content = content.trim();
+                let parent = path.parent().map(|p| p.to_path_buf());
+                let device_matches = || device.is_none_or(|device| parent.as_deref().is_some_and(|dir| device.matches(dir)));
+                if trimmed_content == name && device_matches() {
+                    return parent;
+                }
+            } else {
+                log!("Failed to read content from: {:?}", path);
+            }
+        } else {
+            log!("Failed to process glob entry");
+        }
+    }
+    None
+}
+
+pub fn find_pwmchip_path(name: &str) -> Option<PathBuf> {
+    let pwm_root = sysfs_class("pwm");
+    let path = pwm_root.join(name);
+    if path.is_dir() {
+        Some(path)
+    } else {
+        log!("PWM chip {} not found in {}", name, pwm_root.display());
+        None
+    }
+}
+
+// Glob matching the name file of every hwmon device
+fn hwmon_name_pattern() -> String {
+    format!("{}/hwmon*/name", sysfs_class("hwmon").display())
+}
+
+// File name part of a (possibly already resolved) sysfs path
+fn sysfs_file_name(path: &str) -> String {
+    Path::new(path).file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// File of a sensor_input naming a file of the device (temp1_input) or the label of one
+// of its tempN_input files (Tctl), with the label to keep when it was one. An already
+// resolved label is looked up again, as kernels may number the sensors differently.
+fn resolve_sensor_input(hwmon_dir: &Path, sensor_input: &str, label: Option<String>) -> (String, Option<String>) {
+    let input = label.unwrap_or_else(|| sysfs_file_name(sensor_input));
+    if hwmon_dir.join(&input).exists() {
+        return (input, None);
+    }
+    match find_temp_input_by_label(hwmon_dir, &input) {
+        Some(file) => (file, Some(input)),
+        None => {
+            log!("{}: no such file or temperature label in {}", input, hwmon_dir.display());
+            (input, None)
+        }
+    }
+}
+
+/// tempN_input file of a hwmon device whose tempN_label is `label`
+pub fn find_temp_input_by_label(hwmon_dir: &Path, label: &str) -> Option<String> {
+    let mut inputs: Vec<String> = fs::read_dir(hwmon_dir).ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            let index = file.strip_prefix("temp")?.strip_suffix("_label")?.to_string();
+            let content = fs::read_to_string(entry.path()).ok()?;
+            (content.trim() == label).then(|| format!("temp{}_input", index))
+        })
+        .collect();
+    // Lowest channel first should two sensors share a label
+    inputs.sort_by_key(|input| channel_order(input));
+    inputs.into_iter().next()
+}
+
+/// Resolve sensor_input and pwm_input of a fan to full sysfs paths by looking up
+/// their devices by name. Already resolved paths are looked up again, so this also
+/// recovers from hwmonN renumbering. Returns false if a device wasn't found.
+pub fn resolve_fan_paths(fan: &mut FanConfig) -> bool {
+    let sensor_path = if fan.reads_hwmon_sensor() {
+        find_sysfs_path(&fan.sensor_name, &hwmon_name_pattern(), fan.sensor_device.as_ref())
+    } else {
+        None
+    };
+    // sysctl nodes are named in full, there is nothing to look up
+    let pwm_path = match fan.backend {
+        backend if backend.is_sysctl() => None,
+        FanBackend::Pwmchip => Some(find_pwmchip_path(&fan.pwm_name)),
+        _ => Some(find_sysfs_path(&fan.pwm_name, &hwmon_name_pattern(), fan.pwm_device.as_ref())),
+    };
+
+    if fan.reads_hwmon_sensor() && sensor_path.is_none() {
+        log!("Sensor path not found");
+        return false;
+    }
+    if let Some(None) = pwm_path {
+        log!("PWM path not found");
+        return false;
+    }
+
+    if let Some(source) = &mut fan.sensor {
+        if !source.resolve_paths() {
+            log!("Sensor path not found");
+            return false;
+        }
+    }
+    if let Some(ambient) = &mut fan.ambient {
+        if !ambient.resolve_path() {
+            log!("Ambient sensor path not found");
+            return false;
+        }
+    }
+
+    if let Some(sensor_path) = sensor_path {
+        let (file, label) = resolve_sensor_input(&sensor_path, &fan.sensor_input, fan.sensor_label.take());
+        fan.sensor_input = sensor_path.join(file).to_str().unwrap().to_string();
+        fan.sensor_label = label;
+    }
+    if let Some(Some(pwm_path)) = pwm_path {
+        fan.pwm_input = pwm_path.join(sysfs_file_name(&fan.pwm_input)).to_str().unwrap().to_string();
+    }
+    true
+}
+
+// Resolve the fans whose paths are still bare file names
+fn resolve_unresolved_paths(config: &mut Config) {
+    update_fan_paths(config, |fan| {
+        let sensor_resolved = match &fan.sensor {
+            Some(source) => source.is_resolved(),
+            None => fan.follows.is_some() || Path::new(&fan.sensor_input).is_absolute(),
+        };
+        let ambient_resolved = fan.ambient.as_ref().is_none_or(SensorConfig::is_resolved);
+        if !sensor_resolved || !ambient_resolved || !fan.pwm_resolved() {
+            resolve_fan_paths(fan);
+        }
+    });
+    for source in config.sensors.values_mut() {
+        if !source.is_resolved() {
+            source.resolve_paths();
+        }
+    }
+}
+
+pub fn load_config() -> Config {
+    let config_data = fs::read_to_string(CONFIG_PATH).expect("Failed to read config");
+    serde_json::from_str(&config_data).expect("Invalid config")
+}
+
+pub fn create_config() -> Config {
+    let mut config = load_config();
+
+    update_fan_paths(&mut config, |fan| {
+        resolve_fan_paths(fan);
+    });
+    for (name, source) in &mut config.sensors {
+        if !source.resolve_paths() {
+            log!("Sensor path of virtual sensor {} not found", name);
+        }
+    }
+    for curve in config.missing_curves() {
+        log!("Curve '{}' is not defined, fans using it run at full speed", curve);
+    }
+    for sensor in config.missing_sensors() {
+        log!("Virtual sensor '{}' is not defined, fans using it can't be controlled", sensor);
+    }
+
+    config
+}
+
+// Helper to strip sysfs directory from sensor_input and pwm_input for saving
+fn config_for_save(config: &Config) -> Config {
+    let mut new_config = config.clone();
+    update_fan_paths(&mut new_config, |fan| {
+        if let Some(label) = fan.sensor_label.take() {
+            fan.sensor_input = label;
+        } else if let Some(sensor_file) = Path::new(&fan.sensor_input).file_name() {
+            fan.sensor_input = sensor_file.to_string_lossy().to_string();
+        }
+        if let Some(pwm_file) = Path::new(&fan.pwm_input).file_name() {
+            fan.pwm_input = pwm_file.to_string_lossy().to_string();
+        }
+        if let Some(source) = &mut fan.sensor {
+            source.strip_paths();
+        }
+        if let Some(ambient) = &mut fan.ambient {
+            ambient.strip_path();
+        }
+    });
+    new_config.sensors.values_mut().for_each(SensorSource::strip_paths);
+    new_config
+}
+
+pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let config_to_save = config_for_save(config);
+    let config_str = serde_json::to_string_pretty(&config_to_save)?;
+    fs::write(CONFIG_PATH, config_str)?;
+    Ok(())
+}
+
+/// JSON Schema of the configuration file, generated from `Config` so it can't drift
+/// from what the daemon accepts. Unknown settings are rejected to catch typos the
+/// daemon would silently ignore, and `$schema` is allowed for editors.
+pub fn config_schema() -> serde_json::Value {
+    let generator = SchemaSettings::draft2019_09()
+        .with_visitor(RejectUnknownProperties)
+        .into_generator();
+    let mut schema = generator.into_root_schema_for::<Config>();
+    schema.schema.metadata().title = Some("coold-rs configuration".to_string());
+    schema.schema.object().properties.insert("$schema".to_string(), SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some("Schema the file is validated against, ignored by the daemon".to_string()),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    }.into());
+    serde_json::to_value(schema).unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+struct RejectUnknownProperties;
+
+impl Visitor for RejectUnknownProperties {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        // Maps (fans and curves by name) have no properties and keep their values' schema
+        if let Some(object) = schema.object.as_mut().filter(|object| !object.properties.is_empty()) {
+            object.additional_properties.get_or_insert_with(|| Box::new(Schema::Bool(false)));
+        }
+        visit_schema_object(self, schema);
+    }
+}
+
+// Speed the rpm_steps ask for at `temp`, interpolated between the steps
+fn target_rpm(steps: &[RpmStep], temp: i32) -> u32 {
+    let mut sorted: Vec<_> = steps.iter().collect();
+    sorted.sort_by_key(|step| step.temp);
+    let (Some(first), Some(last)) = (sorted.first(), sorted.last()) else {
+        return 0;
+    };
+
+    if temp <= first.temp {
+        return first.rpm;
+    }
+    if temp >= last.temp {
+        return last.rpm;
+    }
+    sorted.windows(2)
+        .find(|pair| temp >= pair[0].temp && temp <= pair[1].temp)
+        .map_or(last.rpm, |pair| {
+            let (low, high) = (pair[0], pair[1]);
+            let offset = (temp - low.temp) as f32 / (high.temp - low.temp) as f32;
+            (low.rpm as f32 + offset * (high.rpm as f32 - low.rpm as f32)).round() as u32
+        })
+}
+
+// Power rpm_curve measured the fan reaching `rpm` at, the reverse of expected_rpm
+fn power_for_rpm(curve: &[RpmPoint], rpm: u32) -> Option<u8> {
+    let mut sorted: Vec<_> = curve.iter().collect();
+    sorted.sort_by_key(|point| point.rpm);
+    let first = sorted.first()?;
+    let last = sorted.last()?;
+
+    if rpm <= first.rpm {
+        return Some(first.power);
+    }
+    if rpm >= last.rpm {
+        return Some(last.power);
+    }
+    sorted.windows(2)
+        .find(|pair| rpm >= pair[0].rpm && rpm <= pair[1].rpm)
+        .map(|pair| {
+            let (low, high) = (pair[0], pair[1]);
+            let offset = (rpm - low.rpm) as f32 / (high.rpm - low.rpm) as f32;
+            (low.power as f32 + offset * (high.power as f32 - low.power as f32)).round() as u8
+        })
+}
+
+// Speed expected at `power`, interpolated between the measured points
+fn expected_rpm(curve: &[RpmPoint], power: u8) -> Option<u32> {
+    let mut sorted: Vec<_> = curve.iter().collect();
+    sorted.sort_by_key(|point| point.power);
+    let first = sorted.first()?;
+    let last = sorted.last()?;
+
+    if power <= first.power {
+        return Some(first.rpm);
+    }
+    if power >= last.power {
+        return Some(last.rpm);
+    }
+    sorted.windows(2)
+        .find(|pair| power >= pair[0].power && power <= pair[1].power)
+        .map(|pair| {
+            let (low, high) = (pair[0], pair[1]);
+            let offset = (power - low.power) as f32 / (high.power - low.power) as f32;
+            (low.rpm as f32 + offset * (high.rpm as f32 - low.rpm as f32)).round() as u32
+        })
+}
+
+fn get_fan_power(steps: &[FanStep], temp: i32) -> u8 {
+    if steps.is_empty() {
+        return 0;
+    }
+
+    // Sort steps by temperature to ensure proper curve calculation
+    let mut sorted_steps: Vec<_> = steps.iter().collect();
+    sorted_steps.sort_by_key(|step| step.temp);
+
+    // If temperature is below the lowest step, return the lowest power
+    if temp <= sorted_steps[0].temp {
+        return sorted_steps[0].power;
+    }
+
+    // If temperature is above the highest step, return the highest power
+    if temp >= sorted_steps.last().unwrap().temp {
+        return sorted_steps.last().unwrap().power;
+    }
+
+    // Find the two steps to interpolate between
+    for i in 0..sorted_steps.len() - 1 {
+        let current_step = &sorted_steps[i];
+        let next_step = &sorted_steps[i + 1];
+
+        if temp >= current_step.temp && temp <= next_step.temp {
+            // Linear interpolation between the two steps
+            let temp_diff = next_step.temp - current_step.temp;
+            let power_diff = next_step.power as i32 - current_step.power as i32;
+            let temp_offset = temp - current_step.temp;
+            
+            let interpolated_power = current_step.power as f32 + 
+                (power_diff as f32 * temp_offset as f32 / temp_diff as f32);
+            
+            return interpolated_power.round() as u8;
+        }
+    }
+
+    // Fallback: return the power of the closest step
+    let closest_step = sorted_steps.iter()
+        .min_by_key(|step| (step.temp - temp).abs())
+        .unwrap();
+    
+    closest_step.power
+}
+
+fn set_pwm_enable_with_retry(fan: &FanConfig, enable: bool) {
+    // Outputs are left alone in observe mode, and outputs never found can't be written
+    if backend::is_observe_only() || !fan.pwm_resolved() {
+        return;
+    }
+    let sink = backend::pwm_sink(fan);
+    if !sink.needs_retry() {
+        if sink.set_manual(enable).is_err() {
+            log!("Failed to {} PWM for {}", if enable { "enable" } else { "disable" }, fan.pwm_input);
+        }
+        return;
+    }
+
+    for _ in 0..10 {
+        if sink.is_manual() == enable {
+            break;
+        }
+        if sink.set_manual(enable).is_err() {
+            log!("Failed to {} PWM for {}", if enable { "enable" } else { "disable" }, fan.pwm_input);
+        }
+        thread::sleep(Duration::from_millis(300));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonSensorInfo {
+    pub input: String,
+    pub label: Option<String>,
+    /// Current temperature in °C, None if it can't be read
+    #[serde(default)]
+    pub temperature: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonDeviceInfo {
+    pub hwmon_path: String,
+    pub name: String,
+    /// Where the device is in the device tree, for pwm_device/sensor_device `path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_path: Option<String>,
+    /// Serial number of the device, for pwm_device/sensor_device `serial`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    pub sensors: Vec<HwmonSensorInfo>,
+    pub pwms: Vec<String>,
+    /// Current drive mode of the PWM outputs that report one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pwm_modes: HashMap<String, PwmMode>,
+    /// Current raw 0-255 duty cycle of the PWM outputs that can be read
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pwm_values: HashMap<String, u8>,
+    /// Tachometers with their current speed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fans: Vec<HwmonFanInput>,
+}
+
+impl HwmonDeviceInfo {
+    /// Whether the device goes by `name` (e.g. nct6798) or is in directory `name` (e.g. hwmon2)
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name == name || Path::new(&self.hwmon_path).file_name().is_some_and(|dir| dir == name)
+    }
+}
+
+/// Temperature input of a hwmon device with its current value
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonSensorReading {
+    /// hwmon device name, as used for `sensor_name`
+    pub name: String,
+    pub hwmon_path: String,
+    pub input: String,
+    pub label: Option<String>,
+    /// Current temperature in °C
+    pub temperature: i32,
+}
+
+/// Read every temperature input of every hwmon device, skipping the ones that can't be read
+pub fn read_hwmon_sensors() -> Vec<HwmonSensorReading> {
+    enumerate_hwmon_devices().into_iter()
+        .flat_map(|device| {
+            let hwmon_dir = PathBuf::from(&device.hwmon_path);
+            device.sensors.into_iter().filter_map(move |sensor| {
+                let temperature = backend::read_hwmon_temp(&hwmon_dir.join(&sensor.input)).ok()?;
+                Some(HwmonSensorReading {
+                    name: device.name.clone(),
+                    hwmon_path: device.hwmon_path.clone(),
+                    input: sensor.input,
+                    label: sensor.label,
+                    temperature,
+                })
+            })
+        })
+        .collect()
+}
+
+/// PWM output of a hwmon device with its current raw state
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonPwmInfo {
+    /// hwmon device name, as used for `pwm_name`
+    pub name: String,
+    pub hwmon_path: String,
+    pub pwm: String,
+    /// Raw 0-255 duty cycle
+    pub value: Option<u8>,
+    /// Raw pwmN_enable value
+    pub enable: Option<u8>,
+    pub mode: Option<PwmMode>,
+    /// Configured fan driving this output
+    pub fan: Option<String>,
+    /// The daemon is currently setting this output's speed
+    pub controlled: bool,
+}
+
+/// Tachometer input of a hwmon device
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonFanInput {
+    pub input: String,
+    pub label: Option<String>,
+    pub rpm: Option<u32>,
+}
+
+/// Everything about a single hwmon device, with live readings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwmonDeviceDetail {
+    pub name: String,
+    pub hwmon_path: String,
+    /// Kernel driver bound to the underlying device, when it has one
+    pub driver: Option<String>,
+    pub sensors: Vec<HwmonSensorReading>,
+    pub pwms: Vec<HwmonPwmInfo>,
+    pub fans: Vec<HwmonFanInput>,
+}
+
+// Sort key of a hwmon attribute by channel number, so the fan10_input of chips with
+// many channels (max31790) comes after fan9_input rather than fan1_input
+fn channel_order(file_name: &str) -> (String, u32, String) {
+    let index_start = file_name.find(|c: char| c.is_ascii_digit()).unwrap_or(file_name.len());
+    let (prefix, rest) = file_name.split_at(index_start);
+    let index_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    (prefix.to_string(), rest[..index_end].parse().unwrap_or(0), rest[index_end..].to_string())
+}
+
+/// fanN_input tachometers of a hwmon directory, with their labels and current speed
+pub fn read_hwmon_fan_inputs(hwmon_dir: &Path) -> Vec<HwmonFanInput> {
+    let mut fans: Vec<HwmonFanInput> = fs::read_dir(hwmon_dir).into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let input = entry.file_name().to_string_lossy().to_string();
+            let index = input.strip_prefix("fan")?.strip_suffix("_input")?;
+            let label = fs::read_to_string(hwmon_dir.join(format!("fan{}_label", index))).ok()
+                .map(|label| label.trim().to_string())
+                .filter(|label| !label.is_empty());
+            let rpm = fs::read_to_string(entry.path()).ok().and_then(|rpm| rpm.trim().parse().ok());
+            Some(HwmonFanInput { input, label, rpm })
+        })
+        .collect();
+    fans.sort_by_key(|fan| channel_order(&fan.input));
+    fans
+}
+
+/// Enumerate all available hwmon devices, listing their name, sensor inputs (with labels),
+/// PWM outputs and tachometers, with their current readings
+pub fn enumerate_hwmon_devices() -> Vec<HwmonDeviceInfo> {
+    let mut devices = Vec::new();
+    for name_path in glob(&hwmon_name_pattern()).unwrap().flatten() {
+        let hwmon_dir = name_path.parent().unwrap();
+        let name = fs::read_to_string(&name_path).unwrap_or_else(|_| "unknown".to_string()).trim().to_string();
+        // Find all temp*_input and pwm* files in this hwmon directory
+        let mut sensors = Vec::new();
+        let mut pwms = Vec::new();
+        if let Ok(entries) = fs::read_dir(hwmon_dir) {
+            for entry in entries.flatten() {
+                let fname = entry.file_name();
+                let fname = fname.to_string_lossy();
+                if fname.starts_with("temp") && fname.ends_with("_input") {
+                    // Try to get label: tempN_label for tempN_input
+                    let label_file = hwmon_dir.join(fname.replace("_input", "_label"));
+                    let label = fs::read_to_string(&label_file).ok().map(|s| s.trim().to_string());
+                    sensors.push(HwmonSensorInfo {
+                        input: fname.to_string(),
+                        label: label.filter(|l| !l.is_empty()),
+                        temperature: backend::read_hwmon_temp(&entry.path()).ok(),
+                    });
+                } else if fname.starts_with("pwm") && fname.len() > 3 && fname[3..].chars().all(|c| c.is_ascii_digit()) {
+                    pwms.push(fname.to_string());
+                }
+            }
+        }
+        sensors.sort_by_key(|sensor| channel_order(&sensor.input));
+        pwms.sort_by_key(|pwm| channel_order(pwm));
+        let pwm_modes = pwms.iter()
+            .filter_map(|pwm| Some((pwm.clone(), backend::read_hwmon_pwm_mode(&hwmon_dir.join(pwm))?)))
+            .collect();
+        let pwm_values = pwms.iter()
+            .filter_map(|pwm| Some((pwm.clone(), backend::read_hwmon_pwm_value(&hwmon_dir.join(pwm))?)))
+            .collect();
+        devices.push(HwmonDeviceInfo {
+            hwmon_path: hwmon_dir.to_string_lossy().to_string(),
+            name,
+            device_path: hwmon_device_path(hwmon_dir).map(|path| path.to_string_lossy().to_string()),
+            serial: hwmon_device_serial(hwmon_dir),
+            sensors,
+            pwms,
+            pwm_modes,
+            pwm_values,
+            fans: read_hwmon_fan_inputs(hwmon_dir),
+        });
+    }
+    devices
+} 
\ No newline at end of file
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use crate::backend;
+use crate::daemon::{enumerate_hwmon_devices, read_hwmon_fan_inputs, FanConfig, FanController, RpmPoint};
+use crate::templates;
+use crate::log;
+
+/// Seconds fans are given to reach their new speed, by default
+pub const DEFAULT_SETTLE_SECS: u64 = 5;
+
+// Powers the outputs are switched between; low enough to be told apart, high enough
+// for fans to keep spinning
+const HIGH_POWER: u8 = 100;
+const LOW_POWER: u8 = 40;
+
+// hwmon pwmN_enable value for manual control
+const MANUAL_ENABLE: u8 = 1;
+
+// A tachometer follows an output when it slows down by at least this much
+const MIN_DROP_RPM: u32 = 150;
+const MIN_DROP_PCT: u32 = 25;
+
+/// What detection found out about a PWM output
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DetectedOutput {
+    pub pwm_name: String,
+    pub pwm_input: String,
+    pub hwmon_path: String,
+    /// Tachometer that followed the output, as `<device>/fanN_input`
+    pub fan_input: Option<String>,
+    pub rpm_high: Option<u32>,
+    pub rpm_low: Option<u32>,
+    /// Fan entry for this output, to complete with a sensor
+    pub suggested: Option<FanConfig>,
+    /// Why the output was not tested
+    pub skipped: Option<String>,
+}
+
+struct Output {
+    pwm_name: String,
+    pwm_input: PathBuf,
+}
+
+// Speed of every tachometer on every device, by "<device>/fanN_input"
+fn read_tachs(devices: &[(String, PathBuf)]) -> HashMap<String, u32> {
+    devices.iter()
+        .flat_map(|(name, hwmon_dir)| {
+            read_hwmon_fan_inputs(hwmon_dir).into_iter()
+                .filter_map(move |fan| Some((format!("{}/{}", name, fan.input), fan.rpm?)))
+        })
+        .collect()
+}
+
+fn raw_value(power: u8) -> u8 {
+    (power as u32 * 255 / 100) as u8
+}
+
+/// Vary each PWM output in turn while watching every tachometer, to find out which
+/// physical fan each output drives. Outputs controlled by the daemon are left alone;
+/// the others are put back to their previous value and mode afterwards.
+pub fn detect_fans(controller: &FanController, settle: Duration) -> Vec<DetectedOutput> {
+    let devices: Vec<(String, PathBuf)> = enumerate_hwmon_devices().into_iter()
+        .map(|device| (device.name, PathBuf::from(device.hwmon_path)))
+        .collect();
+
+    let mut detected = Vec::new();
+    for pwm in controller.get_pwm_outputs() {
+        let output = Output {
+            pwm_input: Path::new(&pwm.hwmon_path).join(&pwm.pwm),
+            pwm_name: pwm.name.clone(),
+        };
+        let mut result = DetectedOutput {
+            pwm_name: pwm.name,
+            pwm_input: pwm.pwm,
+            hwmon_path: pwm.hwmon_path,
+            fan_input: None,
+            rpm_high: None,
+            rpm_low: None,
+            suggested: None,
+            skipped: None,
+        };
+
+        if pwm.controlled {
+            result.skipped = Some(format!("Controlled as {}", pwm.fan.unwrap_or_default()));
+        } else {
+            log!("Detect: varying {}/{}", output.pwm_name, result.pwm_input);
+            match vary_output(&output, &devices, settle) {
+                Ok(Some((fan_input, rpm_high, rpm_low))) => {
+                    result.suggested = Some(suggested_fan(&output, &result.pwm_input, rpm_high, rpm_low));
+                    result.fan_input = Some(fan_input);
+                    result.rpm_high = Some(rpm_high);
+                    result.rpm_low = Some(rpm_low);
+                }
+                Ok(None) => {}
+                Err(e) => result.skipped = Some(format!("Failed to drive the output: {}", e)),
+            }
+        }
+        detected.push(result);
+    }
+    detected
+}
+
+// Run the output high then low, and pick the tachometer that slowed down the most
+fn vary_output(output: &Output, devices: &[(String, PathBuf)], settle: Duration) -> io::Result<Option<(String, u32, u32)>> {
+    let original_value = backend::read_hwmon_pwm_value(&output.pwm_input);
+    let original_enable = backend::read_hwmon_pwm_enable(&output.pwm_input);
+
+    let measured = (|| {
+        // Outputs without pwmN_enable, like those of the emc2305, are always under manual control
+        if original_enable.is_some() {
+            backend::write_hwmon_pwm_enable(&output.pwm_input, MANUAL_ENABLE)?;
+        }
+        backend::write_hwmon_pwm_value(&output.pwm_input, raw_value(HIGH_POWER))?;
+        thread::sleep(settle);
+        let high = read_tachs(devices);
+        backend::write_hwmon_pwm_value(&output.pwm_input, raw_value(LOW_POWER))?;
+        thread::sleep(settle);
+        let low = read_tachs(devices);
+        Ok::<_, io::Error>((high, low))
+    })();
+
+    // Always hand the output back the way it was found
+    if let Some(value) = original_value {
+        let _ = backend::write_hwmon_pwm_value(&output.pwm_input, value);
+    }
+    if let Some(enable) = original_enable {
+        if let Err(e) = backend::write_hwmon_pwm_enable(&output.pwm_input, enable) {
+            log!("Detect: failed to restore {}: {}", output.pwm_input.display(), e);
+        }
+    }
+
+    let (high, low) = measured?;
+    Ok(high.into_iter()
+        .filter_map(|(fan_input, rpm_high)| {
+            let rpm_low = *low.get(&fan_input)?;
+            let drop = rpm_high.checked_sub(rpm_low)?;
+            (drop >= MIN_DROP_RPM && drop * 100 >= rpm_high * MIN_DROP_PCT).then_some((fan_input, rpm_high, rpm_low))
+        })
+        .max_by_key(|(_, rpm_high, rpm_low)| rpm_high - rpm_low))
+}
+
+fn suggested_fan(output: &Output, pwm_input: &str, rpm_high: u32, rpm_low: u32) -> FanConfig {
+    let steps = templates::find("balanced").map(|template| template.steps.to_vec()).unwrap_or_default();
+    FanConfig {
+        rpm_curve: vec![
+            RpmPoint { power: LOW_POWER, rpm: rpm_low },
+            RpmPoint { power: HIGH_POWER, rpm: rpm_high },
+        ],
+        ..FanConfig::hwmon("", "", &output.pwm_name, pwm_input, steps)
+    }
+}
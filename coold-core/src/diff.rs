@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::daemon::Config;
+
+// Top-level entries made of named items, diffed item by item
+const SECTIONS: [&str; 4] = ["fan", "zones", "curves", "sensors"];
+
+/// A setting or field whose value differs, None standing for unset
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// Named items (fans, zones...) added, removed or changed
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: BTreeMap<String, Vec<FieldChange>>,
+}
+
+impl SectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// What applying a candidate configuration would change
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ConfigDiff {
+    /// fan, zones, curves and sensors
+    pub sections: BTreeMap<String, SectionDiff>,
+    /// Every other top-level setting (alerts, night_mode...)
+    pub settings: Vec<FieldChange>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty() && self.sections.values().all(SectionDiff::is_empty)
+    }
+
+    /// Number of items added, removed or changed, and settings changed
+    pub fn len(&self) -> usize {
+        self.settings.len() + self.sections.values()
+            .map(|section| section.added.len() + section.removed.len() + section.changed.len())
+            .sum::<usize>()
+    }
+}
+
+fn as_object(config: &Config) -> Map<String, Value> {
+    match serde_json::to_value(config) {
+        Ok(Value::Object(object)) => object,
+        _ => Map::new(),
+    }
+}
+
+fn field_changes(from: &Map<String, Value>, to: &Map<String, Value>) -> Vec<FieldChange> {
+    let fields: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+    fields.into_iter()
+        .filter(|field| from.get(*field) != to.get(*field))
+        .map(|field| FieldChange { field: field.clone(), from: from.get(field).cloned(), to: to.get(field).cloned() })
+        .collect()
+}
+
+fn section_diff(from: Option<&Value>, to: Option<&Value>) -> SectionDiff {
+    let empty = Map::new();
+    let from = from.and_then(Value::as_object).unwrap_or(&empty);
+    let to = to.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut diff = SectionDiff {
+        added: to.keys().filter(|name| !from.contains_key(*name)).cloned().collect(),
+        removed: from.keys().filter(|name| !to.contains_key(*name)).cloned().collect(),
+        ..SectionDiff::default()
+    };
+    diff.added.sort();
+    diff.removed.sort();
+    for (name, old) in from {
+        let Some(new) = to.get(name).filter(|new| *new != old) else {
+            continue;
+        };
+        let changes = match (old, new) {
+            (Value::Object(old), Value::Object(new)) => field_changes(old, new),
+            // Named curves are bare lists of steps
+            _ => vec![FieldChange { field: "steps".to_string(), from: Some(old.clone()), to: Some(new.clone()) }],
+        };
+        diff.changed.insert(name.clone(), changes);
+    }
+    diff
+}
+
+/// Compare the running configuration with a candidate, both as saved (file names, not sysfs paths)
+pub fn diff_configs(running: &Config, candidate: &Config) -> ConfigDiff {
+    let (mut running, mut candidate) = (as_object(running), as_object(candidate));
+    let sections = SECTIONS.iter()
+        .map(|section| (section.to_string(), section_diff(running.remove(*section).as_ref(), candidate.remove(*section).as_ref())))
+        .collect();
+    ConfigDiff { sections, settings: field_changes(&running, &candidate) }
+}
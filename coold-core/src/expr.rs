@@ -0,0 +1,316 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Node of a parsed expression
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Temp,
+    Neg(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "clamp" => Some(Function::Clamp),
+            "abs" => Some(Function::Abs),
+            _ => None,
+        }
+    }
+
+    // Whether the function accepts `count` arguments, with what it expects otherwise
+    fn check_arity(self, count: usize) -> Result<(), &'static str> {
+        match self {
+            Function::Min | Function::Max if count < 2 => Err("at least 2 arguments"),
+            Function::Clamp if count != 3 => Err("3 arguments (value, min, max)"),
+            Function::Abs if count != 1 => Err("1 argument"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+/// An invalid expression, with the 1-based position of the offending character
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+fn error<T>(position: usize, message: String) -> Result<T, ExprError> {
+    Err(ExprError { position, message })
+}
+
+// Tokens with their 1-based position
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let (c, position) = (chars[index], index + 1);
+        let token = match c {
+            c if c.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            '0'..='9' | '.' => {
+                let start = index;
+                while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                    index += 1;
+                }
+                let text: String = chars[start..index].iter().collect();
+                match text.parse() {
+                    Ok(number) => tokens.push((Token::Number(number), position)),
+                    Err(_) => return error(position, format!("Invalid number '{}'", text)),
+                }
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = index;
+                while index < chars.len() && (chars[index].is_ascii_alphanumeric() || chars[index] == '_') {
+                    index += 1;
+                }
+                tokens.push((Token::Ident(chars[start..index].iter().collect()), position));
+                continue;
+            }
+            '+' | '-' | '*' | '/' => Token::Op(c),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            _ => return error(position, format!("Unexpected character '{}'", c)),
+        };
+        tokens.push((token, position));
+        index += 1;
+    }
+    Ok(tokens)
+}
+
+// Deepest nesting of parentheses, calls and negations, so the recursive parser
+// can't run out of stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    // Position just past the end, for errors at the end of the expression
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExprError> {
+        match self.advance() {
+            Some((token, _)) if token == expected => Ok(()),
+            Some((token, position)) => error(position, format!("Expected '{}', found '{}'", expected, token)),
+            None => error(self.end, format!("Expected '{}'", expected)),
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    // unary := '-' unary | primary
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        if self.depth == MAX_DEPTH {
+            let position = self.tokens.get(self.next).map_or(self.end, |(_, position)| *position);
+            return error(position, format!("Expression nested more than {} levels deep", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let node = if self.peek() == Some(&Token::Op('-')) {
+            self.advance();
+            self.unary().map(|node| Node::Neg(Box::new(node)))
+        } else {
+            self.primary()
+        };
+        self.depth -= 1;
+        node
+    }
+
+    // primary := number | 'temp' | function '(' sum (',' sum)* ')' | '(' sum ')'
+    fn primary(&mut self) -> Result<Node, ExprError> {
+        match self.advance() {
+            Some((Token::Number(number), _)) => Ok(Node::Number(number)),
+            Some((Token::Open, _)) => {
+                let node = self.sum()?;
+                self.expect(Token::Close)?;
+                Ok(node)
+            }
+            Some((Token::Ident(name), position)) if self.peek() == Some(&Token::Open) => {
+                let Some(function) = Function::from_name(&name) else {
+                    return error(position, format!("Unknown function '{}' (min, max, clamp and abs are available)", name));
+                };
+                self.advance();
+                let mut args = vec![self.sum()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.advance();
+                    args.push(self.sum()?);
+                }
+                self.expect(Token::Close)?;
+                if let Err(expected) = function.check_arity(args.len()) {
+                    return error(position, format!("{} takes {}, got {}", name, expected, args.len()));
+                }
+                Ok(Node::Call(function, args))
+            }
+            Some((Token::Ident(name), _)) if name == "temp" => Ok(Node::Temp),
+            Some((Token::Ident(name), position)) => error(position, format!("Unknown variable '{}' (only temp is available)", name)),
+            Some((token, position)) => error(position, format!("Unexpected '{}'", token)),
+            None => error(self.end, "Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl Node {
+    fn eval(&self, temp: f64) -> f64 {
+        match self {
+            Node::Number(number) => *number,
+            Node::Temp => temp,
+            Node::Neg(node) => -node.eval(temp),
+            Node::Binary(op, left, right) => {
+                let (left, right) = (left.eval(temp), right.eval(temp));
+                match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left / right,
+                }
+            }
+            Node::Call(function, args) => {
+                let mut values = args.iter().map(|arg| arg.eval(temp));
+                match function {
+                    Function::Min => values.fold(f64::INFINITY, f64::min),
+                    Function::Max => values.fold(f64::NEG_INFINITY, f64::max),
+                    Function::Abs => values.next().unwrap_or_default().abs(),
+                    Function::Clamp => {
+                        let (value, min, max) = (values.next().unwrap_or_default(), values.next().unwrap_or_default(), values.next().unwrap_or_default());
+                        value.max(min).min(max)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Power as a function of the temperature, e.g. "clamp((temp - 30) * 2, 20, 100)"
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PowerExpr {
+    source: String,
+    node: Node,
+}
+
+impl PowerExpr {
+    /// Power for `temp`, rounded and kept within 0-100
+    pub fn eval(&self, temp: i32) -> u8 {
+        let power = self.node.eval(temp as f64);
+        // Only a division by zero gets here, better too loud than too hot
+        if power.is_nan() {
+            return 100;
+        }
+        power.round().clamp(0.0, 100.0) as u8
+    }
+}
+
+impl TryFrom<String> for PowerExpr {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let tokens = tokenize(&source).map_err(|e| format!("Invalid power expression '{}': {}", source, e))?;
+        let end = source.chars().count() + 1;
+        let mut parser = Parser { tokens, next: 0, end, depth: 0 };
+        let node = parser.sum().and_then(|node| match parser.advance() {
+            Some((token, position)) => error(position, format!("Unexpected '{}'", token)),
+            None => Ok(node),
+        });
+        match node {
+            Ok(node) => Ok(PowerExpr { source, node }),
+            Err(e) => Err(format!("Invalid power expression '{}': {}", source, e)),
+        }
+    }
+}
+
+// Written as its source, the syntax is checked when the configuration is loaded
+impl JsonSchema for PowerExpr {
+    fn schema_name() -> String {
+        "PowerExpr".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl From<PowerExpr> for String {
+    fn from(expr: PowerExpr) -> Self {
+        expr.source
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::backend::sysfs_class;
+use crate::daemon::{hwmon_device_path, Config, FanBackend, FanConfig};
+
+/// /etc/fancontrol file equivalent to a configuration, for the lm-sensors `fancontrol` script
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FancontrolExport {
+    pub content: String,
+    /// Fans fancontrol can't drive, left out of `content`
+    pub skipped: Vec<SkippedFan>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedFan {
+    pub fan: String,
+    pub reason: String,
+}
+
+// One FCTEMPS entry and its curve settings, hwmon paths relative to /sys/class/hwmon
+struct Entry {
+    fan: String,
+    pwm: String,
+    temp: String,
+    tach: Option<String>,
+    min_temp: i32,
+    max_temp: i32,
+    min_stop: u32,
+    max_pwm: u32,
+    notes: Vec<String>,
+}
+
+// Raw 0-255 pwm value of a power in %, as written by the hwmon backend
+fn raw_pwm(power: u8) -> u32 {
+    power.min(100) as u32 * 255 / 100
+}
+
+// `hwmonN/file` of a resolved hwmon path
+fn hwmon_relative(path: &str) -> Option<(String, String)> {
+    let path = Path::new(path);
+    let file = path.file_name()?.to_str()?;
+    let dir = path.parent()?.file_name()?.to_str()?;
+    (path.is_absolute() && dir.starts_with("hwmon")).then(|| (dir.to_string(), file.to_string()))
+}
+
+fn entry(name: &str, fan: &FanConfig) -> Result<Entry, String> {
+    if fan.backend != FanBackend::Hwmon {
+        return Err(format!("fancontrol only drives hwmon outputs, not {:?}", fan.backend));
+    }
+    if let Some(leader) = &fan.follows {
+        return Err(format!("follows {}", leader));
+    }
+    if fan.sensor.is_some() {
+        return Err("reads a sensor other than a hwmon tempN_input".to_string());
+    }
+    if fan.ambient.is_some() {
+        return Err("its steps are relative to an ambient sensor".to_string());
+    }
+    if fan.power.is_some() || fan.script.is_some() {
+        return Err("computes its power with an expression or a script".to_string());
+    }
+    if !fan.rpm_steps.is_empty() {
+        return Err("targets speeds rather than power".to_string());
+    }
+    let (Some((temp_dir, temp_file)), Some((pwm_dir, pwm_file))) = (hwmon_relative(&fan.sensor_input), hwmon_relative(&fan.pwm_input)) else {
+        return Err("its devices weren't found".to_string());
+    };
+
+    let mut steps = fan.steps.clone();
+    steps.sort_by_key(|step| step.temp);
+    let (Some(first), Some(last)) = (steps.first(), steps.last()) else {
+        return Err("has no curve".to_string());
+    };
+    let min_stop = raw_pwm(first.power.max(fan.min_power.unwrap_or(0)));
+    let max_pwm = raw_pwm(last.power.min(fan.max_power.unwrap_or(100)));
+    // fancontrol ramps linearly from MINSTOP at MINTEMP to MAXPWM at MAXTEMP
+    if first.temp >= last.temp || min_stop >= max_pwm {
+        return Err("its curve doesn't rise, fancontrol needs power increasing with temperature".to_string());
+    }
+
+    let mut notes = Vec::new();
+    if steps.len() > 2 {
+        notes.push(format!("{} steps approximated by a line from {}°C to {}°C", steps.len(), first.temp, last.temp));
+    }
+    if !fan.cooldown_steps.is_empty() {
+        notes.push("cooldown_steps left out".to_string());
+    }
+    if !fan.load_steps.is_empty() {
+        notes.push("load_steps left out".to_string());
+    }
+    let tach = pwm_file.strip_prefix("pwm").map(|index| format!("fan{}_input", index))
+        .filter(|tach| Path::new(&fan.pwm_input).with_file_name(tach).exists())
+        .map(|tach| format!("{}/{}", pwm_dir, tach));
+
+    Ok(Entry {
+        fan: name.to_string(),
+        pwm: format!("{}/{}", pwm_dir, pwm_file),
+        temp: format!("{}/{}", temp_dir, temp_file),
+        tach,
+        min_temp: first.temp,
+        max_temp: last.temp,
+        min_stop,
+        max_pwm,
+        notes,
+    })
+}
+
+// Device of a hwmon directory relative to /sys, as fancontrol checks it in DEVPATH
+fn device_path(hwmon_dir: &Path) -> Option<String> {
+    let root = fs::canonicalize(sysfs_class("hwmon").parent()?.parent()?).ok()?;
+    let device = hwmon_device_path(hwmon_dir)?;
+    Some(device.strip_prefix(root).ok()?.to_string_lossy().to_string())
+}
+
+fn setting(name: &str, entries: &[(String, String)]) -> String {
+    let values: Vec<String> = entries.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+    format!("{}={}\n", name, values.join(" "))
+}
+
+/// fancontrol equivalent of a running configuration, whose device paths are resolved.
+/// Curves become the straight line from their first to their last step; fans fancontrol
+/// can't express (other backends, followers, expressions...) are skipped.
+pub fn export(config: &Config, interval_secs: u64) -> FancontrolExport {
+    let mut fans: Vec<(String, FanConfig)> = config.controlled_fans().into_iter().collect();
+    fans.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut skipped = Vec::new();
+    for (name, fan) in &fans {
+        let result = entry(name, fan).and_then(|entry| match entries.iter().find(|other| other.pwm == entry.pwm) {
+            Some(other) => Err(format!("{} is already driven by {}", entry.pwm, other.fan)),
+            None => Ok(entry),
+        });
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(reason) => skipped.push(SkippedFan { fan: name.clone(), reason }),
+        }
+    }
+
+    let mut devices: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let hwmon = sysfs_class("hwmon");
+    for entry in &entries {
+        for path in [&entry.pwm, &entry.temp] {
+            let dir = path.split('/').next().unwrap_or_default().to_string();
+            devices.entry(dir.clone()).or_insert_with(|| hwmon.join(dir));
+        }
+    }
+    let names: HashMap<&String, String> = devices.iter()
+        .map(|(dir, path)| (dir, fs::read_to_string(path.join("name")).map(|name| name.trim().to_string()).unwrap_or_default()))
+        .collect();
+
+    let mut content = String::from("# Generated by coold-rs from its running configuration\n");
+    for entry in &entries {
+        for note in &entry.notes {
+            content.push_str(&format!("# {}: {}\n", entry.fan, note));
+        }
+    }
+    for skipped in &skipped {
+        content.push_str(&format!("# {}: left out, {}\n", skipped.fan, skipped.reason));
+    }
+    content.push_str(&format!("INTERVAL={}\n", interval_secs.max(1)));
+    let device_paths: Vec<(String, String)> = devices.iter()
+        .filter_map(|(dir, path)| Some((dir.clone(), device_path(path)?)))
+        .collect();
+    if !device_paths.is_empty() {
+        content.push_str(&setting("DEVPATH", &device_paths));
+    }
+    content.push_str(&setting("DEVNAME", &devices.keys().map(|dir| (dir.clone(), names[dir].clone())).collect::<Vec<_>>()));
+    let each = |value: &dyn Fn(&Entry) -> String| entries.iter().map(|entry| (entry.pwm.clone(), value(entry))).collect::<Vec<_>>();
+    content.push_str(&setting("FCTEMPS", &each(&|entry| entry.temp.clone())));
+    let tachs: Vec<(String, String)> = entries.iter()
+        .filter_map(|entry| Some((entry.pwm.clone(), entry.tach.clone()?)))
+        .collect();
+    if !tachs.is_empty() {
+        content.push_str(&setting("FCFANS", &tachs));
+    }
+    content.push_str(&setting("MINTEMP", &each(&|entry| entry.min_temp.to_string())));
+    content.push_str(&setting("MAXTEMP", &each(&|entry| entry.max_temp.to_string())));
+    // Below MINTEMP coold holds the first step, which fancontrol does with MINPWM
+    content.push_str(&setting("MINSTART", &each(&|entry| entry.min_stop.to_string())));
+    content.push_str(&setting("MINSTOP", &each(&|entry| entry.min_stop.to_string())));
+    content.push_str(&setting("MINPWM", &each(&|entry| entry.min_stop.to_string())));
+    content.push_str(&setting("MAXPWM", &each(&|entry| entry.max_pwm.to_string())));
+
+    FancontrolExport { content, skipped }
+}
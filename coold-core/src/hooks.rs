@@ -0,0 +1,118 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::log;
+
+/// How long the daemon waits for on_stop before exiting anyway
+const STOP_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Commands run through `sh -c` on daemon events, described by COOLD_* environment variables
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Hooks {
+    /// Once the control loop started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_start: Option<String>,
+    /// On shutdown, the daemon waits up to 10s for it before exiting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_stop: Option<String>,
+    /// When the daemon switches between the default, night, paused and uncapped profiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_profile_change: Option<String>,
+    /// When a fan starts failing: write or sensor errors, RPM off its curve, stuck control loop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_fan_error: Option<String>,
+}
+
+/// Kind of fan error reported to on_fan_error in COOLD_ERROR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanError {
+    Write,
+    Sensor,
+    Failing,
+    Stalled,
+    SelfTest,
+}
+
+impl FanError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FanError::Write => "write",
+            FanError::Sensor => "sensor",
+            FanError::Failing => "failing",
+            FanError::Stalled => "stalled",
+            FanError::SelfTest => "self_test",
+        }
+    }
+}
+
+/// The set of rules fans are driven by, as reported to on_profile_change
+pub fn profile(paused: bool, power_cap_lifted: bool, night_cap: Option<u8>) -> &'static str {
+    if paused {
+        "paused"
+    } else if power_cap_lifted {
+        "uncapped"
+    } else if night_cap.is_some() {
+        "night"
+    } else {
+        "default"
+    }
+}
+
+fn spawn(event: &str, command: &str, env: &[(&str, String)]) -> Option<Child> {
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("COOLD_EVENT", event)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .spawn();
+    match result {
+        Ok(child) => Some(child),
+        Err(e) => {
+            log!("Hook {}: failed to run {}: {}", event, command, e);
+            None
+        }
+    }
+}
+
+/// Run a hook in the background, so a slow script can't hold up the daemon
+pub fn run(event: &'static str, command: Option<&str>, env: &[(&str, String)]) {
+    let Some(mut child) = command.and_then(|command| spawn(event, command, env)) else {
+        return;
+    };
+    thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => log!("Hook {}: command exited with {}", event, status),
+        Ok(_) => {}
+        Err(e) => log!("Hook {}: failed to wait for the command: {}", event, e),
+    });
+}
+
+/// Run a hook and wait for it, killing it after STOP_HOOK_TIMEOUT
+pub fn run_and_wait(event: &'static str, command: Option<&str>, env: &[(&str, String)]) {
+    let Some(mut child) = command.and_then(|command| spawn(event, command, env)) else {
+        return;
+    };
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    log!("Hook {}: command exited with {}", event, status);
+                }
+                return;
+            }
+            Ok(None) if started.elapsed() < STOP_HOOK_TIMEOUT => thread::sleep(Duration::from_millis(100)),
+            Ok(None) => {
+                log!("Hook {}: still running after {}s, killing it", event, STOP_HOOK_TIMEOUT.as_secs());
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Err(e) => {
+                log!("Hook {}: failed to wait for the command: {}", event, e);
+                return;
+            }
+        }
+    }
+}
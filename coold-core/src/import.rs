@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use crate::daemon::{Config, FanConfig, FanStep, SensorSource};
+
+/// Configuration converted from another fan control tool
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Imported {
+    pub config: Config,
+    /// What couldn't be carried over and is left to do by hand
+    pub warnings: Vec<String>,
+}
+
+// Sensor settings of a fan
+#[derive(Clone)]
+struct Sensor {
+    name: String,
+    input: String,
+    source: Option<SensorSource>,
+    scale: Option<f64>,
+}
+
+impl Sensor {
+    fn hwmon(name: &str, input: String) -> Self {
+        Sensor { name: name.to_string(), input, source: None, scale: None }
+    }
+
+    fn fan(&self, pwm_name: &str, pwm_input: &str, steps: Vec<FanStep>) -> FanConfig {
+        FanConfig {
+            sensor: self.source.clone(),
+            sensor_scale: self.scale,
+            ..FanConfig::hwmon(&self.name, &self.input, pwm_name, pwm_input, steps)
+        }
+    }
+}
+
+// Percentage of a raw 0-255 pwm value
+fn percent(pwm: f64) -> u8 {
+    (pwm.clamp(0.0, 255.0) * 100.0 / 255.0).round() as u8
+}
+
+// Name usable as a fan or curve name in the API paths
+fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' }).collect()
+}
+
+#[derive(Deserialize)]
+struct Fan2go {
+    #[serde(default)]
+    fans: Vec<Fan2goFan>,
+    #[serde(default)]
+    sensors: Vec<Fan2goSensor>,
+    #[serde(default)]
+    curves: Vec<Fan2goCurve>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Fan2goFan {
+    id: String,
+    hwmon: Option<Fan2goHwmonFan>,
+    curve: Option<String>,
+    min_pwm: Option<f64>,
+    max_pwm: Option<f64>,
+    #[serde(default)]
+    never_stop: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Fan2goHwmonFan {
+    platform: String,
+    index: Option<u32>,
+    rpm_channel: Option<u32>,
+    pwm_channel: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Fan2goSensor {
+    id: String,
+    hwmon: Option<Fan2goHwmonSensor>,
+    file: Option<Fan2goFileSensor>,
+    cmd: Option<Fan2goCmdSensor>,
+}
+
+#[derive(Deserialize)]
+struct Fan2goHwmonSensor {
+    platform: String,
+    index: u32,
+}
+
+#[derive(Deserialize)]
+struct Fan2goFileSensor {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct Fan2goCmdSensor {
+    exec: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Fan2goCurve {
+    id: String,
+    linear: Option<Fan2goLinear>,
+}
+
+#[derive(Deserialize)]
+struct Fan2goLinear {
+    sensor: String,
+    min: Option<f64>,
+    max: Option<f64>,
+    steps: Option<Value>,
+}
+
+// hwmon device name of a fan2go platform, a regex over e.g. "nct6798-isa-0290"
+fn fan2go_device(platform: &str) -> String {
+    let name = platform.trim_start_matches('^').split('-').next().unwrap_or(platform);
+    name.trim_end_matches(['$', '*', '.']).to_string()
+}
+
+// Quoted for `sh -c`
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn fan2go_sensor(sensor: &Fan2goSensor) -> Result<Sensor, String> {
+    if let Some(hwmon) = &sensor.hwmon {
+        return Ok(Sensor::hwmon(&fan2go_device(&hwmon.platform), format!("temp{}_input", hwmon.index)));
+    }
+    // fan2go reads millidegrees from files and commands
+    let cmd = if let Some(file) = &sensor.file {
+        format!("cat {}", shell_quote(&file.path))
+    } else if let Some(cmd) = &sensor.cmd {
+        std::iter::once(&cmd.exec).chain(&cmd.args).map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ")
+    } else {
+        return Err("is neither a hwmon, file nor cmd sensor".to_string());
+    };
+    Ok(Sensor {
+        name: String::new(),
+        input: String::new(),
+        source: Some(SensorSource::Command { cmd, timeout_secs: None }),
+        scale: Some(0.001),
+    })
+}
+
+// fan2go steps, `- 40: 0` items or a `40: 0` mapping, of raw 0-255 values
+fn fan2go_steps(steps: &Value) -> Result<Vec<FanStep>, String> {
+    let pairs: Vec<(&Value, &Value)> = match steps {
+        Value::Mapping(map) => map.iter().collect(),
+        Value::Sequence(items) => items.iter().filter_map(Value::as_mapping).flat_map(|map| map.iter()).collect(),
+        _ => return Err("steps are neither a list nor a mapping".to_string()),
+    };
+    let mut steps = pairs.into_iter()
+        .map(|(temp, pwm)| {
+            // Keys are numbers unless quoted
+            let temp = temp.as_f64().or_else(|| temp.as_str()?.parse().ok())
+                .ok_or_else(|| format!("step temperature {:?} isn't a number", temp))?;
+            let pwm = pwm.as_f64().ok_or_else(|| format!("step at {}°C has no pwm value", temp))?;
+            Ok(FanStep { temp: temp.round() as i32, power: percent(pwm) })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    steps.sort_by_key(|step| step.temp);
+    Ok(steps)
+}
+
+/// Convert a fan2go configuration (fan2go.yaml). hwmon fans with linear curves are
+/// carried over; PID and function curves and file or command fans are reported.
+pub fn from_fan2go(content: &str) -> Result<Imported, String> {
+    let fan2go: Fan2go = serde_yaml::from_str(content).map_err(|e| format!("Not a fan2go configuration: {}", e))?;
+    let mut config = Config::default();
+    let mut warnings = Vec::new();
+
+    let mut sensors = HashMap::new();
+    for sensor in &fan2go.sensors {
+        match fan2go_sensor(sensor) {
+            Ok(converted) => {
+                sensors.insert(sensor.id.as_str(), converted);
+            }
+            Err(e) => warnings.push(format!("Sensor {} {}, fans using it are left out", sensor.id, e)),
+        }
+    }
+
+    let mut curves: HashMap<&str, &Sensor> = HashMap::new();
+    for curve in &fan2go.curves {
+        let Some(linear) = &curve.linear else {
+            warnings.push(format!("Curve {} isn't linear (PID and function curves have no equivalent), fans using it are left out", curve.id));
+            continue;
+        };
+        let Some(sensor) = sensors.get(linear.sensor.as_str()) else {
+            warnings.push(format!("Curve {} reads unknown sensor {}, fans using it are left out", curve.id, linear.sensor));
+            continue;
+        };
+        let steps = match (&linear.steps, linear.min, linear.max) {
+            (Some(steps), _, _) => fan2go_steps(steps),
+            (None, Some(min), Some(max)) => Ok(vec![
+                FanStep { temp: min.round() as i32, power: 0 },
+                FanStep { temp: max.round() as i32, power: 100 },
+            ]),
+            _ => Err("has neither steps nor min and max".to_string()),
+        };
+        match steps {
+            Ok(steps) => {
+                config.curves.insert(sanitize(&curve.id), steps);
+                curves.insert(curve.id.as_str(), sensor);
+            }
+            Err(e) => warnings.push(format!("Curve {}: {}, fans using it are left out", curve.id, e)),
+        }
+    }
+
+    for fan in &fan2go.fans {
+        let Some(hwmon) = &fan.hwmon else {
+            warnings.push(format!("Fan {} isn't a hwmon fan, add it by hand", fan.id));
+            continue;
+        };
+        let Some(channel) = hwmon.pwm_channel.or(hwmon.rpm_channel).or(hwmon.index) else {
+            warnings.push(format!("Fan {} has no pwmChannel, rpmChannel or index", fan.id));
+            continue;
+        };
+        let Some((curve, sensor)) = fan.curve.as_deref().and_then(|curve| Some((curve, *curves.get(curve)?))) else {
+            warnings.push(format!("Fan {} has no usable curve", fan.id));
+            continue;
+        };
+        let converted = FanConfig {
+            curve: Some(sanitize(curve)),
+            // fan2go only keeps a fan spinning at minPwm with neverStop
+            min_power: fan.min_pwm.filter(|_| fan.never_stop).map(percent).filter(|power| *power > 0),
+            max_power: fan.max_pwm.map(percent).filter(|power| *power < 100),
+            ..sensor.fan(&fan2go_device(&hwmon.platform), &format!("pwm{}", channel), Vec::new())
+        };
+        config.fan.insert(sanitize(&fan.id), converted);
+    }
+    Ok(Imported { config, warnings })
+}
+
+#[derive(Deserialize)]
+struct CoolerControl {
+    #[serde(default)]
+    devices: HashMap<String, String>,
+    #[serde(default, rename = "device-settings")]
+    device_settings: HashMap<String, HashMap<String, CoolerControlSetting>>,
+    #[serde(default)]
+    profiles: Vec<CoolerControlProfile>,
+}
+
+#[derive(Deserialize)]
+struct CoolerControlSetting {
+    profile_uid: Option<String>,
+    speed_fixed: Option<f64>,
+    speed_profile: Option<Vec<(f64, f64)>>,
+    temp_source: Option<CoolerControlTempSource>,
+}
+
+#[derive(Deserialize)]
+struct CoolerControlProfile {
+    uid: String,
+    name: String,
+    #[serde(default)]
+    p_type: String,
+    speed_fixed: Option<f64>,
+    speed_profile: Option<Vec<(f64, f64)>>,
+    temp_source: Option<CoolerControlTempSource>,
+}
+
+#[derive(Deserialize)]
+struct CoolerControlTempSource {
+    temp_name: String,
+    device_uid: String,
+}
+
+fn coolercontrol_steps(profile: &[(f64, f64)]) -> Vec<FanStep> {
+    profile.iter()
+        .map(|(temp, duty)| FanStep { temp: temp.round() as i32, power: duty.clamp(0.0, 100.0).round() as u8 })
+        .collect()
+}
+
+/// Convert a CoolerControl daemon configuration (/etc/coolercontrol/config.toml).
+/// Channels set to a fixed speed or a graph profile are carried over, their devices
+/// taken as hwmon devices of the same name.
+pub fn from_coolercontrol(content: &str) -> Result<Imported, String> {
+    let cc: CoolerControl = toml::from_str(content).map_err(|e| format!("Not a CoolerControl configuration: {}", e))?;
+    let mut config = Config::default();
+    let mut warnings = Vec::new();
+
+    let device_name = |uid: &str| cc.devices.get(uid).cloned().unwrap_or_else(|| uid.to_string());
+    let sensor = |source: &CoolerControlTempSource| {
+        // tempN channels are hwmon inputs, other names are taken as tempN_label
+        let input = match source.temp_name.strip_prefix("temp").filter(|index| index.parse::<u32>().is_ok()) {
+            Some(_) => format!("{}_input", source.temp_name),
+            None => source.temp_name.clone(),
+        };
+        Sensor::hwmon(&device_name(&source.device_uid), input)
+    };
+    let profiles: HashMap<&str, &CoolerControlProfile> = cc.profiles.iter().map(|profile| (profile.uid.as_str(), profile)).collect();
+    // Fixed speeds don't depend on a sensor, any input of the device does
+    let fixed = |speed: f64| vec![FanStep { temp: 0, power: speed.clamp(0.0, 100.0).round() as u8 }];
+
+    let mut device_uids: Vec<&String> = cc.device_settings.keys().collect();
+    device_uids.sort();
+    for device_uid in device_uids {
+        let device = device_name(device_uid);
+        if device.contains(' ') {
+            warnings.push(format!("Device {} doesn't look like a hwmon device name, set the pwm_name of its fans by hand", device));
+        }
+        let mut channels: Vec<(&String, &CoolerControlSetting)> = cc.device_settings[device_uid].iter().collect();
+        channels.sort_by_key(|(channel, _)| channel.as_str());
+        for (channel, setting) in channels {
+            let name = sanitize(&format!("{}_{}", device, channel));
+            // hwmon channels are named fanN after their pwmN output
+            let pwm_input = match channel.strip_prefix("fan") {
+                Some(index) if index.parse::<u32>().is_ok() => format!("pwm{}", index),
+                _ => channel.clone(),
+            };
+            let (label, speed_fixed, speed_profile, temp_source) = match setting.profile_uid.as_deref() {
+                Some(uid) => match profiles.get(uid) {
+                    Some(profile) if profile.p_type == "Default" => {
+                        warnings.push(format!("{} uses the default profile, it is left to the firmware", name));
+                        continue;
+                    }
+                    Some(profile) => (format!("profile {}", profile.name), profile.speed_fixed, profile.speed_profile.as_ref(), profile.temp_source.as_ref()),
+                    None => {
+                        warnings.push(format!("{} uses unknown profile {}", name, uid));
+                        continue;
+                    }
+                },
+                None => ("its settings".to_string(), setting.speed_fixed, setting.speed_profile.as_ref(), setting.temp_source.as_ref()),
+            };
+            let fan = match (speed_profile, temp_source, speed_fixed) {
+                (Some(profile), Some(source), _) => sensor(source).fan(&device, &pwm_input, coolercontrol_steps(profile)),
+                (_, _, Some(speed)) => {
+                    warnings.push(format!("{} runs at a fixed {}% but still needs a sensor, temp1_input of {} was picked", name, speed, device));
+                    Sensor::hwmon(&device, "temp1_input".to_string()).fan(&device, &pwm_input, fixed(speed))
+                }
+                _ => {
+                    warnings.push(format!("{}: {} is neither a fixed speed nor a graph (mix profiles have no equivalent)", name, label));
+                    continue;
+                }
+            };
+            config.fan.insert(name, fan);
+        }
+    }
+    Ok(Imported { config, warnings })
+}
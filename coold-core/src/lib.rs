@@ -0,0 +1,53 @@
+//! Fan control engine of coold-rs, for programs embedding it instead of running the daemon.
+//!
+//! A [`daemon::Config`] describes the fans: the sensor each one follows, the PWM output it
+//! drives and the curve turning temperatures into power. [`daemon::FanController`] runs it,
+//! one task per fan, and is shared between threads by cloning it:
+//!
+//! - [`daemon::FanController::run`] drives the fans until [`daemon::FanController::get_running`]
+//!   is set to false, then hands them back to the firmware
+//! - [`daemon::FanController::get_fan_states`] reports temperatures, power and RPM of every fan
+//! - [`daemon::FanController::apply_config`] and [`daemon::FanController::modify_config`]
+//!   change the configuration of a running controller
+//! - [`daemon::FanController::send`] overrides, boosts, pauses or stops the fans
+//!
+//! Devices are reached through [`backend`], which reads hwmon under `/sys` unless
+//! [`backend::set_sysfs_root`] points it elsewhere, and can be replaced by a scripted
+//! [`simulation`] or a [`recording`] replay.
+
+/// Configuration, curve math and the controller driving the fans
+pub mod daemon;
+/// Sensor and PWM access: hwmon, commands, HTTP and the other fan backends
+pub mod backend;
+/// Operating system specific access, such as FreeBSD sysctls
+pub mod os;
+/// Daemon logs, as text or JSON lines
+pub mod logging;
+/// Webhook alerts on fan and temperature events
+pub mod alert;
+/// Field by field differences between two configurations
+pub mod diff;
+/// Power expressions such as `clamp((temp - 30) * 2, 20, 100)`
+pub mod expr;
+/// Commands run on daemon events
+pub mod hooks;
+/// Power computed by user scripts
+pub mod script;
+/// Scripted sensors and fans, in place of the hardware
+pub mod simulation;
+/// Recording of sensor readings, and their replay
+pub mod recording;
+/// Trip points run when a sensor crosses a temperature
+pub mod trip;
+/// Built-in curve templates
+pub mod templates;
+/// Export of a configuration to the lm-sensors fancontrol format
+pub mod fancontrol;
+/// Import of fan2go and CoolerControl configurations
+pub mod import;
+/// Matching of PWM outputs to fan tachometers by varying them
+pub mod detect;
+/// Request and response bodies of the REST API
+pub mod api_types;
+/// Client of the REST API
+pub mod client;
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{json, Map, Value};
+
+/// Lines kept in memory for GET /logs
+const RECENT_LINES: usize = 500;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Log JSON lines from now on, for log shippers such as Loki or Logstash
+pub fn set_json() {
+    JSON.store(true, Ordering::SeqCst);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+// Print a log line, keeping it for GET /logs
+fn print(line: String) {
+    println!("{}", line);
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    if recent.len() == RECENT_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+/// The last `limit` lines logged, oldest first
+pub fn recent(limit: usize) -> Vec<String> {
+    let recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    recent.iter().skip(recent.len().saturating_sub(limit)).cloned().collect()
+}
+
+fn timestamp() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| (d.as_millis() as f64) / 1000.0)
+}
+
+/// Log a line of text, wrapped in a `message` event with JSON logs
+pub fn message(text: String) {
+    if is_json() {
+        event("message", json!({ "message": text }));
+    } else {
+        print(text);
+    }
+}
+
+/// Log a structured event with its fields, only with JSON logs: the text logs have a
+/// line of their own for it
+pub fn event(event: &str, fields: Value) {
+    if !is_json() {
+        return;
+    }
+    let mut line = Map::new();
+    line.insert("timestamp".to_string(), json!(timestamp()));
+    line.insert("event".to_string(), json!(event));
+    if let Value::Object(fields) = fields {
+        line.extend(fields);
+    }
+    print(Value::Object(line).to_string());
+}
+
+/// `println!` for the daemon's logs, following the log format
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::logging::message(format!($($arg)*))
+    };
+}
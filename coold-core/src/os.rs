@@ -0,0 +1,44 @@
+use std::io;
+use std::process::Command;
+
+/// Whether the daemon runs on FreeBSD, where sensors and fans are sysctls rather than sysfs files
+pub const IS_FREEBSD: bool = cfg!(target_os = "freebsd");
+
+fn sysctl_error(name: &str, stderr: &[u8]) -> io::Error {
+    let message = String::from_utf8_lossy(stderr).trim().to_string();
+    let message = if message.is_empty() { format!("sysctl {} failed", name) } else { message };
+    io::Error::other(message)
+}
+
+/// Value of a sysctl, as printed by `sysctl -n`
+pub fn read_sysctl(name: &str) -> io::Result<String> {
+    let output = Command::new("sysctl").arg("-n").arg(name).output()?;
+    if !output.status.success() {
+        return Err(sysctl_error(name, &output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Set a sysctl, which needs root
+pub fn write_sysctl(name: &str, value: &str) -> io::Result<()> {
+    let output = Command::new("sysctl").arg(format!("{}={}", name, value)).output()?;
+    if !output.status.success() {
+        return Err(sysctl_error(name, &output.stderr));
+    }
+    Ok(())
+}
+
+/// Numeric sysctl, e.g. `dev.acpi_ibm.0.fan_level`
+pub fn read_sysctl_value<T: std::str::FromStr>(name: &str) -> io::Result<T> {
+    let value = read_sysctl(name)?;
+    value.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected value of {}: {}", name, value)))
+}
+
+/// Temperature sysctl in °C, e.g. `hw.acpi.thermal.tz0.temperature` or `dev.cpu.0.temperature`.
+/// `sysctl` prints them like `45.0C`.
+pub fn read_sysctl_temp(name: &str) -> io::Result<i32> {
+    let value = read_sysctl(name)?;
+    value.trim_end_matches('C').parse::<f64>()
+        .map(|temp| temp.round() as i32)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected temperature in {}: {}", name, value)))
+}
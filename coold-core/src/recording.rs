@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Instant;
+use crate::simulation::{Scenario, TracePoint};
+use crate::log;
+
+/// One raw sensor reading, written as a JSON line
+#[derive(Debug, Serialize, Deserialize)]
+struct Reading {
+    at_secs: f64,
+    sensor: String,
+    temp: i32,
+}
+
+struct Recorder {
+    file: Mutex<File>,
+    started: Instant,
+}
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+
+/// Append every raw sensor reading to `path` from now on
+pub fn start(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to open recording {}: {}", path.display(), e)))?;
+    if RECORDER.set(Recorder { file: Mutex::new(file), started: Instant::now() }).is_err() {
+        log!("Already recording, ignoring {}", path.display());
+    }
+    Ok(())
+}
+
+pub fn is_active() -> bool {
+    RECORDER.get().is_some()
+}
+
+/// Record a raw reading of `sensor` (see `simulation::sensor_key`)
+pub fn record(sensor: &str, temp: i32) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let reading = Reading {
+        at_secs: recorder.started.elapsed().as_secs_f64(),
+        sensor: sensor.to_string(),
+        temp,
+    };
+    let Ok(line) = serde_json::to_string(&reading) else {
+        return;
+    };
+    let mut file = recorder.file.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(e) = writeln!(file, "{}", line) {
+        log!("Failed to record reading of {}: {}", sensor, e);
+    }
+}
+
+/// Turn a recording into a scenario replaying its readings against config.json
+pub fn load_replay(path: &Path) -> io::Result<Scenario> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to read recording {}: {}", path.display(), e)))?;
+
+    let mut sensors: HashMap<String, Vec<TracePoint>> = HashMap::new();
+    for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let reading: Reading = serde_json::from_str(line).map_err(|e| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid recording {} at line {}: {}", path.display(), index + 1, e),
+        ))?;
+        sensors.entry(reading.sensor).or_default().push(TracePoint {
+            at_secs: reading.at_secs,
+            temp: reading.temp as f64,
+        });
+    }
+
+    Ok(Scenario {
+        config: None,
+        sensors,
+        max_rpm: HashMap::new(),
+        repeat: false,
+    })
+}
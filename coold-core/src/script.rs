@@ -0,0 +1,112 @@
+use mlua::{Function, HookTriggers, Lua, Value, VmState};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant, SystemTime};
+use crate::daemon::read_hwmon_sensors;
+
+/// How long a script may take, defaults to 2 seconds
+const DEFAULT_TIMEOUT_SECS: u64 = 2;
+// Lua instructions run between two checks of the timeout
+const TIMEOUT_CHECK_INSTRUCTIONS: u32 = 10_000;
+
+/// Lua script computing a fan's power each cycle, in place of its curve
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ScriptPolicy {
+    /// Lua script file, returning the power
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Lua state of a fan's script, kept from one cycle to the next so the script can
+/// remember values in its globals. Reloaded when the file changes.
+pub struct ScriptState {
+    path: String,
+    modified: Option<SystemTime>,
+    lua: Lua,
+    chunk: Function,
+}
+
+impl ScriptState {
+    fn load(path: &str) -> io::Result<Self> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let source = fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("Failed to read {}: {}", path, e)))?;
+        let lua = Lua::new();
+        let chunk = lua.load(source).set_name(format!("@{}", path)).into_function().map_err(lua_error)?;
+        Ok(Self { path: path.to_string(), modified, lua, chunk })
+    }
+
+    fn is_current(&self, path: &str) -> bool {
+        self.path == path && fs::metadata(path).and_then(|metadata| metadata.modified()).ok() == self.modified
+    }
+}
+
+fn lua_error(e: mlua::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+// Globals the script is given before each run:
+//   fan      name of the fan
+//   temp     filtered temperature of the fan's sensor, °C
+//   power    power set on the previous cycle, nil on the first one
+//   sensors  every hwmon temperature input, sensors[<hwmon name>][<input>] in °C
+fn set_globals(lua: &Lua, fan: &str, temp: i32, last_power: Option<u8>) -> mlua::Result<()> {
+    let globals = lua.globals();
+    globals.set("fan", fan)?;
+    globals.set("temp", temp)?;
+    globals.set("power", last_power)?;
+    let sensors = lua.create_table()?;
+    for sensor in read_hwmon_sensors() {
+        let device = match sensors.get::<Option<mlua::Table>>(sensor.name.as_str())? {
+            Some(device) => device,
+            None => {
+                let device = lua.create_table()?;
+                sensors.set(sensor.name.as_str(), &device)?;
+                device
+            }
+        };
+        device.set(sensor.input, sensor.temperature)?;
+    }
+    globals.set("sensors", sensors)
+}
+
+impl ScriptPolicy {
+    /// Run the script in the fan's Lua state, loading it first if needed, and take
+    /// the power it returned, 0 to 100
+    pub fn power(&self, state: &mut Option<ScriptState>, fan: &str, temp: i32, last_power: Option<u8>) -> io::Result<u8> {
+        if !state.as_ref().is_some_and(|state| state.is_current(&self.path)) {
+            *state = None;
+        }
+        let script = match state {
+            Some(script) => script,
+            None => state.insert(ScriptState::load(&self.path)?),
+        };
+
+        set_globals(&script.lua, fan, temp, last_power).map_err(lua_error)?;
+        // A script stuck in a loop is stopped rather than holding the fan
+        let timeout = Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        // A timeout too long to add to the clock is no deadline at all
+        let deadline = Instant::now().checked_add(timeout);
+        script.lua.set_hook(HookTriggers::new().every_nth_instruction(TIMEOUT_CHECK_INSTRUCTIONS), move |_, _| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(mlua::Error::runtime(format!("took more than {}s", timeout.as_secs())));
+            }
+            Ok(VmState::Continue)
+        });
+        let result = script.chunk.call::<Value>(());
+        script.lua.remove_hook();
+
+        let returned = result.map_err(|e| io::Error::other(format!("{}: {}", self.path, e)))?;
+        let power = match &returned {
+            Value::Integer(power) => Some(*power as f64),
+            Value::Number(power) => Some(*power),
+            _ => None,
+        };
+        power.filter(|power| (0.0..=100.0).contains(power))
+            .map(|power| power.round() as u8)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} returned {} instead of a power between 0 and 100", self.path, returned.to_string().unwrap_or_else(|_| returned.type_name().to_string()))))
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Instant;
+use crate::backend::{PwmSink, TempSource};
+use crate::daemon::{Config, FanConfig, SensorSource};
+use crate::log;
+
+/// Scripted scenario run by `daemon --simulate`
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    /// Configuration to run, config.json when unset
+    #[serde(default)]
+    pub config: Option<Config>,
+    /// Temperature traces, by sensor key (e.g. "sensor_name/sensor_input")
+    pub sensors: HashMap<String, Vec<TracePoint>>,
+    /// Fan speed at 100%, by "pwm_name/pwm_input", for tachometer readings
+    #[serde(default)]
+    pub max_rpm: HashMap<String, u32>,
+    /// Start the traces over once they end
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+/// Temperature of a sensor at a point of the scenario; values in between are interpolated
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct TracePoint {
+    pub at_secs: f64,
+    pub temp: f64,
+}
+
+// State of a simulated PWM output
+#[derive(Debug, Default, Clone, Copy)]
+struct Output {
+    power: u8,
+    manual: bool,
+}
+
+struct Simulation {
+    sensors: HashMap<String, Vec<TracePoint>>,
+    max_rpm: HashMap<String, u32>,
+    repeat: bool,
+    started: Instant,
+    outputs: Mutex<HashMap<String, Output>>,
+}
+
+static SIMULATION: OnceLock<Simulation> = OnceLock::new();
+
+pub fn load(path: &Path) -> io::Result<Scenario> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to read scenario {}: {}", path.display(), e)))?;
+    serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid scenario {}: {}", path.display(), e)))
+}
+
+/// Replace every sensor and fan output with the scenario's in-memory ones
+pub fn start(scenario: Scenario) {
+    let simulation = Simulation {
+        sensors: scenario.sensors,
+        max_rpm: scenario.max_rpm,
+        repeat: scenario.repeat,
+        started: Instant::now(),
+        outputs: Mutex::new(HashMap::new()),
+    };
+    if SIMULATION.set(simulation).is_err() {
+        log!("Simulation already running, ignoring the new scenario");
+    }
+}
+
+pub fn is_active() -> bool {
+    SIMULATION.get().is_some()
+}
+
+// Devices are named like in config.json, whether or not their paths were resolved
+fn key(name: &str, input: &str) -> String {
+    let file = Path::new(input).file_name().map_or(input.into(), |file| file.to_string_lossy());
+    format!("{}/{}", name, file)
+}
+
+/// Key naming the sensor of a fan in scenarios and recordings: `sensor_name/sensor_input`
+/// for hwmon, `thermal_zone/<zone>`, `remote/<url>` or `command/<cmd>` otherwise.
+/// Inputs of combined sensors have their own keys.
+pub fn sensor_key(fan: &FanConfig) -> String {
+    match &fan.sensor {
+        None => key(&fan.sensor_name, &fan.sensor_input),
+        Some(SensorSource::ThermalZone { zone }) => format!("thermal_zone/{}", zone),
+        Some(SensorSource::Sysctl { name }) => format!("sysctl/{}", name),
+        Some(SensorSource::HottestDrive) => "hottest_drive".to_string(),
+        Some(SensorSource::Remote { url, .. }) => format!("remote/{}", url),
+        Some(SensorSource::Command { cmd, .. }) => format!("command/{}", cmd),
+        Some(SensorSource::Weighted { .. }) => "weighted".to_string(),
+        Some(SensorSource::Max { .. }) => "max".to_string(),
+        Some(SensorSource::Virtual { name }) => format!("virtual/{}", name),
+    }
+}
+
+/// Simulated source of a fan's sensor, None outside a simulation
+pub fn temp_source(fan: &FanConfig) -> Option<Box<dyn TempSource>> {
+    let simulation = SIMULATION.get()?;
+    Some(Box::new(SimulatedTemp { simulation, key: sensor_key(fan) }))
+}
+
+/// Simulated output of a fan, None outside a simulation
+pub fn pwm_sink(fan: &FanConfig) -> Option<Box<dyn PwmSink>> {
+    let simulation = SIMULATION.get()?;
+    Some(Box::new(SimulatedPwm { simulation, key: key(&fan.pwm_name, &fan.pwm_input) }))
+}
+
+impl Simulation {
+    fn temp_at(&self, trace: &[TracePoint]) -> Option<f64> {
+        let first = trace.first()?;
+        let last = trace.last()?;
+        let mut at = self.started.elapsed().as_secs_f64();
+        if self.repeat && last.at_secs > 0.0 {
+            at %= last.at_secs;
+        }
+
+        if at <= first.at_secs {
+            return Some(first.temp);
+        }
+        trace.windows(2)
+            .find(|pair| at >= pair[0].at_secs && at <= pair[1].at_secs)
+            .map(|pair| {
+                let span = pair[1].at_secs - pair[0].at_secs;
+                let offset = if span > 0.0 { (at - pair[0].at_secs) / span } else { 1.0 };
+                pair[0].temp + offset * (pair[1].temp - pair[0].temp)
+            })
+            .or(Some(last.temp))
+    }
+
+    fn output(&self, key: &str) -> Output {
+        let outputs = self.outputs.lock().unwrap_or_else(PoisonError::into_inner);
+        outputs.get(key).copied().unwrap_or_default()
+    }
+
+    fn update_output(&self, key: &str, update: impl FnOnce(&mut Output)) {
+        let mut outputs = self.outputs.lock().unwrap_or_else(PoisonError::into_inner);
+        update(outputs.entry(key.to_string()).or_default());
+    }
+}
+
+struct SimulatedTemp {
+    simulation: &'static Simulation,
+    key: String,
+}
+
+impl TempSource for SimulatedTemp {
+    fn read_temp(&self) -> io::Result<i32> {
+        self.simulation.sensors.get(&self.key)
+            .and_then(|trace| self.simulation.temp_at(trace))
+            .map(|temp| temp.round() as i32)
+            .ok_or_else(|| io::Error::other(format!("No temperature trace for {} in the scenario", self.key)))
+    }
+}
+
+struct SimulatedPwm {
+    simulation: &'static Simulation,
+    key: String,
+}
+
+impl PwmSink for SimulatedPwm {
+    fn set_power(&self, power: u8) -> io::Result<()> {
+        self.simulation.update_output(&self.key, |output| output.power = power.min(100));
+        Ok(())
+    }
+
+    fn read_power(&self) -> Option<u8> {
+        Some(self.simulation.output(&self.key).power)
+    }
+
+    fn read_rpm(&self) -> Option<u32> {
+        let max_rpm = self.simulation.max_rpm.get(&self.key)?;
+        Some(max_rpm * self.simulation.output(&self.key).power as u32 / 100)
+    }
+
+    fn is_manual(&self) -> bool {
+        self.simulation.output(&self.key).manual
+    }
+
+    fn set_manual(&self, manual: bool) -> io::Result<()> {
+        self.simulation.update_output(&self.key, |output| output.manual = manual);
+        Ok(())
+    }
+
+    fn needs_retry(&self) -> bool {
+        false
+    }
+
+    fn control_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
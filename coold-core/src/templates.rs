@@ -0,0 +1,56 @@
+use serde::Serialize;
+use crate::daemon::FanStep;
+
+/// Built-in curve preset, a starting point for new fans
+#[derive(Debug, Serialize)]
+pub struct CurveTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub steps: &'static [FanStep],
+}
+
+pub const CURVE_TEMPLATES: &[CurveTemplate] = &[
+    CurveTemplate {
+        name: "silent",
+        description: "Quiet at idle and under light load, ramps up late",
+        steps: &[
+            FanStep { temp: 40, power: 20 },
+            FanStep { temp: 60, power: 35 },
+            FanStep { temp: 75, power: 60 },
+            FanStep { temp: 85, power: 100 },
+        ],
+    },
+    CurveTemplate {
+        name: "balanced",
+        description: "Steady ramp between noise and temperatures",
+        steps: &[
+            FanStep { temp: 35, power: 25 },
+            FanStep { temp: 50, power: 40 },
+            FanStep { temp: 65, power: 65 },
+            FanStep { temp: 80, power: 100 },
+        ],
+    },
+    CurveTemplate {
+        name: "aggressive",
+        description: "Keeps temperatures low, ramps up early",
+        steps: &[
+            FanStep { temp: 30, power: 40 },
+            FanStep { temp: 45, power: 60 },
+            FanStep { temp: 60, power: 85 },
+            FanStep { temp: 70, power: 100 },
+        ],
+    },
+    CurveTemplate {
+        name: "server",
+        description: "High floor for headless machines where noise doesn't matter",
+        steps: &[
+            FanStep { temp: 30, power: 50 },
+            FanStep { temp: 50, power: 70 },
+            FanStep { temp: 65, power: 100 },
+        ],
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static CurveTemplate> {
+    CURVE_TEMPLATES.iter().find(|template| template.name == name)
+}
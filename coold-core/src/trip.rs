@@ -0,0 +1,104 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::log;
+
+/// Seconds between two runs of a trip point's command, by default
+const DEFAULT_COOLDOWN_SECS: u64 = 60;
+
+/// A temperature on one or every fan at which a command is run, e.g. to throttle
+/// a workload, and optionally another once the temperature is back below it
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct TripPoint {
+    pub name: String,
+    /// Fan whose temperature is watched, every fan when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan: Option<String>,
+    /// Trips at or above this temperature (°C)
+    pub temp: i32,
+    /// Run through `sh -c` when tripping
+    pub command: String,
+    /// Run through `sh -c` when the temperature is back below `temp`, once tripped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear_command: Option<String>,
+    /// Seconds the temperature has to stay at or above `temp` before tripping
+    #[serde(default)]
+    pub for_secs: u64,
+    /// Seconds before `command` may run again, defaults to 60
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+}
+
+// State of a (trip point, fan) pair
+#[derive(Default)]
+struct Trip {
+    // When the temperature went at or above the trip point, while it stays there
+    above_since: Option<Instant>,
+    tripped: bool,
+    last_run: Option<Instant>,
+}
+
+/// Tracks each trip point on each fan, running their commands
+#[derive(Default)]
+pub struct TripTracker {
+    trips: HashMap<(String, String), Trip>,
+}
+
+impl TripTracker {
+    /// Feed a fan's filtered temperature, running the commands of the trip points it crosses
+    pub fn observe(&mut self, trip_points: &[TripPoint], fan: &str, temp: i32) {
+        // Forget trip points that were removed or renamed
+        self.trips.retain(|(name, trip_fan), _| trip_fan != fan || trip_points.iter().any(|trip| &trip.name == name));
+
+        for trip_point in trip_points.iter().filter(|trip| trip.fan.as_deref().is_none_or(|f| f == fan)) {
+            let trip = self.trips.entry((trip_point.name.clone(), fan.to_string())).or_default();
+            if temp >= trip_point.temp {
+                let above_since = *trip.above_since.get_or_insert_with(Instant::now);
+                let cooldown = Duration::from_secs(trip_point.cooldown_secs.unwrap_or(DEFAULT_COOLDOWN_SECS));
+                let cooled_down = trip.last_run.is_none_or(|last_run| last_run.elapsed() >= cooldown);
+                if !trip.tripped && cooled_down && above_since.elapsed() >= Duration::from_secs(trip_point.for_secs) {
+                    log!("Trip point {}: {} at {}°C, running {}", trip_point.name, fan, temp, trip_point.command);
+                    run(trip_point, &trip_point.command, fan, temp, "tripped");
+                    trip.tripped = true;
+                    trip.last_run = Some(Instant::now());
+                }
+            } else {
+                trip.above_since = None;
+                if trip.tripped {
+                    trip.tripped = false;
+                    log!("Trip point {}: {} back below {}°C at {}°C", trip_point.name, fan, trip_point.temp, temp);
+                    if let Some(command) = &trip_point.clear_command {
+                        run(trip_point, command, fan, temp, "cleared");
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Commands run in the background, so a slow script can't hold up the control loop
+fn run(trip_point: &TripPoint, command: &str, fan: &str, temp: i32, event: &str) {
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("COOLD_TRIP", &trip_point.name)
+        .env("COOLD_EVENT", event)
+        .env("COOLD_FAN", fan)
+        .env("COOLD_TEMP", temp.to_string())
+        .env("COOLD_TRIP_TEMP", trip_point.temp.to_string())
+        .spawn();
+    match result {
+        Ok(mut child) => {
+            let name = trip_point.name.clone();
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => log!("Trip point {}: command exited with {}", name, status),
+                Ok(_) => {}
+                Err(e) => log!("Trip point {}: failed to wait for the command: {}", name, e),
+            });
+        }
+        Err(e) => log!("Trip point {}: failed to run {}: {}", trip_point.name, command, e),
+    }
+}
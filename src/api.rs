@@ -1,74 +1,131 @@
-use actix_web::{web, App, HttpServer, HttpResponse, Responder, Result};
-use actix_web::middleware::Logger;
-use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex, RwLock};
-use crate::daemon::{Config, FanConfig, FanStep, FanController, save_config, enumerate_hwmon_devices};
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpServer, HttpResponse, Responder, Result};
+use actix_web::http::header;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::{from_fn, Logger, Next};
+use futures_util::stream;
+use std::collections::HashMap;
+use coold_core::daemon::{ApplyError, Config, ControlCommand, DaemonState, FanConfig, FanState, FanStats, NightMode, SensorSource, FanController, config_schema, enumerate_hwmon_devices, read_hwmon_sensors, resolve_fan_paths};
+use coold_core::api_types::{
+    AddFanRequest, ApiResponse, AuditEntry, AuditQuery, BoostQuery, DetectRequest, DeviceQuery, FanFilter, FanStatus, Health, LogsQuery,
+    NightModeRequest, OverrideFanRequest, PreviewPoint, PreviewQuery, TestAlertsRequest, UpdateCurveRequest, UpdateFanRequest,
+    REQUEST_ID_HEADER,
+};
+use crate::audit;
+use crate::auth::{ApiTokens, Role};
+use coold_core::backend;
+use coold_core::logging;
+use coold_core::detect;
+use coold_core::simulation;
+use coold_core::templates;
+use coold_core::log;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ApiResponse<T> {
-    success: bool,
-    message: String,
-    data: Option<T>,
+/// `FanController` is internally synchronized, so handlers share it directly and
+/// read endpoints run concurrently
+pub struct ApiState {
+    controller: FanController,
+    tokens: ApiTokens,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FanStatus {
-    name: String,
-    temperature: Option<i32>,
-    power: Option<u8>,
-    sensor_input: String,
-    pwm_input: String,
-    steps: Vec<FanStep>,
+impl ApiState {
+    pub fn new(controller: FanController, tokens: ApiTokens) -> Self {
+        Self { controller, tokens }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateFanRequest {
-    steps: Vec<FanStep>,
-}
+// Name of the token a request was authorized with, for the audit log
+#[derive(Clone)]
+struct TokenName(String);
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AddFanRequest {
-    sensor_name: String,
-    sensor_input: String,
-    pwm_name: String,
-    pwm_input: String,
-    steps: Vec<FanStep>,
+// ID of the call being handled, logged and returned to the client
+#[derive(Clone)]
+struct RequestId(String);
+
+// Handlers take the ID to pass it on to the control loop
+impl actix_web::FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = std::future::Ready<Result<Self>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let id = req.extensions().get::<RequestId>().cloned();
+        std::future::ready(Ok(id.unwrap_or_else(|| RequestId(new_request_id()))))
+    }
 }
 
-pub struct ApiState {
-    controller: Arc<Mutex<FanController>>,
+static REQUEST_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// Random-looking 16 hex digits, unique within the daemon's lifetime
+pub fn new_request_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let count = REQUEST_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(count);
+    format!("{:016x}", hasher.finish())
 }
 
-impl ApiState {
-    pub fn new(controller: FanController) -> Self {
-        Self {
-            controller: Arc::new(Mutex::new(controller)),
-        }
-    }
+pub fn valid_request_id(id: &str) -> bool {
+    (1..=64).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
 }
 
-pub async fn start_api(controller: FanController, port: u16) -> std::io::Result<()> {
-    let state = web::Data::new(ApiState::new(controller));
+pub async fn start_api(controller: FanController, tokens: ApiTokens, port: u16) -> std::io::Result<()> {
+    let state = web::Data::new(ApiState::new(controller, tokens));
     
-    println!("Starting REST API server on port {}", port);
+    log!("Starting REST API server on port {}", port);
     
     HttpServer::new(move || {
         App::new()
             .app_data(state.clone())
-            .wrap(Logger::default())
+            .wrap(from_fn(assign_request_id))
+            .wrap(Logger::new(r#"%a "%r" %s %b %Dms request %{x-request-id}o"#))
+            .route("/", web::get().to(dashboard))
+            .route("/healthz", web::get().to(healthz))
             .service(
                 web::scope("/api/v1")
+                    .wrap(from_fn(authorize))
+                    .wrap(from_fn(audit_mutations))
                     .route("/status", web::get().to(get_status))
+                    .route("/health", web::get().to(get_health))
                     .route("/config", web::get().to(get_config))
                     .route("/config", web::put().to(update_config))
+                    .route("/config/save", web::post().to(save_config_now))
+                    .route("/config/diff", web::post().to(diff_config))
+                    .route("/config/schema", web::get().to(get_config_schema))
+                    .route("/config/fancontrol", web::get().to(get_fancontrol_config))
                     .route("/fans", web::get().to(get_fans))
                     .route("/fans/{name}", web::get().to(get_fan))
                     .route("/fans/{name}", web::put().to(update_fan))
                     .route("/fans/{name}", web::delete().to(delete_fan))
                     .route("/fans", web::post().to(add_fan))
+                    .route("/curves", web::get().to(get_curves))
+                    .route("/curves/{name}", web::get().to(get_curve))
+                    .route("/curves/{name}", web::put().to(update_curve))
+                    .route("/curves/{name}", web::delete().to(delete_curve))
+                    .route("/curve_templates", web::get().to(get_curve_templates))
+                    .route("/fans/{name}/override", web::put().to(override_fan))
+                    .route("/fans/{name}/override", web::delete().to(clear_fan_override))
+                    .route("/fans/{name}/boost", web::post().to(boost_fan))
+                    .route("/fans/{name}/preview", web::get().to(preview_fan))
+                    .route("/power_cap/lift", web::post().to(lift_power_cap))
+                    .route("/power_cap/restore", web::post().to(restore_power_cap))
+                    .route("/night_mode", web::put().to(update_night_mode))
+                    .route("/pause", web::post().to(pause_daemon))
+                    .route("/resume", web::post().to(resume_daemon))
                     .route("/stop", web::post().to(stop_daemon))
                     .route("/start", web::post().to(start_daemon))
+                    .route("/alerts/test", web::post().to(test_alerts))
+                    .route("/stats", web::get().to(get_stats))
+                    .route("/stats/reset", web::post().to(reset_stats))
+                    .route("/metrics", web::get().to(get_metrics))
                     .route("/hwmon_devices", web::get().to(get_hwmon_devices))
+                    .route("/hwmon_devices/rescan", web::post().to(rescan_hwmon_devices))
+                    .route("/hwmon_devices/{name}", web::get().to(get_hwmon_device))
+                    .route("/sensors", web::get().to(get_sensors))
+                    .route("/pwms", web::get().to(get_pwms))
+                    .route("/detect", web::post().to(detect_fans))
+                    .route("/audit", web::get().to(get_audit))
+                    .route("/logs", web::get().to(get_logs))
             )
     })
     .bind(("127.0.0.1", port))?
@@ -76,47 +133,338 @@ pub async fn start_api(controller: FanController, port: u16) -> std::io::Result<
     .await
 }
 
-async fn get_status(state: web::Data<ApiState>) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
-    let config = controller.get_config();
+// Tag every call with an ID, in the logs, the audit log and the response headers
+async fn assign_request_id(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>> {
+    let id = req.headers().get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+    req.extensions_mut().insert(RequestId(id.clone()));
+    
+    let mut res = next.call(req).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    if res.status().is_server_error() {
+        log!("Request {} failed: {} {} -> {}", id, res.request().method(), res.request().path(), res.status());
+    }
+    Ok(res)
+}
+
+// Record every call that isn't a plain read, with its body, once it has been answered
+async fn audit_mutations(mut req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>> {
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        return next.call(req).await;
+    }
+    
+    // Read the body, then hand it back to the handler
+    let body = req.extract::<web::Bytes>().await?;
+    let request = serde_json::from_slice(&body).ok();
+    req.set_payload(actix_web::dev::Payload::from(
+        Box::pin(stream::once(async move { Ok(body) })) as std::pin::Pin<Box<_>>
+    ));
+    let client = req.connection_info().realip_remote_addr().unwrap_or("unknown").to_string();
+    let (method, path) = (req.method().to_string(), req.uri().to_string());
+    
+    let res = next.call(req).await?;
+    let token = res.request().extensions().get::<TokenName>().map(|TokenName(name)| name.clone());
+    let request_id = res.request().extensions().get::<RequestId>().map(|RequestId(id)| id.clone());
+    audit::record(AuditEntry {
+        timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        client,
+        token,
+        request_id,
+        method,
+        path,
+        request,
+        status: res.status().as_u16(),
+    });
+    Ok(res)
+}
+
+// With tokens configured, every call needs one, and only admin tokens may change anything
+async fn authorize(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>> {
+    let Some(state) = req.app_data::<web::Data<ApiState>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if !state.tokens.is_enabled() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    
+    let token = req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| state.tokens.find(token.trim()));
+    let read_only = req.method() == Method::GET || req.method() == Method::HEAD;
+    let rejection = match token {
+        None => Some(HttpResponse::Unauthorized().json(ApiResponse::<()> {
+            success: false,
+            message: "Missing or invalid API token".to_string(),
+            data: None,
+        })),
+        Some(token) if token.role == Role::ReadOnly && !read_only => Some(HttpResponse::Forbidden().json(ApiResponse::<()> {
+            success: false,
+            message: format!("Token '{}' is read-only", token.name),
+            data: None,
+        })),
+        Some(_) => None,
+    };
+    if let Some(token) = token {
+        req.extensions_mut().insert(TokenName(token.name.clone()));
+    }
+    
+    match rejection {
+        Some(response) => Ok(req.into_response(response).map_into_right_body()),
+        None => Ok(next.call(req).await?.map_into_left_body()),
+    }
+}
+
+// Single-page dashboard polling /status, with curve editing
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+async fn dashboard() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(DASHBOARD_HTML)
+}
+
+fn health(controller: &FanController) -> Health {
+    Health {
+        state: controller.daemon_state(),
+        running: controller.get_running().load(std::sync::atomic::Ordering::SeqCst),
+        stalled: controller.stalled_fans(),
+        problems: controller.fan_problems(),
+    }
+}
+
+// One line summing up the problems of the degraded fans
+fn describe_problems(health: &Health) -> String {
+    health.problems.iter()
+        .map(|(fan, problems)| format!("{} ({})", fan, problems.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+async fn healthz(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let health = health(&state.controller);
+    
+    // Fans that can't be read or written still run the control loop, only stuck
+    // loops and a stopping daemon fail the probe
+    if health.running && health.stalled.is_empty() {
+        let message = match health.state {
+            DaemonState::Degraded => format!("Degraded: {}", describe_problems(&health)),
+            _ => "Healthy".to_string(),
+        };
+        Ok(HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message,
+            data: Some(health),
+        }))
+    } else {
+        let message = if health.running {
+            format!("Control loop stuck for {}", health.stalled.join(", "))
+        } else {
+            "Daemon stopping".to_string()
+        };
+        Ok(HttpResponse::ServiceUnavailable().json(ApiResponse {
+            success: false,
+            message,
+            data: Some(health),
+        }))
+    }
+}
+
+/// Daemon state and fan problems, answered with 200 whatever the state for API clients
+async fn get_health(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let health = health(&state.controller);
+    let message = match health.state {
+        DaemonState::Degraded => format!("Daemon degraded: {}", describe_problems(&health)),
+        state => format!("Daemon {}", state.as_str()),
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message,
+        data: Some(health),
+    }))
+}
+
+/// Status of the fans matching `filter`, reading their sensors and outputs, which may block
+pub fn fan_statuses(controller: &FanController, filter: &FanFilter) -> Vec<FanStatus> {
+    let (config, fan_states) = (controller.get_config(), controller.get_fan_states());
     
     let mut fan_statuses = Vec::new();
     
-    for (name, fan) in &config.fan {
-        // Try to read current temperature
-        let temperature = std::fs::read_to_string(&fan.sensor_input)
-            .ok()
-            .and_then(|content| content.trim().parse::<i32>().ok())
-            .map(|temp| temp / 1000);
-        
-        // Try to read current power
-        let power = std::fs::read_to_string(&fan.pwm_input)
-            .ok()
-            .and_then(|content| content.trim().parse::<u32>().ok())
-            .map(|pwm| (pwm * 100 / 255) as u8);
+    for (name, fan) in config.controlled_fans().iter().filter(|(_, fan)| filter.matches(fan)) {
+        // Try to read current temperature and power
+        let temperature = backend::temp_source(fan).read_temp().ok();
+        let power = backend::pwm_sink(fan).read_power();
+        let fan_state = fan_states.get(name).cloned().unwrap_or_default();
         
         fan_statuses.push(FanStatus {
             name: name.clone(),
             temperature,
+            predicted_temperature: fan_state.predicted_temperature,
             power,
             sensor_input: fan.sensor_input.clone(),
             pwm_input: fan.pwm_input.clone(),
             steps: fan.steps.clone(),
+            control_reasserts: fan_state.control_reasserts,
+            override_power: fan_state.override_power,
+            boost_remaining_secs: fan_state.boost_until
+                .map(|until| until.saturating_duration_since(std::time::Instant::now()).as_secs()),
+            critical: fan_state.critical,
+            rpm: fan_state.rpm,
+            expected_rpm: fan_state.expected_rpm,
+            target_rpm: fan_state.target_rpm,
+            failing: fan_state.failing,
+            sensor_offset: fan.sensor_offset,
+            sensor_scale: fan.sensor_scale,
+            group: fan.group.clone(),
+            demand: fan_state.demand,
+            max_power: fan.max_power,
+            capped: fan_state.capped,
+            semi_passive: fan_state.semi_passive,
+            stalled: fan_state.stalled,
+            owned: fan_state.owned,
+            hardware_curve: fan_state.hardware_curve,
+            pwm_enable: fan_state.pwm_enable,
+            last_write_error: fan_state.last_write_error.clone(),
+            write_errors: fan_state.write_errors,
+            read_errors: fan_state.read_errors,
+            self_test_error: fan_state.self_test_error.clone(),
+            problems: fan_state.problems(),
         });
     }
+    fan_statuses
+}
+
+async fn get_status(state: web::Data<ApiState>, filter: web::Query<FanFilter>) -> Result<impl Responder> {
+    // Sensor reads may block (e.g. remote sources), keep them off the async workers
+    let controller = state.controller.clone();
+    let fan_statuses = web::block(move || fan_statuses(&controller, &filter)).await?;
     
+    let message = match state.controller.daemon_state() {
+        DaemonState::Running => "Status retrieved successfully".to_string(),
+        daemon_state => format!("Status retrieved successfully, daemon {}", daemon_state.as_str()),
+    };
     let response = ApiResponse {
         success: true,
-        message: "Status retrieved successfully".to_string(),
+        message,
         data: Some(fan_statuses),
     };
     
     Ok(HttpResponse::Ok().json(response))
 }
 
+async fn test_alerts(state: web::Data<ApiState>, test_data: Option<web::Json<TestAlertsRequest>>) -> Result<impl Responder> {
+    let controller = state.controller.clone();
+    let rule = test_data.and_then(|data| data.into_inner().rule);
+    
+    // Webhooks are retried with a backoff, keep them off the async workers
+    let results = web::block(move || controller.test_alerts(rule.as_deref())).await?;
+    
+    let response = if results.is_empty() {
+        ApiResponse {
+            success: false,
+            message: "No matching alert webhooks configured".to_string(),
+            data: Some(results),
+        }
+    } else {
+        let delivered = results.iter().filter(|result| result.success).count();
+        ApiResponse {
+            success: delivered == results.len(),
+            message: format!("Test alert delivered to {} of {} webhooks", delivered, results.len()),
+            data: Some(results),
+        }
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_stats(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = &state.controller;
+    
+    let response = ApiResponse {
+        success: true,
+        message: "Statistics retrieved successfully".to_string(),
+        data: Some(controller.get_stats()),
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Label value of a Prometheus sample
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Readings and control loop metrics in the Prometheus text format
+async fn get_metrics(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = &state.controller;
+    let stats = controller.get_stats();
+    let fan_states = controller.get_fan_states();
+    let mut names: Vec<&String> = fan_states.keys().chain(stats.fans.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    type Value = fn(Option<&FanState>, Option<&FanStats>) -> Option<f64>;
+    let metrics: [(&str, &str, &str, Value); 9] = [
+        ("coold_fan_temperature_celsius", "gauge", "Temperature of the fan's sensor", |state, _| state?.temperature.map(f64::from)),
+        ("coold_fan_power_percent", "gauge", "Power the fan was last set to", |state, _| state?.power.map(f64::from)),
+        ("coold_fan_rpm", "gauge", "Speed read from the fan's tachometer", |state, _| state?.rpm.map(f64::from)),
+        ("coold_cycles_total", "counter", "Control cycles completed", |_, stats| Some(stats?.cycles as f64)),
+        ("coold_cycle_duration_seconds", "gauge", "Duration of the last control cycle", |_, stats| Some(stats?.last_cycle_ms / 1000.0)),
+        ("coold_cycle_duration_seconds_avg", "gauge", "Average duration of the control cycles", |_, stats| Some(stats?.avg_cycle_ms / 1000.0)),
+        ("coold_cycle_duration_seconds_max", "gauge", "Longest control cycle", |_, stats| Some(stats?.max_cycle_ms / 1000.0)),
+        ("coold_sensor_read_failures_total", "counter", "Sensor reads that failed", |_, stats| Some(stats?.read_failures as f64)),
+        ("coold_pwm_write_failures_total", "counter", "Power writes that failed", |_, stats| Some(stats?.write_failures as f64)),
+    ];
+    let mut body = String::new();
+    for (metric, kind, help, value) in metrics {
+        body.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", metric, help, metric, kind));
+        for name in &names {
+            if let Some(value) = value(fan_states.get(*name), stats.fans.get(*name)) {
+                body.push_str(&format!("{}{{fan=\"{}\"}} {}\n", metric, escape_label(name), value));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(body))
+}
+
+async fn reset_stats(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = &state.controller;
+    controller.reset_stats();
+    
+    let response: ApiResponse<()> = ApiResponse {
+        success: true,
+        message: "Statistics reset successfully".to_string(),
+        data: None,
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn config_etag(revision: u64) -> String {
+    format!("\"{}\"", revision)
+}
+
+// Revision named by an If-Match header: Some(None) for "*", None if absent or unparsable
+fn if_match_revision(req: &HttpRequest) -> Option<Option<u64>> {
+    let value = req.headers().get(header::IF_MATCH)?.to_str().ok()?.trim();
+    if value == "*" {
+        return Some(None);
+    }
+    let value = value.strip_prefix("W/").unwrap_or(value);
+    value.trim_matches('"').parse::<u64>().ok().map(Some)
+}
+
 async fn get_config(state: web::Data<ApiState>) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
-    let config = controller.get_config().clone();
+    let controller = &state.controller;
+    let (config, revision) = controller.get_config_with_revision();
     
     let response = ApiResponse {
         success: true,
@@ -124,44 +472,170 @@ async fn get_config(state: web::Data<ApiState>) -> Result<impl Responder> {
         data: Some(config),
     };
     
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, config_etag(revision)))
+        .json(response))
+}
+
+// Served as is rather than in an ApiResponse, so editors and validators can point at the URL
+async fn get_config_schema() -> impl Responder {
+    HttpResponse::Ok().content_type("application/schema+json").json(config_schema())
+}
+
+async fn get_fancontrol_config(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let export = state.controller.export_fancontrol();
+    let response = ApiResponse {
+        success: true,
+        message: match export.skipped.len() {
+            0 => "Every fan exported".to_string(),
+            skipped => format!("{} fans can't be driven by fancontrol and were left out", skipped),
+        },
+        data: Some(export),
+    };
     Ok(HttpResponse::Ok().json(response))
 }
 
 async fn update_config(
     state: web::Data<ApiState>,
+    req: HttpRequest,
     new_config: web::Json<Config>,
 ) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
-    controller.update_config(new_config.into_inner());
+    let controller = &state.controller;
     
-    // Save to file
-    let config = controller.get_config().clone();
-    if let Err(e) = save_config(&config) {
+    // Optimistic concurrency: the client must prove it edited the current revision
+    let Some(expected_revision) = if_match_revision(&req) else {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "If-Match header with the configuration ETag is required".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::PreconditionRequired().json(response));
+    };
+    
+    let missing_curves = new_config.missing_curves();
+    if !missing_curves.is_empty() {
         let response = ApiResponse::<()> {
             success: false,
-            message: format!("Failed to save configuration: {}", e),
+            message: format!("Undefined curves: {}", missing_curves.join(", ")),
             data: None,
         };
-        return Ok(HttpResponse::InternalServerError().json(response));
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    let missing_sensors = new_config.missing_sensors();
+    if !missing_sensors.is_empty() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Undefined virtual sensors: {}", missing_sensors.join(", ")),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    let missing_leaders = new_config.missing_leaders();
+    if !missing_leaders.is_empty() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Followed fans not found: {}", missing_leaders.join(", ")),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    // Taking over the outputs may wait on slow sysfs writes
+    let apply_controller = controller.clone();
+    let result = web::block(move || apply_controller.apply_config(expected_revision, new_config.into_inner())).await?;
+    match result {
+        Ok(()) => {}
+        Err(ApplyError::Modified(current_revision)) => {
+            let response = ApiResponse::<()> {
+                success: false,
+                message: "Configuration was modified by someone else, fetch it again and retry".to_string(),
+                data: None,
+            };
+            return Ok(HttpResponse::PreconditionFailed()
+                .insert_header((header::ETAG, config_etag(current_revision)))
+                .json(response));
+        }
+        Err(ApplyError::FansFailed(failures)) => {
+            let fans: Vec<&str> = failures.iter().map(|failure| failure.fan.as_str()).collect();
+            let response = ApiResponse {
+                success: false,
+                message: format!("Failed to take over {}, configuration rolled back", fans.join(", ")),
+                data: Some(failures),
+            };
+            return Ok(HttpResponse::UnprocessableEntity().json(response));
+        }
     }
     
     let response = ApiResponse::<()> {
         success: true,
-        message: "Configuration updated and saved successfully".to_string(),
+        message: "Configuration updated successfully".to_string(),
         data: None,
     };
     
-    Ok(HttpResponse::Ok().json(response))
+    let (_, revision) = controller.get_config_with_revision();
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, config_etag(revision)))
+        .json(response))
 }
 
-async fn get_fans(state: web::Data<ApiState>) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
+async fn diff_config(state: web::Data<ApiState>, candidate: web::Json<Config>) -> Result<impl Responder> {
+    let (diff, revision) = state.controller.diff_config(&candidate);
+    let message = if diff.is_empty() {
+        "No changes".to_string()
+    } else {
+        format!("{} changes", diff.len())
+    };
+    let response = ApiResponse {
+        success: true,
+        message,
+        data: Some(diff),
+    };
+    // Applying with this ETag fails if the configuration changes after the diff
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, config_etag(revision)))
+        .json(response))
+}
+
+async fn save_config_now(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = &state.controller;
+    
+    match controller.save_config_now() {
+        Ok(saved) => {
+            let response = ApiResponse::<()> {
+                success: true,
+                message: if saved {
+                    "Configuration saved successfully".to_string()
+                } else {
+                    "Configuration already up to date".to_string()
+                },
+                data: None,
+            };
+            Ok(HttpResponse::Ok().json(response))
+        }
+        Err(e) => {
+            let response = ApiResponse::<()> {
+                success: false,
+                message: format!("Failed to save configuration: {}", e),
+                data: None,
+            };
+            Ok(HttpResponse::InternalServerError().json(response))
+        }
+    }
+}
+
+async fn get_fans(state: web::Data<ApiState>, filter: web::Query<FanFilter>) -> Result<impl Responder> {
+    let controller = &state.controller;
     let config = controller.get_config().clone();
+    let fans: HashMap<String, FanConfig> = config.fan.into_iter()
+        .filter(|(_, fan)| filter.matches(fan))
+        .collect();
     
     let response = ApiResponse {
         success: true,
         message: "Fans retrieved successfully".to_string(),
-        data: Some(config.fan),
+        data: Some(fans),
     };
     
     Ok(HttpResponse::Ok().json(response))
@@ -172,7 +646,7 @@ async fn get_fan(
     path: web::Path<String>,
 ) -> Result<impl Responder> {
     let fan_name = path.into_inner();
-    let controller = state.controller.lock().unwrap();
+    let controller = &state.controller;
     let config = controller.get_config().clone();
     
     if let Some(fan) = config.fan.get(&fan_name) {
@@ -198,24 +672,11 @@ async fn update_fan(
     update_data: web::Json<UpdateFanRequest>,
 ) -> Result<impl Responder> {
     let fan_name = path.into_inner();
-    let controller = state.controller.lock().unwrap();
-    let mut config = controller.get_config().clone();
+    let controller = &state.controller;
+    let UpdateFanRequest { steps, cooldown_steps } = update_data.into_inner();
+    let updated = controller.update_fan_steps(&fan_name, steps, cooldown_steps);
     
-    if let Some(fan) = config.fan.get_mut(&fan_name) {
-        fan.steps = update_data.steps.clone();
-        controller.update_config(config);
-        
-        // Save to file
-        let config = controller.get_config().clone();
-        if let Err(e) = save_config(&config) {
-            let response = ApiResponse::<()> {
-                success: false,
-                message: format!("Failed to save configuration: {}", e),
-                data: None,
-            };
-            return Ok(HttpResponse::InternalServerError().json(response));
-        }
-        
+    if updated {
         let response = ApiResponse::<()> {
             success: true,
             message: format!("Fan '{}' updated successfully", fan_name),
@@ -237,23 +698,17 @@ async fn delete_fan(
     path: web::Path<String>,
 ) -> Result<impl Responder> {
     let fan_name = path.into_inner();
-    let controller = state.controller.lock().unwrap();
-    let mut config = controller.get_config().clone();
+    let controller = &state.controller;
+    let removed = controller.remove_fan(&fan_name);
     
-    if config.fan.remove(&fan_name).is_some() {
-        controller.update_config(config);
-        
-        // Save to file
-        let config = controller.get_config().clone();
-        if let Err(e) = save_config(&config) {
-            let response = ApiResponse::<()> {
-                success: false,
-                message: format!("Failed to save configuration: {}", e),
-                data: None,
-            };
-            return Ok(HttpResponse::InternalServerError().json(response));
-        }
-        
+    if removed.is_none() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Fan '{}' is followed by other fans", fan_name),
+            data: None,
+        };
+        Ok(HttpResponse::Conflict().json(response))
+    } else if removed == Some(true) {
         let response = ApiResponse::<()> {
             success: true,
             message: format!("Fan '{}' deleted successfully", fan_name),
@@ -274,34 +729,112 @@ async fn add_fan(
     state: web::Data<ApiState>,
     add_data: web::Json<AddFanRequest>,
 ) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
-    let mut config = controller.get_config().clone();
-    
-    // Generate a unique name for the fan
-    let fan_name = format!("fan_{}", config.fan.len() + 1);
+    let controller = &state.controller;
     
     let new_fan = FanConfig {
         sensor_name: add_data.sensor_name.clone(),
         sensor_input: add_data.sensor_input.clone(),
+        sensor: add_data.sensor.clone(),
+        sensor_device: add_data.sensor_device.clone(),
+        sensor_label: None,
         pwm_name: add_data.pwm_name.clone(),
         pwm_input: add_data.pwm_input.clone(),
+        pwm_device: add_data.pwm_device.clone(),
         steps: add_data.steps.clone(),
+        curve: add_data.curve.clone(),
+        cooldown_steps: add_data.cooldown_steps.clone(),
+        backend: add_data.backend,
+        pwm_period_ns: add_data.pwm_period_ns,
+        valid_temp_range: add_data.valid_temp_range,
+        interval_secs: add_data.interval_secs,
+        critical_temp: add_data.critical_temp,
+        rpm_curve: add_data.rpm_curve.clone(),
+        rpm_tolerance_pct: add_data.rpm_tolerance_pct,
+        rpm_steps: add_data.rpm_steps.clone(),
+        pwm_mode: add_data.pwm_mode,
+        pwm_enable: add_data.pwm_enable,
+        sensor_offset: add_data.sensor_offset,
+        sensor_scale: add_data.sensor_scale,
+        group: add_data.group.clone(),
+        noise_weight: add_data.noise_weight,
+        follows: add_data.follows.clone(),
+        follow_offset: add_data.follow_offset,
+        follow_ratio: add_data.follow_ratio,
+        min_power: add_data.min_power,
+        max_power: add_data.max_power,
+        spin_up: add_data.spin_up,
+        power_step: add_data.power_step,
+        ambient: add_data.ambient.clone(),
+        load_steps: add_data.load_steps.clone(),
+        load_window_secs: add_data.load_window_secs,
+        rise_boost: add_data.rise_boost,
+        predict: add_data.predict,
+        min_dwell_secs: add_data.min_dwell_secs,
+        dead_band: add_data.dead_band,
+        power: add_data.power.clone(),
+        script: add_data.script.clone(),
+        hardware_curve: add_data.hardware_curve,
     };
     
-    config.fan.insert(fan_name.clone(), new_fan);
-    controller.update_config(config);
-    
-    // Save to file
-    let config = controller.get_config().clone();
-    if let Err(e) = save_config(&config) {
+    if new_fan.steps.is_empty() && new_fan.rpm_steps.is_empty() && new_fan.curve.is_none() && new_fan.follows.is_none() && new_fan.power.is_none() && new_fan.script.is_none() {
         let response = ApiResponse::<()> {
             success: false,
-            message: format!("Failed to save configuration: {}", e),
+            message: "Either steps, rpm_steps, curve, power, script or follows is required".to_string(),
             data: None,
         };
-        return Ok(HttpResponse::InternalServerError().json(response));
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    // After dropping root, only the outputs handed over at startup can be written
+    if backend::is_unprivileged() {
+        let mut resolved = new_fan.clone();
+        resolve_fan_paths(&mut resolved);
+        if let Err(e) = backend::check_writable(&resolved) {
+            let response = ApiResponse::<()> {
+                success: false,
+                message: e.to_string(),
+                data: None,
+            };
+            return Ok(HttpResponse::Forbidden().json(response));
+        }
     }
     
+    let fan_name = controller.modify_config(|config| {
+        if let Some(curve) = new_fan.curve.as_ref().filter(|curve| !config.curves.contains_key(*curve)) {
+            return Err(format!("Curve '{}' not found", curve));
+        }
+        let virtual_sensors = [new_fan.sensor.as_ref(), new_fan.ambient.as_ref().and_then(|ambient| ambient.sensor.as_ref())];
+        for source in virtual_sensors.into_iter().flatten() {
+            if let SensorSource::Virtual { name } = source {
+                if !config.sensors.contains_key(name) {
+                    return Err(format!("Virtual sensor '{}' not found", name));
+                }
+            }
+        }
+        if let Some(leader) = new_fan.follows.as_ref().filter(|leader| !config.controlled_fans().contains_key(*leader)) {
+            return Err(format!("Fan '{}' not found", leader));
+        }
+        // Generate a unique name for the fan
+        let fan_name = (config.fan.len() + 1..)
+            .map(|n| format!("fan_{}", n))
+            .find(|name| !config.fan.contains_key(name))
+            .unwrap();
+        config.fan.insert(fan_name.clone(), new_fan);
+        Ok(fan_name)
+    });
+    
+    let fan_name = match fan_name {
+        Ok(fan_name) => fan_name,
+        Err(message) => {
+            let response = ApiResponse::<()> {
+                success: false,
+                message,
+                data: None,
+            };
+            return Ok(HttpResponse::BadRequest().json(response));
+        }
+    };
+    
     let response = ApiResponse {
         success: true,
         message: format!("Fan '{}' added successfully", fan_name),
@@ -311,8 +844,397 @@ async fn add_fan(
     Ok(HttpResponse::Created().json(response))
 }
 
+async fn get_curve_templates() -> Result<impl Responder> {
+    let response = ApiResponse {
+        success: true,
+        message: "Curve templates retrieved successfully".to_string(),
+        data: Some(templates::CURVE_TEMPLATES),
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_curves(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = &state.controller;
+    let config = controller.get_config();
+    
+    let response = ApiResponse {
+        success: true,
+        message: "Curves retrieved successfully".to_string(),
+        data: Some(config.curves),
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_curve(
+    state: web::Data<ApiState>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let curve_name = path.into_inner();
+    let controller = &state.controller;
+    let config = controller.get_config();
+    
+    if let Some(steps) = config.curves.get(&curve_name) {
+        let response = ApiResponse {
+            success: true,
+            message: "Curve retrieved successfully".to_string(),
+            data: Some(steps.clone()),
+        };
+        Ok(HttpResponse::Ok().json(response))
+    } else {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Curve '{}' not found", curve_name),
+            data: None,
+        };
+        Ok(HttpResponse::NotFound().json(response))
+    }
+}
+
+async fn update_curve(
+    state: web::Data<ApiState>,
+    path: web::Path<String>,
+    update_data: web::Json<UpdateCurveRequest>,
+) -> Result<impl Responder> {
+    let curve_name = path.into_inner();
+    let controller = &state.controller;
+    
+    if update_data.steps.is_empty() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "A curve needs at least one step".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    // Creates the curve if needed; every fan using it picks the change up
+    let created = controller.modify_config(|config| {
+        config.curves.insert(curve_name.clone(), update_data.steps.clone()).is_none()
+    });
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: format!("Curve '{}' {} successfully", curve_name, if created { "created" } else { "updated" }),
+        data: None,
+    };
+    if created {
+        Ok(HttpResponse::Created().json(response))
+    } else {
+        Ok(HttpResponse::Ok().json(response))
+    }
+}
+
+async fn delete_curve(
+    state: web::Data<ApiState>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let curve_name = path.into_inner();
+    let controller = &state.controller;
+    
+    let result = controller.modify_config(|config| {
+        if !config.curves.contains_key(&curve_name) {
+            return Err(format!("Curve '{}' not found", curve_name));
+        }
+        if config.uses_curve(&curve_name) {
+            return Err(format!("Curve '{}' is used by fans or zones", curve_name));
+        }
+        config.curves.remove(&curve_name);
+        Ok(())
+    });
+    
+    match result {
+        Ok(()) => {
+            let response = ApiResponse::<()> {
+                success: true,
+                message: format!("Curve '{}' deleted successfully", curve_name),
+                data: None,
+            };
+            Ok(HttpResponse::Ok().json(response))
+        }
+        Err(message) => {
+            let in_use = controller.get_config().curves.contains_key(&curve_name);
+            let response = ApiResponse::<()> {
+                success: false,
+                message,
+                data: None,
+            };
+            if in_use {
+                Ok(HttpResponse::Conflict().json(response))
+            } else {
+                Ok(HttpResponse::NotFound().json(response))
+            }
+        }
+    }
+}
+
+async fn override_fan(
+    state: web::Data<ApiState>,
+    request_id: RequestId,
+    path: web::Path<String>,
+    override_data: web::Json<OverrideFanRequest>,
+) -> Result<impl Responder> {
+    let fan_name = path.into_inner();
+    let controller = &state.controller;
+    
+    if override_data.power > 100 {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "Power must be between 0 and 100".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    if !controller.get_config().controlled_fans().contains_key(&fan_name) {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Fan '{}' not found", fan_name),
+            data: None,
+        };
+        return Ok(HttpResponse::NotFound().json(response));
+    }
+    
+    controller.send_from(ControlCommand::Override { fan: fan_name.clone(), power: Some(override_data.power) }, &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: format!("Fan '{}' overridden to {}%", fan_name, override_data.power),
+        data: None,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+const DEFAULT_PREVIEW_STEP: u32 = 5;
+const MAX_PREVIEW_POINTS: usize = 1000;
+
+async fn preview_fan(
+    state: web::Data<ApiState>,
+    path: web::Path<String>,
+    query: web::Query<PreviewQuery>,
+) -> Result<impl Responder> {
+    let fan_name = path.into_inner();
+    let bad_request = |message: String| HttpResponse::BadRequest().json(ApiResponse::<()> {
+        success: false,
+        message,
+        data: None,
+    });
+    
+    let Some(fan) = state.controller.get_config().controlled_fans().remove(&fan_name) else {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Fan '{}' not found", fan_name),
+            data: None,
+        };
+        return Ok(HttpResponse::NotFound().json(response));
+    };
+    if let Some(leader) = &fan.follows {
+        return Ok(bad_request(format!("Fan '{}' follows {} and has no curve of its own", fan_name, leader)));
+    }
+    
+    let temps: Vec<i32> = match (query.temp, query.from, query.to) {
+        (Some(temp), None, None) => vec![temp],
+        (None, Some(from), Some(to)) if from <= to => {
+            let step = query.step.unwrap_or(DEFAULT_PREVIEW_STEP).max(1) as usize;
+            // The end of the range is always included
+            let mut temps: Vec<i32> = (from..=to).step_by(step).collect();
+            if temps.last() != Some(&to) {
+                temps.push(to);
+            }
+            temps
+        }
+        _ => return Ok(bad_request("Expected either temp, or from and to with from <= to".to_string())),
+    };
+    if temps.len() > MAX_PREVIEW_POINTS {
+        return Ok(bad_request(format!("At most {} points, use a larger step", MAX_PREVIEW_POINTS)));
+    }
+    
+    let points: Vec<PreviewPoint> = temps.into_iter()
+        .map(|temp| PreviewPoint { temp, power: fan.curve_power(temp, query.cooling) })
+        .collect();
+    let response = ApiResponse {
+        success: true,
+        message: format!("Curve of '{}'", fan_name),
+        data: Some(points),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+const DEFAULT_BOOST_SECS: u64 = 60;
+const MAX_BOOST_SECS: u64 = 3600;
+
+async fn boost_fan(
+    state: web::Data<ApiState>,
+    request_id: RequestId,
+    path: web::Path<String>,
+    query: web::Query<BoostQuery>,
+) -> Result<impl Responder> {
+    let fan_name = path.into_inner();
+    let controller = &state.controller;
+    let seconds = query.seconds.unwrap_or(DEFAULT_BOOST_SECS);
+    
+    if seconds == 0 || seconds > MAX_BOOST_SECS {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Boost must last between 1 and {} seconds", MAX_BOOST_SECS),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    if !controller.get_config().controlled_fans().contains_key(&fan_name) {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Fan '{}' not found", fan_name),
+            data: None,
+        };
+        return Ok(HttpResponse::NotFound().json(response));
+    }
+    
+    controller.send_from(ControlCommand::Boost { fan: fan_name.clone(), duration: std::time::Duration::from_secs(seconds) }, &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: format!("Fan '{}' boosted to 100% for {}s", fan_name, seconds),
+        data: None,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn clear_fan_override(
+    state: web::Data<ApiState>,
+    request_id: RequestId,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let fan_name = path.into_inner();
+    let controller = &state.controller;
+    
+    if !controller.get_config().controlled_fans().contains_key(&fan_name) {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: format!("Fan '{}' not found", fan_name),
+            data: None,
+        };
+        return Ok(HttpResponse::NotFound().json(response));
+    }
+    
+    controller.send_from(ControlCommand::Override { fan: fan_name.clone(), power: None }, &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: format!("Fan '{}' returned to curve control", fan_name),
+        data: None,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn lift_power_cap(state: web::Data<ApiState>, request_id: RequestId) -> Result<impl Responder> {
+    let controller = &state.controller;
+    controller.send_from(ControlCommand::LiftPowerCap(true), &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: "Power caps lifted, fans may exceed their max_power".to_string(),
+        data: None,
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn restore_power_cap(state: web::Data<ApiState>, request_id: RequestId) -> Result<impl Responder> {
+    let controller = &state.controller;
+    controller.send_from(ControlCommand::LiftPowerCap(false), &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: "Power caps restored".to_string(),
+        data: None,
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn update_night_mode(
+    state: web::Data<ApiState>,
+    update_data: web::Json<NightModeRequest>,
+) -> Result<impl Responder> {
+    let controller = &state.controller;
+    
+    if update_data.max_power.is_some_and(|max_power| max_power > 100) {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "Power must be between 0 and 100".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::BadRequest().json(response));
+    }
+    
+    let result = controller.modify_config(|config| {
+        let night_mode = match config.night_mode.take() {
+            Some(current) => NightMode {
+                enabled: update_data.enabled,
+                start: update_data.start.unwrap_or(current.start),
+                end: update_data.end.unwrap_or(current.end),
+                max_power: update_data.max_power.unwrap_or(current.max_power),
+            },
+            None => match (update_data.start, update_data.end, update_data.max_power) {
+                (Some(start), Some(end), Some(max_power)) => NightMode { enabled: update_data.enabled, start, end, max_power },
+                _ => return Err("Night mode isn't configured yet, start, end and max_power are required".to_string()),
+            },
+        };
+        config.night_mode = Some(night_mode.clone());
+        Ok(night_mode)
+    });
+    
+    match result {
+        Ok(night_mode) => {
+            let response = ApiResponse {
+                success: true,
+                message: format!("Night mode {}", if night_mode.enabled { "enabled" } else { "disabled" }),
+                data: Some(night_mode),
+            };
+            Ok(HttpResponse::Ok().json(response))
+        }
+        Err(message) => {
+            let response = ApiResponse::<()> {
+                success: false,
+                message,
+                data: None,
+            };
+            Ok(HttpResponse::BadRequest().json(response))
+        }
+    }
+}
+
+async fn pause_daemon(state: web::Data<ApiState>, request_id: RequestId) -> Result<impl Responder> {
+    let controller = &state.controller;
+    controller.send_from(ControlCommand::Pause(true), &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: "Fan control paused, fans handed back to firmware".to_string(),
+        data: None,
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn resume_daemon(state: web::Data<ApiState>, request_id: RequestId) -> Result<impl Responder> {
+    let controller = &state.controller;
+    controller.send_from(ControlCommand::Pause(false), &request_id.0);
+    
+    let response = ApiResponse::<()> {
+        success: true,
+        message: "Fan control resumed".to_string(),
+        data: None,
+    };
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
 async fn stop_daemon(state: web::Data<ApiState>) -> Result<impl Responder> {
-    let controller = state.controller.lock().unwrap();
+    let controller = &state.controller;
     controller.stop();
     
     let response = ApiResponse::<()> {
@@ -324,7 +1246,7 @@ async fn stop_daemon(state: web::Data<ApiState>) -> Result<impl Responder> {
     Ok(HttpResponse::Ok().json(response))
 }
 
-async fn start_daemon(state: web::Data<ApiState>) -> Result<impl Responder> {
+async fn start_daemon(_state: web::Data<ApiState>) -> Result<impl Responder> {
     // This would require more complex state management to actually restart
     // For now, we'll just return a message
     let response = ApiResponse::<()> {
@@ -337,12 +1259,132 @@ async fn start_daemon(state: web::Data<ApiState>) -> Result<impl Responder> {
 }
 
 // New endpoint to fetch all available hwmon devices (sensors and PWM)
-async fn get_hwmon_devices() -> Result<impl Responder> {
-    let devices = enumerate_hwmon_devices();
+async fn get_hwmon_devices(query: web::Query<DeviceQuery>) -> Result<impl Responder> {
+    // Live readings may block on slow devices, keep them off the async workers
+    let devices = web::block(enumerate_hwmon_devices).await?;
+    let devices: Vec<_> = devices.into_iter()
+        .filter(|device| query.device.as_deref().is_none_or(|name| device.is_named(name)))
+        .collect();
     let response = ApiResponse {
         success: true,
         message: "Hwmon devices enumerated successfully".to_string(),
         data: Some(devices),
     };
     Ok(HttpResponse::Ok().json(response))
-} 
\ No newline at end of file
+}
+
+async fn get_hwmon_device(state: web::Data<ApiState>, path: web::Path<String>) -> Result<impl Responder> {
+    let name = path.into_inner();
+    let controller = state.controller.clone();
+    let lookup = name.clone();
+    let device = web::block(move || controller.get_hwmon_device(&lookup)).await?;
+    
+    match device {
+        Some(device) => Ok(HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: "Hwmon device retrieved successfully".to_string(),
+            data: Some(device),
+        })),
+        None => Ok(HttpResponse::NotFound().json(ApiResponse::<()> {
+            success: false,
+            message: format!("Hwmon device '{}' not found", name),
+            data: None,
+        })),
+    }
+}
+
+async fn get_sensors() -> Result<impl Responder> {
+    let sensors = web::block(read_hwmon_sensors).await?;
+    let response = ApiResponse {
+        success: true,
+        message: format!("{} sensors read", sensors.len()),
+        data: Some(sensors),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn rescan_hwmon_devices(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = state.controller.clone();
+    // Device lookup reads sysfs, keep it off the async workers
+    let report = web::block(move || controller.rescan_devices()).await?;
+    let message = match (report.moved.len(), report.missing.len()) {
+        (0, 0) => "Rescan done, every fan was already at its devices".to_string(),
+        (moved, 0) => format!("Rescan done, {} fans found at new devices", moved),
+        (0, _) => format!("Rescan done, still missing: {}", report.missing.join(", ")),
+        (moved, _) => format!("Rescan done, {} fans found at new devices, still missing: {}", moved, report.missing.join(", ")),
+    };
+    let message = if report.unwritable.is_empty() {
+        message
+    } else {
+        format!("{}; can't write {} without root, restart the daemon", message, report.unwritable.join(", "))
+    };
+    let response = ApiResponse {
+        success: true,
+        message,
+        data: Some(report),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_pwms(state: web::Data<ApiState>) -> Result<impl Responder> {
+    let controller = state.controller.clone();
+    let pwms = web::block(move || controller.get_pwm_outputs()).await?;
+    let response = ApiResponse {
+        success: true,
+        message: format!("{} PWM outputs found", pwms.len()),
+        data: Some(pwms),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_audit(query: web::Query<AuditQuery>) -> Result<impl Responder> {
+    let entries = audit::recent(query.limit.unwrap_or(50));
+    let response = ApiResponse {
+        success: true,
+        message: format!("{} audit entries", entries.len()),
+        data: Some(entries),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_logs(query: web::Query<LogsQuery>) -> Result<impl Responder> {
+    let lines = logging::recent(query.limit.unwrap_or(200));
+    let response = ApiResponse {
+        success: true,
+        message: format!("{} log lines", lines.len()),
+        data: Some(lines),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn detect_fans(state: web::Data<ApiState>, detect_data: Option<web::Json<DetectRequest>>) -> Result<impl Responder> {
+    if simulation::is_active() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "Fan detection needs real hardware, it can't run in a simulation".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::Conflict().json(response));
+    }
+    if backend::is_observe_only() {
+        let response = ApiResponse::<()> {
+            success: false,
+            message: "Fan detection drives the fans, it can't run in observe mode".to_string(),
+            data: None,
+        };
+        return Ok(HttpResponse::Conflict().json(response));
+    }
+    
+    let settle_secs = detect_data.and_then(|data| data.into_inner().settle_secs).unwrap_or(detect::DEFAULT_SETTLE_SECS);
+    let controller = state.controller.clone();
+    // Fans take a few seconds to change speed, keep the waits off the async workers
+    let detected = web::block(move || detect::detect_fans(&controller, std::time::Duration::from_secs(settle_secs))).await?;
+    
+    let found = detected.iter().filter(|output| output.fan_input.is_some()).count();
+    let response = ApiResponse {
+        success: true,
+        message: format!("Found the fan of {} of {} PWM outputs", found, detected.len()),
+        data: Some(detected),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use coold_core::api_types::AuditEntry;
+use coold_core::log;
+
+/// Entries kept in memory for GET /audit
+const RECENT_ENTRIES: usize = 200;
+
+static AUDIT_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+static RECENT: Mutex<VecDeque<AuditEntry>> = Mutex::new(VecDeque::new());
+
+/// Append every entry to `path` from now on, picking up the recent entries already in it
+pub fn start(path: &Path) -> io::Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(io::Error::new(e.kind(), format!("Failed to read audit log {}: {}", path.display(), e))),
+    };
+    // Opened now, while still root, and kept open once privileges are dropped
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to open audit log {}: {}", path.display(), e)))?;
+    if AUDIT_FILE.set(Mutex::new(file)).is_err() {
+        log!("Already auditing, ignoring {}", path.display());
+        return Ok(());
+    }
+
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    for entry in content.lines().filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok()) {
+        push_recent(&mut recent, entry);
+    }
+    Ok(())
+}
+
+fn push_recent(recent: &mut VecDeque<AuditEntry>, entry: AuditEntry) {
+    if recent.len() == RECENT_ENTRIES {
+        recent.pop_front();
+    }
+    recent.push_back(entry);
+}
+
+/// Keep track of an API call, and append it to the audit log if there is one
+pub fn record(entry: AuditEntry) {
+    if let (Some(file), Ok(line)) = (AUDIT_FILE.get(), serde_json::to_string(&entry)) {
+        let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = writeln!(file, "{}", line) {
+            log!("Failed to write audit entry for {} {}: {}", entry.method, entry.path, e);
+        }
+    }
+    push_recent(&mut RECENT.lock().unwrap_or_else(PoisonError::into_inner), entry);
+}
+
+/// The last `limit` entries, oldest first
+pub fn recent(limit: usize) -> Vec<AuditEntry> {
+    let recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    recent.iter().skip(recent.len().saturating_sub(limit)).cloned().collect()
+}
@@ -0,0 +1,59 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What a token may do
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Reads only: status, configuration, statistics...
+    ReadOnly,
+    /// Everything, including configuration changes and overrides
+    Admin,
+}
+
+/// A bearer token accepted by the API
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiToken {
+    /// Who the token was given to, reported in the audit log
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+/// Tokens file given with --tokens-file
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct ApiTokens {
+    pub tokens: Vec<ApiToken>,
+}
+
+impl ApiTokens {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to read tokens file {}: {}", path.display(), e)))?;
+        let tokens: Self = serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid tokens file {}: {}", path.display(), e)))?;
+        if tokens.tokens.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No token in {}", path.display())));
+        }
+        if let Some(token) = tokens.tokens.iter().find(|token| token.token.is_empty()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Empty token for {} in {}", token.name, path.display())));
+        }
+        Ok(tokens)
+    }
+
+    /// Without any token, the API is open to every local client
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    pub fn find(&self, token: &str) -> Option<&ApiToken> {
+        self.tokens.iter().find(|known| constant_time_eq(known.token.as_bytes(), token.as_bytes()))
+    }
+}
+
+// Compare without giving away how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,58 @@
+use coold_core::client::{Client, ClientError};
+use coold_core::daemon::Config;
+use coold_core::api_types::FanFilter;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A part of the bundle, or why the daemon couldn't provide it
+fn part<T: Serialize>(result: Result<T, ClientError>) -> Value {
+    match result {
+        Ok(value) => serde_json::to_value(value).unwrap_or(Value::Null),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+// Webhook URLs often carry a secret (Slack, Discord), only their host is kept
+fn redact_url(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => format!("{}://{}/<redacted>", scheme, rest.split('/').next().unwrap_or_default()),
+        None => "<redacted>".to_string(),
+    }
+}
+
+fn redact(mut config: Config) -> Config {
+    for rule in &mut config.alerts {
+        rule.webhooks = rule.webhooks.iter().map(|url| redact_url(url)).collect();
+    }
+    config
+}
+
+/// Everything about the daemon worth attaching to a bug report: its configuration
+/// (webhook URLs redacted), health, live status, stats, hwmon devices, and its last
+/// `log_lines` log lines and API calls. Parts the daemon fails to provide hold the error.
+pub async fn collect(client: &Client, log_lines: usize) -> Value {
+    let mut bundle = Map::new();
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    bundle.insert("created".to_string(), json!(created));
+    bundle.insert("cli_version".to_string(), json!(env!("CARGO_PKG_VERSION")));
+    bundle.insert("health".to_string(), part(client.health().await));
+    bundle.insert("status".to_string(), part(client.status(&FanFilter::default()).await));
+    bundle.insert("config".to_string(), part(client.config().await.map(|(config, _)| redact(config))));
+    bundle.insert("stats".to_string(), part(client.stats().await));
+    bundle.insert("hwmon_devices".to_string(), part(client.hwmon_devices(None).await));
+    bundle.insert("logs".to_string(), part(client.logs(Some(log_lines)).await));
+    bundle.insert("audit".to_string(), part(client.audit(Some(log_lines)).await));
+    Value::Object(bundle)
+}
+
+/// Names of the parts of `bundle` that hold an error instead of their content
+pub fn failed_parts(bundle: &Value) -> Vec<String> {
+    let Some(parts) = bundle.as_object() else {
+        return Vec::new();
+    };
+    parts.iter()
+        .filter(|(_, value)| value.get("error").is_some_and(Value::is_string) && value.as_object().is_some_and(|part| part.len() == 1))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
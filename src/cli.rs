@@ -1,14 +1,52 @@
-use clap::{Parser, Subcommand, Args};
+use clap::{Args, Subcommand, ValueEnum};
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use crate::daemon::{Config, FanConfig, FanStep};
+use coold_core::api_types::{AddFanRequest, AuditEntry, CurveTemplateInfo, FanFilter, FanStatus, Health, NightModeRequest, PreviewQuery, UpdateFanRequest};
+use coold_core::backend;
+use coold_core::client::Client;
+use coold_core::daemon::{Config, DeviceMatch, FanConfig, FanStep, HwmonDeviceDetail, HwmonDeviceInfo, HwmonPwmInfo, HwmonSensorReading, LoadStep, RpmStep, SensorConfig, SpinUp, Stats, TimeOfDay, CONFIG_PATH};
+use coold_core::detect::DetectedOutput;
+use coold_core::import;
+use coold_core::diff::{ConfigDiff, FieldChange};
+use crate::bundle;
+use crate::context::{Context, Contexts};
+use crate::doctor;
+use crate::pidfile::DEFAULT_PID_FILE;
+use crate::setup;
+use coold_core::templates;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-const API_BASE_URL: &str = "http://127.0.0.1:8080/api/v1";
+// Daemon the commands are sent to, selected once at startup
+static TARGET: OnceLock<Context> = OnceLock::new();
+
+/// Fan control tools `cli import` converts configurations from
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportFormat {
+    Fan2go,
+    Coolercontrol,
+}
 
 #[derive(Subcommand)]
 pub enum CliCommands {
     /// Get current status of all fans
-    Status,
+    Status {
+        /// Show min/max/average statistics since daemon start instead
+        #[arg(long, conflicts_with_all = ["device", "sensor"])]
+        stats: bool,
+        #[command(flatten)]
+        filter: FanFilterArgs,
+    },
+    
+    /// Reset the fan statistics
+    ResetStats,
+    
+    /// Send a test event to the alert webhooks
+    TestAlerts {
+        /// Only test this alert rule
+        rule: Option<String>,
+    },
     
     /// Get current configuration
     Config,
@@ -17,10 +55,34 @@ pub enum CliCommands {
     UpdateConfig {
         /// Path to configuration file
         file: String,
+        /// Apply without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    
+    /// Convert another tool's configuration to a coold one, to review and apply with update-config
+    Import {
+        /// Tool the configuration comes from
+        #[arg(value_enum)]
+        from: ImportFormat,
+        /// Its configuration file, e.g. /etc/fan2go/fan2go.yaml or /etc/coolercontrol/config.toml
+        file: PathBuf,
+        /// File to write the converted configuration to instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Show what updating the configuration from a file would change
+    DiffConfig {
+        /// Path to configuration file
+        file: String,
     },
     
     /// List all fans
-    List,
+    List {
+        #[command(flatten)]
+        filter: FanFilterArgs,
+    },
     
     /// Get specific fan configuration
     Get {
@@ -34,32 +96,115 @@ pub enum CliCommands {
         name: String,
         /// Temperature-power pairs (format: temp:power,temp:power,...)
         steps: String,
+        /// Temperature-power pairs followed while the temperature falls, "" to remove them
+        #[arg(long)]
+        cooldown_steps: Option<String>,
     },
     
     /// Add new fan
-    Add {
-        /// Sensor name
-        #[arg(long)]
-        sensor_name: String,
-        /// Sensor input path
-        #[arg(long)]
-        sensor_input: String,
-        /// PWM name
-        #[arg(long)]
-        pwm_name: String,
-        /// PWM input path
-        #[arg(long)]
-        pwm_input: String,
+    Add(Box<AddArgs>),
+    
+    /// List the named curves
+    Curves,
+    
+    /// List the built-in curve templates
+    Templates,
+    
+    /// Create or update a named curve, shared by every fan using it
+    SetCurve {
+        /// Curve name
+        name: String,
         /// Temperature-power pairs (format: temp:power,temp:power,...)
         steps: String,
     },
     
+    /// Delete a named curve no fan uses anymore
+    DeleteCurve {
+        /// Curve name
+        name: String,
+    },
+    
     /// Remove fan
     Remove {
         /// Fan name
         name: String,
+        /// Remove without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    
+    /// Run a fan at a fixed power instead of its curve
+    Override {
+        /// Fan name
+        name: String,
+        /// Power (0-100%)
+        power: u8,
+    },
+    
+    /// Return an overridden fan to its curve
+    ClearOverride {
+        /// Fan name
+        name: String,
+    },
+    
+    /// Run a fan at 100% for a while, then return it to its curve
+    Boost {
+        /// Fan name
+        name: String,
+        /// How long to boost the fan for
+        #[arg(long, default_value_t = 60)]
+        seconds: u64,
     },
     
+    /// Show the power a fan's curve gives at a temperature, or over a range (20-90°C by default)
+    Preview {
+        /// Fan name
+        name: String,
+        /// Single temperature to evaluate
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        temp: Option<i32>,
+        /// Start of the range
+        #[arg(long, default_value_t = 20)]
+        from: i32,
+        /// End of the range
+        #[arg(long, default_value_t = 90)]
+        to: i32,
+        /// Degrees between the points of the range
+        #[arg(long, default_value_t = 5)]
+        step: u32,
+        /// Evaluate the cooldown steps, followed while the temperature falls
+        #[arg(long)]
+        cooling: bool,
+    },
+    
+    /// Let fans exceed their max_power and the night mode cap until restored
+    LiftCap,
+    
+    /// Hold fans to their max_power again
+    RestoreCap,
+    
+    /// Turn the night-time power cap on or off
+    NightMode {
+        /// on or off
+        #[arg(value_parser = ["on", "off"])]
+        state: String,
+        /// Start of the night (HH:MM)
+        #[arg(long)]
+        start: Option<String>,
+        /// End of the night (HH:MM)
+        #[arg(long)]
+        end: Option<String>,
+        /// Power every fan is capped at during the night
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        max_power: Option<u8>,
+    },
+    
+    /// Pause fan control, handing the fans back to the firmware
+    Pause,
+    
+    /// Resume fan control
+    Resume,
+    
     /// Stop the daemon
     Stop,
     
@@ -67,121 +212,628 @@ pub enum CliCommands {
     Start,
     
     /// List all available hwmon devices, sensors, and PWM outputs
-    Devices,
+    Devices {
+        /// Show everything about this device only (name or hwmonN)
+        name: Option<String>,
+        /// Only list the devices with this name or hwmonN directory
+        #[arg(long, conflicts_with = "name")]
+        device: Option<String>,
+    },
+    
+    /// Show the current value of every temperature sensor
+    Sensors,
+    
+    /// Show the current state of every PWM output
+    Pwms,
+    
+    /// Look the fans' devices up again, after loading a driver or plugging in a device
+    Rescan,
+    
+    /// Find out which PWM output drives which fan by briefly varying each of them
+    Detect {
+        /// Seconds fans are given to change speed at each step
+        #[arg(long, default_value_t = 5)]
+        settle_secs: u64,
+    },
+    
+    /// Show the recent API calls that changed something
+    Audit {
+        /// Number of entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    
+    /// Print the running configuration as an /etc/fancontrol file, for the lm-sensors fancontrol script
+    ExportFancontrol {
+        /// File to write instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Write the daemon's configuration, status, devices, stats and recent logs to one
+    /// JSON file to attach to a bug report
+    SupportBundle {
+        /// File to write the bundle to
+        file: PathBuf,
+        /// Number of log lines and API calls to include
+        #[arg(long, default_value_t = 500)]
+        log_lines: usize,
+    },
+    
+    /// Check the setup for common problems and suggest fixes
+    Doctor {
+        /// Configuration file the daemon uses
+        #[arg(long, default_value = CONFIG_PATH)]
+        config: PathBuf,
+        /// PID file the daemon locks
+        #[arg(long, default_value = DEFAULT_PID_FILE)]
+        pid_file: PathBuf,
+        /// Root of the sysfs tree the daemon finds devices in
+        #[arg(long, env = "COOLD_SYSFS_ROOT", value_name = "DIR")]
+        sysfs_root: Option<PathBuf>,
+    },
+    
+    /// Manage the daemons this CLI can talk to
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+    
+    /// Print the fan names known to the daemon, for shell completion
+    #[command(hide = true)]
+    FanNames {
+        /// Every controlled fan, zone outputs included, instead of the configured ones
+        #[arg(long)]
+        controlled: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ContextCommands {
+    /// Add or replace a named daemon
+    Add {
+        /// Context name
+        name: String,
+        /// Base URL of the daemon (e.g. http://10.0.0.5:8080)
+        url: String,
+        /// Bearer token sent with every request
+        #[arg(long)]
+        token: Option<String>,
+    },
+    
+    /// Remove a named daemon
+    Remove {
+        /// Context name
+        name: String,
+    },
+    
+    /// List the named daemons
+    List,
+    
+    /// Talk to this daemon by default, or to the local one without a name
+    Use {
+        /// Context name
+        name: Option<String>,
+    },
+}
+
+#[derive(Args)]
+pub struct FanFilterArgs {
+    /// Only fans driven by or reading their temperature from this hwmon device
+    #[arg(long)]
+    device: Option<String>,
+    /// Only fans reading this temperature input (e.g. temp1)
+    #[arg(long)]
+    sensor: Option<String>,
+}
+
+impl FanFilterArgs {
+    fn filter(&self) -> FanFilter {
+        FanFilter { device: self.device.clone(), sensor: self.sensor.clone() }
+    }
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    /// Sensor name
+    #[arg(long, required_unless_present = "follows")]
+    sensor_name: Option<String>,
+    /// Sensor input path
+    #[arg(long, required_unless_present = "follows")]
+    sensor_input: Option<String>,
+    /// Which of several sensor devices with this name to read (format: path:<bus path> or serial:<serial>)
+    #[arg(long, value_parser = parse_device)]
+    sensor_device: Option<DeviceMatch>,
+    /// PWM name
+    #[arg(long)]
+    pwm_name: String,
+    /// PWM input path
+    #[arg(long)]
+    pwm_input: String,
+    /// Which of several PWM devices with this name to drive (format: path:<bus path> or serial:<serial>)
+    #[arg(long, value_parser = parse_device)]
+    pwm_device: Option<DeviceMatch>,
+    /// Fan backend (hwmon, thinkpad_acpi, dell_smm, pwmchip, i2c, acpi_ibm)
+    #[arg(long, default_value = "hwmon")]
+    backend: String,
+    /// PWM period in nanoseconds (pwmchip backend only)
+    #[arg(long)]
+    pwm_period_ns: Option<u64>,
+    /// Seconds between two control cycles of this fan [default: 5]
+    #[arg(long)]
+    interval_secs: Option<u64>,
+    /// Temperature at which every fan is forced to 100%
+    #[arg(long)]
+    critical_temp: Option<i32>,
+    /// Ambient sensor name, to key the steps on the temperature above ambient
+    #[arg(long, requires = "ambient_sensor_input")]
+    ambient_sensor_name: Option<String>,
+    /// Ambient sensor input path
+    #[arg(long, requires = "ambient_sensor_name")]
+    ambient_sensor_input: Option<String>,
+    /// CPU load-power pairs ramping the fan ahead of the temperature (format: load:power,load:power,...)
+    #[arg(long)]
+    load_steps: Option<String>,
+    /// Lowest power the fan reliably spins at
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    min_power: Option<u8>,
+    /// Highest power the fan is driven at, unless the cap is lifted
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    max_power: Option<u8>,
+    /// Power to start the fan from standstill with (format: power or power:secs, 2 seconds by default)
+    #[arg(long, value_parser = parse_spin_up)]
+    spin_up: Option<SpinUp>,
+    /// Round the power to multiples of this many %
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=50))]
+    power_step: Option<u8>,
+    /// Group whose fans all run at the highest power any of them asks for
+    #[arg(long)]
+    group: Option<String>,
+    /// How loud the fan is compared to the rest of its group, for the noise budget [default: 1]
+    #[arg(long, requires = "group")]
+    noise_weight: Option<f64>,
+    /// Fan whose power to track instead of a sensor and curve
+    #[arg(long, conflicts_with_all = ["curve", "template", "steps"])]
+    follows: Option<String>,
+    /// Added to the followed fan's power (after --follow-ratio)
+    #[arg(long, requires = "follows", allow_hyphen_values = true)]
+    follow_offset: Option<i32>,
+    /// Multiplier applied to the followed fan's power [default: 1]
+    #[arg(long, requires = "follows")]
+    follow_ratio: Option<f64>,
+    /// Named curve to follow instead of steps
+    #[arg(long, conflicts_with_all = ["steps", "template"])]
+    curve: Option<String>,
+    /// Start from a built-in curve (silent, balanced, aggressive, server)
+    #[arg(long, conflicts_with = "steps")]
+    template: Option<String>,
+    /// Temperature-power pairs followed while the temperature falls (format: temp:power,...)
+    #[arg(long, conflicts_with = "follows")]
+    cooldown_steps: Option<String>,
+    /// Program the steps into the chip's auto-point curve, which keeps working without the daemon
+    #[arg(long, conflicts_with_all = ["follows", "rpm_steps"])]
+    hardware_curve: bool,
+    /// Temperature-speed pairs to hold instead of steps (format: temp:rpm,temp:rpm,...)
+    #[arg(long, conflicts_with_all = ["follows", "curve", "template", "steps"])]
+    rpm_steps: Option<String>,
+    /// Temperature-power pairs (format: temp:power,temp:power,...)
+    #[arg(required_unless_present_any = ["curve", "template", "follows", "rpm_steps"])]
+    steps: Option<String>,
 }
 
-pub async fn run_cli(cli_command: CliCommands) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn run_cli(cli_command: CliCommands, context: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let contexts = Contexts::load()?;
+    let target = contexts.select(context.as_deref())?;
+    let client = Client::new(target.api_url(), target.token.clone());
+    let _ = TARGET.set(target);
+    
     match cli_command {
-        CliCommands::Status => {
-            let response = make_request("GET", "/status", None).await?;
-            print_status_response(response);
+        CliCommands::Status { stats: false, filter } => {
+            let health = client.health().await?;
+            print_status(&health, &client.status(&filter.filter()).await?);
+        }
+        
+        CliCommands::Status { stats: true, .. } => {
+            print_stats(&client.stats().await?);
+        }
+        
+        CliCommands::ResetStats => {
+            print_message(&client.reset_stats().await?);
+        }
+        
+        CliCommands::TestAlerts { rule } => {
+            let response = client.test_alerts(rule.as_deref()).await?;
+            if response.success {
+                println!("✓ {}", response.message);
+            } else {
+                println!("✗ {}", response.message);
+            }
+            for result in response.data.unwrap_or_default() {
+                match result.error {
+                    Some(error) => println!("  {} -> {}: {}", result.rule, result.url, error),
+                    None => println!("  {} -> {}: delivered", result.rule, result.url),
+                }
+            }
         }
         
         CliCommands::Config => {
-            let response = make_request("GET", "/config", None).await?;
-            print_config_response(response);
+            let (config, _) = client.config().await?;
+            println!("Configuration:");
+            println!("==============");
+            println!("{}", serde_json::to_string_pretty(&config)?);
         }
         
-        CliCommands::UpdateConfig { file } => {
+        CliCommands::UpdateConfig { file, yes } => {
             let config = load_config_from_file(&file)?;
-            let response = make_request("PUT", "/config", Some(config)).await?;
-            print_simple_response(response);
+            // The daemon only accepts the update against the revision it was diffed with
+            let (diff, etag) = client.diff_config(&config).await?;
+            print_diff(&diff);
+            if !(yes || setup::confirm("Apply these changes?", false)?) {
+                return Err("Configuration not updated".into());
+            }
+            print_message(&client.update_config(&config, &etag).await?);
         }
         
-        CliCommands::List => {
-            let response = make_request("GET", "/fans", None).await?;
-            print_fans_response(response);
+        CliCommands::Import { from, file, output } => {
+            let content = std::fs::read_to_string(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+            let imported = match from {
+                ImportFormat::Fan2go => import::from_fan2go(&content),
+                ImportFormat::Coolercontrol => import::from_coolercontrol(&content),
+            }?;
+            for warning in &imported.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            let json = serde_json::to_string_pretty(&imported.config)?;
+            match output {
+                Some(output) => {
+                    std::fs::write(&output, json + "\n").map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+                    println!("{} fans imported to {}, apply them with `cli update-config {}`", imported.config.fan.len(), output.display(), output.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        
+        CliCommands::DiffConfig { file } => {
+            let config = load_config_from_file(&file)?;
+            let (diff, _) = client.diff_config(&config).await?;
+            print_diff(&diff);
+        }
+        
+        CliCommands::List { filter } => {
+            print_fans(&client.fans(&filter.filter()).await?);
         }
         
         CliCommands::Get { name } => {
-            let response = make_request("GET", &format!("/fans/{}", name), None).await?;
-            print_fan_response(response);
+            let fan = client.fan(&name).await?;
+            println!("Fan Configuration:");
+            println!("==================");
+            println!("{}", serde_json::to_string_pretty(&fan)?);
+        }
+        
+        CliCommands::Update { name, steps, cooldown_steps } => {
+            let steps = parse_steps(&steps)?;
+            let cooldown_steps = cooldown_steps
+                .map(|steps| if steps.is_empty() { Ok(Vec::new()) } else { parse_steps(&steps) })
+                .transpose()?;
+            print_message(&client.update_fan(&name, &UpdateFanRequest { steps, cooldown_steps }).await?);
+        }
+        
+        CliCommands::Add(args) => {
+            let AddArgs { sensor_name, sensor_input, sensor_device, pwm_name, pwm_input, pwm_device, backend, pwm_period_ns, interval_secs, critical_temp, ambient_sensor_name, ambient_sensor_input, load_steps, min_power, max_power, spin_up, power_step, group, noise_weight, follows, follow_offset, follow_ratio, curve, template, cooldown_steps, hardware_curve, rpm_steps, steps } = *args;
+            let steps = match template {
+                Some(template) => templates::find(&template)
+                    .ok_or_else(|| format!("Unknown template: {}", template))?
+                    .steps.to_vec(),
+                None => steps.as_deref().map(parse_steps).transpose()?.unwrap_or_default(),
+            };
+            let load_steps = load_steps.as_deref().map(parse_steps).transpose()?.unwrap_or_default().iter()
+                .map(|step| match u8::try_from(step.temp) {
+                    Ok(load) if load <= 100 => Ok(LoadStep { load, power: step.power }),
+                    _ => Err(format!("Load must be between 0 and 100, got {}", step.temp)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let fan = AddFanRequest {
+                sensor_name: sensor_name.unwrap_or_default(),
+                sensor_input: sensor_input.unwrap_or_default(),
+                sensor_device,
+                pwm_name,
+                pwm_input,
+                pwm_device,
+                backend: serde_json::from_value(json!(backend)).map_err(|_| format!("Unknown backend: {}", backend))?,
+                pwm_period_ns,
+                interval_secs,
+                critical_temp,
+                ambient: ambient_sensor_name.zip(ambient_sensor_input).map(|(sensor_name, sensor_input)| SensorConfig {
+                    sensor_name,
+                    sensor_input,
+                    sensor: None,
+                    sensor_device: None,
+                    sensor_label: None,
+                    sensor_offset: None,
+                    sensor_scale: None,
+                }),
+                load_steps,
+                min_power,
+                max_power,
+                spin_up,
+                power_step,
+                group,
+                noise_weight,
+                follows,
+                follow_offset,
+                follow_ratio,
+                curve,
+                cooldown_steps: cooldown_steps.as_deref().map(parse_steps).transpose()?.unwrap_or_default(),
+                rpm_steps: rpm_steps.as_deref().map(parse_rpm_steps).transpose()?.unwrap_or_default(),
+                steps,
+                hardware_curve,
+                ..Default::default()
+            };
+            print_message(&client.add_fan(&fan).await?);
         }
         
-        CliCommands::Update { name, steps } => {
-            let steps_vec = parse_steps(&steps)?;
-            let update_data = json!({
-                "steps": steps_vec
-            });
-            let response = make_request("PUT", &format!("/fans/{}", name), Some(update_data)).await?;
-            print_simple_response(response);
+        CliCommands::Curves => {
+            print_curves(&client.curves().await?);
         }
         
-        CliCommands::Add { sensor_name, sensor_input, pwm_name, pwm_input, steps } => {
-            let steps_vec = parse_steps(&steps)?;
-            let add_data = json!({
-                "sensor_name": sensor_name,
-                "sensor_input": sensor_input,
-                "pwm_name": pwm_name,
-                "pwm_input": pwm_input,
-                "steps": steps_vec
-            });
-            let response = make_request("POST", "/fans", Some(add_data)).await?;
-            print_simple_response(response);
+        CliCommands::Templates => {
+            print_templates(&client.curve_templates().await?);
         }
         
-        CliCommands::Remove { name } => {
-            let response = make_request("DELETE", &format!("/fans/{}", name), None).await?;
-            print_simple_response(response);
+        CliCommands::SetCurve { name, steps } => {
+            print_message(&client.set_curve(&name, parse_steps(&steps)?).await?);
+        }
+        
+        CliCommands::DeleteCurve { name } => {
+            print_message(&client.delete_curve(&name).await?);
+        }
+        
+        CliCommands::Remove { name, yes } => {
+            if !yes && !confirm_removal(&client, &name).await? {
+                return Err(format!("Fan '{}' not removed", name).into());
+            }
+            print_message(&client.remove_fan(&name).await?);
+        }
+        
+        CliCommands::Override { name, power } => {
+            if power > 100 {
+                return Err("Power must be between 0 and 100".into());
+            }
+            print_message(&client.override_fan(&name, power).await?);
+        }
+        
+        CliCommands::ClearOverride { name } => {
+            print_message(&client.clear_override(&name).await?);
+        }
+        
+        CliCommands::Boost { name, seconds } => {
+            print_message(&client.boost(&name, Some(seconds)).await?);
+        }
+        
+        CliCommands::Preview { name, temp, from, to, step, cooling } => {
+            let query = match temp {
+                Some(temp) => PreviewQuery { temp: Some(temp), cooling, ..Default::default() },
+                None => PreviewQuery { from: Some(from), to: Some(to), step: Some(step), cooling, ..Default::default() },
+            };
+            let points = client.preview(&name, &query).await?;
+            println!("Curve of '{}'", name);
+            for point in points {
+                println!("  {:>4}°C {:>3}% {}", point.temp, point.power, "#".repeat(point.power as usize / 5));
+            }
+        }
+        
+        CliCommands::LiftCap => {
+            print_message(&client.lift_power_cap().await?);
+        }
+        
+        CliCommands::RestoreCap => {
+            print_message(&client.restore_power_cap().await?);
+        }
+        
+        CliCommands::NightMode { state, start, end, max_power } => {
+            let night_mode = NightModeRequest {
+                enabled: state == "on",
+                start: start.map(TimeOfDay::try_from).transpose()?,
+                end: end.map(TimeOfDay::try_from).transpose()?,
+                max_power,
+            };
+            print_message(&client.set_night_mode(&night_mode).await?);
+        }
+        
+        CliCommands::Pause => {
+            print_message(&client.pause().await?);
+        }
+        
+        CliCommands::Resume => {
+            print_message(&client.resume().await?);
         }
         
         CliCommands::Stop => {
-            let response = make_request("POST", "/stop", None).await?;
-            print_simple_response(response);
+            print_message(&client.stop().await?);
         }
         
         CliCommands::Start => {
-            let response = make_request("POST", "/start", None).await?;
-            print_simple_response(response);
+            print_message(&client.start().await?);
+        }
+        
+        CliCommands::Devices { name: None, device } => {
+            print_hwmon_devices(&client.hwmon_devices(device.as_deref()).await?);
+        }
+        
+        CliCommands::Devices { name: Some(name), .. } => {
+            print_hwmon_device(&client.hwmon_device(&name).await?);
         }
         
-        CliCommands::Devices => {
-            let response = make_request("GET", "/hwmon_devices", None).await?;
-            print_hwmon_devices_response(response);
+        CliCommands::Sensors => {
+            print_sensors(&client.sensors().await?);
+        }
+        
+        CliCommands::Pwms => {
+            print_pwms(&client.pwms().await?);
+        }
+        
+        CliCommands::Rescan => {
+            let report = client.rescan().await?;
+            if report.moved.is_empty() {
+                println!("Every fan was already at its devices");
+            }
+            for fan in &report.moved {
+                println!("✓ {}: found at new devices", fan);
+            }
+            for fan in &report.missing {
+                println!("✗ {}: devices still missing", fan);
+            }
+            for output in &report.unwritable {
+                println!("✗ {}: can't be written without root, restart the daemon", output);
+            }
+        }
+        
+        CliCommands::Detect { settle_secs } => {
+            println!("Varying each PWM output, fans will speed up and slow down...");
+            print_detected(client.detect(Some(settle_secs)).await?);
+        }
+        
+        CliCommands::Audit { limit } => {
+            print_audit(&client.audit(Some(limit)).await?);
+        }
+        
+        CliCommands::ExportFancontrol { output } => {
+            let export = client.fancontrol().await?;
+            for skipped in &export.skipped {
+                eprintln!("Left out {}: {}", skipped.fan, skipped.reason);
+            }
+            match output {
+                Some(file) => {
+                    std::fs::write(&file, &export.content).map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+                    println!("fancontrol configuration written to {}", file.display());
+                }
+                None => print!("{}", export.content),
+            }
+        }
+        
+        CliCommands::SupportBundle { file, log_lines } => {
+            let bundle = bundle::collect(&client, log_lines).await;
+            std::fs::write(&file, serde_json::to_string_pretty(&bundle)?)
+                .map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+            println!("Support bundle written to {}", file.display());
+            let failed = bundle::failed_parts(&bundle);
+            if !failed.is_empty() {
+                println!("The daemon couldn't provide: {}", failed.join(", "));
+            }
+        }
+        
+        CliCommands::Doctor { config, pid_file, sysfs_root } => {
+            if let Some(root) = sysfs_root {
+                backend::set_sysfs_root(root);
+            }
+            run_doctor(&client, &config, &pid_file).await?;
+        }
+        
+        CliCommands::Context { command } => run_context_command(command, contexts)?,
+        
+        CliCommands::FanNames { controlled } => {
+            // Completion must stay quiet, an unreachable daemon just offers nothing
+            let mut names: Vec<String> = if controlled {
+                client.status(&FanFilter::default()).await.into_iter().flatten().map(|fan| fan.name).collect()
+            } else {
+                client.fans(&FanFilter::default()).await.into_iter().flat_map(|fans| fans.into_keys()).collect()
+            };
+            names.sort();
+            for name in names {
+                println!("{}", name);
+            }
         }
     }
     
     Ok(())
 }
 
-async fn make_request(method: &str, endpoint: &str, data: Option<Value>) -> Result<Value, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let url = format!("{}{}", API_BASE_URL, endpoint);
-    
-    let request_builder = match method {
-        "GET" => client.get(&url),
-        "POST" => client.post(&url),
-        "PUT" => client.put(&url),
-        "DELETE" => client.delete(&url),
-        _ => return Err("Unsupported HTTP method".into()),
-    };
-    
-    let request_builder = if let Some(json_data) = data {
-        request_builder.json(&json_data)
-    } else {
-        request_builder
+// Show what removing the fan changes in the configuration, and ask whether to go on
+async fn confirm_removal(client: &Client, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let (mut config, _) = client.config().await?;
+    // An unknown fan is reported by the removal itself
+    if config.fan.remove(name).is_none() {
+        return Ok(true);
+    }
+    print_diff(&client.diff_config(&config).await?.0);
+    Ok(setup::confirm(&format!("Remove fan '{}'?", name), false)?)
+}
+
+async fn run_doctor(client: &Client, config_path: &Path, pid_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = doctor::Report::default();
+    let status = match client.status(&FanFilter::default()).await {
+        Ok(status) => {
+            report.ok(&format!("Daemon reachable at {}", TARGET.get_or_init(Context::local).api_url()));
+            Some(status)
+        }
+        Err(e) => {
+            report.problem(
+                &format!("Daemon not reachable: {}", e),
+                "Start it with `sudo coold-rs daemon`, or select the right one with --context",
+            );
+            None
+        }
     };
-    
-    let response = request_builder.send().await?;
+    doctor::check_pid_file(&mut report, pid_file, status.is_some());
+    if let Some(config) = doctor::check_config(&mut report, config_path) {
+        doctor::check_duplicate_pwms(&mut report, &config);
+        doctor::check_fans(&mut report, &config, status.is_some());
+    }
+    if let Some(status) = &status {
+        doctor::check_status(&mut report, status);
+    }
+    report.finish()
+}
 
-    let response_status = response.status();
-    
-    if !response_status.is_success() {
-        let error_text = response.text().await?;
-        return Err(format!("HTTP {}: {}", response_status, error_text).into());
+fn run_context_command(command: ContextCommands, mut contexts: Contexts) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        ContextCommands::Add { name, url, token } => {
+            contexts.contexts.insert(name.clone(), Context { url, token });
+            contexts.save()?;
+            println!("✓ Context '{}' saved", name);
+        }
+        
+        ContextCommands::Remove { name } => {
+            if contexts.contexts.remove(&name).is_none() {
+                return Err(format!("Unknown context: {}", name).into());
+            }
+            if contexts.current.as_deref() == Some(name.as_str()) {
+                contexts.current = None;
+            }
+            contexts.save()?;
+            println!("✓ Context '{}' removed", name);
+        }
+        
+        ContextCommands::List => {
+            println!("Contexts:");
+            println!("=========");
+            let marker = |current: bool| if current { "*" } else { " " };
+            println!("{} local: {}", marker(contexts.current.is_none()), Context::local().url);
+            for (name, context) in &contexts.contexts {
+                let token = if context.token.is_some() { " (token)" } else { "" };
+                println!("{} {}: {}{}", marker(contexts.current.as_ref() == Some(name)), name, context.url, token);
+            }
+        }
+        
+        ContextCommands::Use { name } => {
+            if let Some(name) = name.as_ref().filter(|name| !contexts.contexts.contains_key(*name)) {
+                return Err(format!("Unknown context: {}", name).into());
+            }
+            println!("✓ Using {}", name.as_deref().unwrap_or("the local daemon"));
+            contexts.current = name;
+            contexts.save()?;
+        }
     }
     
-    let json_response: Value = response.json().await?;
-    Ok(json_response)
+    Ok(())
 }
 
-fn load_config_from_file(file_path: &str) -> Result<Value, Box<dyn std::error::Error>> {
+fn load_config_from_file(file_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let content = std::fs::read_to_string(file_path)?;
-    let config: Config = serde_json::from_str(&content)?;
-    Ok(serde_json::to_value(config)?)
+    Ok(serde_json::from_str(&content)?)
 }
 
-fn parse_steps(steps_str: &str) -> Result<Vec<FanStep>, Box<dyn std::error::Error>> {
+pub fn parse_steps(steps_str: &str) -> Result<Vec<FanStep>, Box<dyn std::error::Error>> {
     let mut steps = Vec::new();
     
     for pair in steps_str.split(',') {
@@ -207,146 +859,381 @@ fn parse_steps(steps_str: &str) -> Result<Vec<FanStep>, Box<dyn std::error::Erro
     Ok(steps)
 }
 
-fn print_status_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            if let Some(data) = response["data"].as_array() {
-                println!("Fan Status:");
-                println!("===========");
-                
-                for fan in data {
-                    if let (Some(name), Some(temp), Some(power)) = (
-                        fan["name"].as_str(),
-                        fan["temperature"].as_i64(),
-                        fan["power"].as_u64()
-                    ) {
-                        println!("{}: {}°C, {}% power", name, temp, power);
-                        
-                        if let Some(steps) = fan["steps"].as_array() {
-                            print!("  Curve: ");
-                            let step_strs: Vec<String> = steps.iter()
-                                .filter_map(|step| {
-                                    if let (Some(temp), Some(power)) = (
-                                        step["temp"].as_i64(),
-                                        step["power"].as_u64()
-                                    ) {
-                                        Some(format!("{}°C:{}%", temp, power))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect();
-                            println!("{}", step_strs.join(" → "));
-                        }
-                    }
-                }
+fn parse_rpm_steps(steps_str: &str) -> Result<Vec<RpmStep>, Box<dyn std::error::Error>> {
+    steps_str.split(',')
+        .map(|pair| {
+            let (temp, rpm) = pair.split_once(':')
+                .ok_or_else(|| format!("Invalid step format: {}. Expected format: temp:rpm", pair))?;
+            Ok(RpmStep { temp: temp.trim().parse()?, rpm: rpm.trim().parse()? })
+        })
+        .collect()
+}
+
+// Device of --sensor-device and --pwm-device, e.g. path:0000:03:00.0 or serial:S4EWNX0R123
+fn parse_device(value: &str) -> Result<DeviceMatch, String> {
+    match value.split_once(':') {
+        Some(("path", path)) if !path.is_empty() => Ok(DeviceMatch::Path(path.to_string())),
+        Some(("serial", serial)) if !serial.is_empty() => Ok(DeviceMatch::Serial(serial.to_string())),
+        _ => Err(format!("Invalid device: {}. Expected path:<bus path> or serial:<serial>", value)),
+    }
+}
+
+// Kick of --spin-up, e.g. 100 or 80:3
+fn parse_spin_up(value: &str) -> Result<SpinUp, String> {
+    let (power, secs) = value.split_once(':').unwrap_or((value, "2"));
+    match (power.trim().parse::<u8>(), secs.trim().parse()) {
+        (Ok(power), Ok(secs)) if power <= 100 => Ok(SpinUp { power, secs }),
+        _ => Err(format!("Invalid spin-up: {}. Expected power or power:secs", value)),
+    }
+}
+
+// A reading, or "?" when it couldn't be made
+fn or_unknown(value: Option<impl Display>) -> String {
+    value.map_or("?".to_string(), |value| value.to_string())
+}
+
+// Steps as "temp°C:power% → ..."
+fn format_steps(steps: &[FanStep]) -> String {
+    steps.iter()
+        .map(|step| format!("{}°C:{}%", step.temp, step.power))
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+fn print_message(message: &str) {
+    println!("✓ {}", message);
+}
+
+fn print_status(health: &Health, fans: &[FanStatus]) {
+    println!("Fan Status:");
+    println!("===========");
+    println!("Daemon: {}", health.state.as_str());
+    for (fan, problems) in &health.problems {
+        println!("  {}: {}", fan, problems.join(", "));
+    }
+    
+    for fan in fans {
+        let (Some(temp), Some(power)) = (fan.temperature, fan.power) else {
+            continue;
+        };
+        println!("{}: {}°C, {}% power", fan.name, temp, power);
+        println!("  Curve: {}", format_steps(&fan.steps));
+        
+        if let Some(predicted) = fan.predicted_temperature {
+            println!("  Predicted: {}°C, followed by the curve", predicted);
+        }
+        
+        if fan.sensor_offset.is_some() || fan.sensor_scale.is_some() {
+            println!("  Temperature corrected: x{} {:+}°C", fan.sensor_scale.unwrap_or(1.0), fan.sensor_offset.unwrap_or(0.0));
+        }
+        
+        if let Some(group) = &fan.group {
+            match fan.demand {
+                Some(demand) => println!("  Group: {} (asks for {}%)", group, demand),
+                None => println!("  Group: {}", group),
+            }
+        }
+        
+        if fan.capped {
+            println!("  Capped at {}%", or_unknown(fan.max_power));
+        }
+        
+        if let Some(override_power) = fan.override_power {
+            println!("  Overridden to {}%", override_power);
+        }
+        
+        if fan.semi_passive {
+            println!("  Stopped while idle (semi-passive)");
+        }
+        
+        if let Some(remaining) = fan.boost_remaining_secs {
+            println!("  Boosted to 100% for another {}s", remaining);
+        }
+        
+        if let Some(rpm) = fan.rpm {
+            match (fan.target_rpm, fan.expected_rpm) {
+                (Some(target), _) => println!("  Speed: {} RPM (target {} RPM)", rpm, target),
+                (None, Some(expected)) => println!("  Speed: {} RPM (expected {} RPM)", rpm, expected),
+                (None, None) => println!("  Speed: {} RPM", rpm),
             }
+        }
+        
+        if fan.hardware_curve {
+            println!("  Control: hardware curve (pwm_enable {})", or_unknown(fan.pwm_enable));
+        } else if fan.owned {
+            println!("  Control: manual (pwm_enable {})", or_unknown(fan.pwm_enable));
         } else {
-            println!("Error: {}", response["message"].as_str().unwrap_or("Unknown error"));
+            println!("  Control: left to the firmware (pwm_enable {})", or_unknown(fan.pwm_enable));
+        }
+        
+        if fan.write_errors > 0 {
+            println!("  Power writes failing: {} in a row ({})", fan.write_errors, fan.last_write_error.as_deref().unwrap_or("unknown error"));
+        }
+        
+        if fan.read_errors > 0 {
+            println!("  Sensor reads failing: {} in a row", fan.read_errors);
+        }
+        
+        if fan.stalled {
+            println!("  STALLED: control loop stuck, check the sensor and device");
+        }
+        
+        if fan.failing {
+            println!("  FAILING: speed off its rpm_curve, check the fan");
+        }
+        
+        if let Some(error) = &fan.self_test_error {
+            println!("  SELF-TEST FAILED: {}", error);
+        }
+        
+        if fan.critical {
+            println!("  CRITICAL temperature, every fan forced to 100%");
+        }
+        
+        if fan.control_reasserts > 0 {
+            println!("  Control reasserted {} times", fan.control_reasserts);
         }
     }
 }
 
-fn print_config_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            if let Some(data) = response["data"].as_object() {
-                println!("Configuration:");
-                println!("==============");
-                println!("{}", serde_json::to_string_pretty(&data).unwrap());
-            }
-        } else {
-            println!("Error: {}", response["message"].as_str().unwrap_or("Unknown error"));
+fn print_stats(stats: &Stats) {
+    println!("Fan Statistics (last {}s):", stats.collected_secs);
+    println!("==========================");
+    
+    let fans: BTreeMap<_, _> = stats.fans.iter().collect();
+    for (name, stats) in fans {
+        println!("{}: {} samples", name, stats.samples);
+        println!("  Temp:  min {}°C, max {}°C, avg {:.1}°C", or_unknown(stats.min_temp), or_unknown(stats.max_temp), stats.avg_temp);
+        println!("  Power: min {}%, max {}%, avg {:.1}%", or_unknown(stats.min_power), or_unknown(stats.max_power), stats.avg_power);
+        println!("  Time at 100%: {}s", stats.secs_at_full_power);
+        println!("  Cycles: {} (last {:.1}ms, avg {:.1}ms, max {:.1}ms)", stats.cycles, stats.last_cycle_ms, stats.avg_cycle_ms, stats.max_cycle_ms);
+        println!("  Failures: {} sensor reads, {} power writes", stats.read_failures, stats.write_failures);
+    }
+}
+
+fn print_fans(fans: &HashMap<String, FanConfig>) {
+    println!("Fans:");
+    println!("=====");
+    
+    let fans: BTreeMap<_, _> = fans.iter().collect();
+    for (name, fan) in fans {
+        println!("{}:", name);
+        match &fan.curve {
+            Some(curve) => println!("  Curve: {}", curve),
+            None => println!("  Curve: {}", format_steps(&fan.steps)),
         }
     }
 }
 
-fn print_fans_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            if let Some(data) = response["data"].as_object() {
-                println!("Fans:");
-                println!("=====");
-                
-                for (name, fan) in data {
-                    println!("{}:", name);
-                    if let Some(steps) = fan["steps"].as_array() {
-                        print!("  Curve: ");
-                        let step_strs: Vec<String> = steps.iter()
-                            .filter_map(|step| {
-                                if let (Some(temp), Some(power)) = (
-                                    step["temp"].as_i64(),
-                                    step["power"].as_u64()
-                                ) {
-                                    Some(format!("{}°C:{}%", temp, power))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-                        println!("{}", step_strs.join(" → "));
-                    }
-                }
+fn print_curves(curves: &HashMap<String, Vec<FanStep>>) {
+    println!("Curves:");
+    println!("=======");
+    
+    let curves: BTreeMap<_, _> = curves.iter().collect();
+    for (name, steps) in curves {
+        println!("{}: {}", name, format_steps(steps));
+    }
+}
+
+fn print_templates(templates: &[CurveTemplateInfo]) {
+    println!("Curve Templates:");
+    println!("================");
+    
+    for template in templates {
+        println!("{}: {}", template.name, template.description);
+        println!("  Curve: {}", format_steps(&template.steps));
+    }
+}
+
+fn print_hwmon_devices(devices: &[HwmonDeviceInfo]) {
+    println!("Available hwmon devices:");
+    println!("========================");
+    for device in devices {
+        println!("Device: {} (at {})", device.name, device.hwmon_path);
+        if let Some(path) = &device.device_path {
+            println!("  Bus path: {}", path);
+        }
+        if let Some(serial) = &device.serial {
+            println!("  Serial: {}", serial);
+        }
+        println!("  Sensors:");
+        for sensor in &device.sensors {
+            match &sensor.label {
+                Some(label) => println!("    {} (label: {}): {}°C", sensor.input, label, or_unknown(sensor.temperature)),
+                None => println!("    {}: {}°C", sensor.input, or_unknown(sensor.temperature)),
             }
-        } else {
-            println!("Error: {}", response["message"].as_str().unwrap_or("Unknown error"));
+        }
+        let pwms: Vec<_> = device.pwms.iter()
+            .map(|pwm| {
+                let value = or_unknown(device.pwm_values.get(pwm));
+                match device.pwm_modes.get(pwm) {
+                    Some(mode) => format!("{} = {} ({})", pwm, value, mode.as_str()),
+                    None => format!("{} = {}", pwm, value),
+                }
+            })
+            .collect();
+        println!("  PWMs: {}", pwms.join(", "));
+        if !device.fans.is_empty() {
+            let fans: Vec<_> = device.fans.iter()
+                .map(|fan| format!("{} = {} RPM", fan.input, or_unknown(fan.rpm)))
+                .collect();
+            println!("  Fans: {}", fans.join(", "));
         }
     }
 }
 
-fn print_fan_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            if let Some(data) = response["data"].as_object() {
-                println!("Fan Configuration:");
-                println!("==================");
-                println!("{}", serde_json::to_string_pretty(&data).unwrap());
-            }
-        } else {
-            println!("Error: {}", response["message"].as_str().unwrap_or("Unknown error"));
+fn print_hwmon_device(device: &HwmonDeviceDetail) {
+    println!("Device: {} (at {})", device.name, device.hwmon_path);
+    if let Some(driver) = &device.driver {
+        println!("  Driver: {}", driver);
+    }
+    println!("  Sensors:");
+    for sensor in &device.sensors {
+        match &sensor.label {
+            Some(label) => println!("    {} ({}): {}°C", sensor.input, label, sensor.temperature),
+            None => println!("    {}: {}°C", sensor.input, sensor.temperature),
+        }
+    }
+    println!("  PWMs:");
+    for pwm in &device.pwms {
+        match &pwm.fan {
+            Some(fan) => println!("    {}: value {}, enable {} ({})", pwm.pwm, or_unknown(pwm.value), or_unknown(pwm.enable), fan),
+            None => println!("    {}: value {}, enable {}", pwm.pwm, or_unknown(pwm.value), or_unknown(pwm.enable)),
+        }
+    }
+    println!("  Fans:");
+    for fan in &device.fans {
+        match &fan.label {
+            Some(label) => println!("    {} ({}): {} RPM", fan.input, label, or_unknown(fan.rpm)),
+            None => println!("    {}: {} RPM", fan.input, or_unknown(fan.rpm)),
         }
     }
 }
 
-fn print_simple_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            println!("✓ {}", response["message"].as_str().unwrap_or("Success"));
-        } else {
-            println!("✗ {}", response["message"].as_str().unwrap_or("Unknown error"));
+fn print_sensors(sensors: &[HwmonSensorReading]) {
+    println!("Sensors:");
+    println!("========");
+    for sensor in sensors {
+        match &sensor.label {
+            Some(label) => println!("{}/{} ({}): {}°C", sensor.name, sensor.input, label, sensor.temperature),
+            None => println!("{}/{}: {}°C", sensor.name, sensor.input, sensor.temperature),
         }
     }
 }
 
-fn print_hwmon_devices_response(response: Value) {
-    if let Some(success) = response["success"].as_bool() {
-        if success {
-            if let Some(devices) = response["data"].as_array() {
-                println!("Available hwmon devices:");
-                println!("========================");
-                for dev in devices {
-                    let name = dev["name"].as_str().unwrap_or("unknown");
-                    let path = dev["hwmon_path"].as_str().unwrap_or("");
-                    println!("Device: {} (at {})", name, path);
-                    if let Some(sensors) = dev["sensors"].as_array() {
-                        println!("  Sensors:");
-                        for sensor in sensors {
-                            let input = sensor["input"].as_str().unwrap_or("");
-                            if let Some(label) = sensor["label"].as_str() {
-                                println!("    {} (label: {})", input, label);
-                            } else {
-                                println!("    {}", input);
-                            }
-                        }
-                    }
-                    if let Some(pwms) = dev["pwms"].as_array() {
-                        let pwms: Vec<_> = pwms.iter().filter_map(|p| p.as_str()).collect();
-                        println!("  PWMs: {}", pwms.join(", "));
-                    }
-                }
-            }
+fn print_pwms(pwms: &[HwmonPwmInfo]) {
+    println!("PWM outputs:");
+    println!("============");
+    for pwm in pwms {
+        print!("{}/{}: value {}, enable {}", pwm.name, pwm.pwm, or_unknown(pwm.value), or_unknown(pwm.enable));
+        if let Some(mode) = pwm.mode {
+            print!(", {}", mode.as_str());
+        }
+        match &pwm.fan {
+            Some(fan) if pwm.controlled => println!(" - controlled as {}", fan),
+            Some(fan) => println!(" - configured as {}, not controlled", fan),
+            None => println!(),
+        }
+    }
+}
+
+fn print_detected(outputs: Vec<DetectedOutput>) {
+    let found = outputs.iter().filter(|output| output.fan_input.is_some()).count();
+    println!("Found the fan of {} of {} PWM outputs", found, outputs.len());
+    let mut suggested = BTreeMap::new();
+    for output in outputs {
+        let pwm = format!("{}/{}", output.pwm_name, output.pwm_input);
+        if let Some(reason) = &output.skipped {
+            println!("  {}: skipped ({})", pwm, reason);
+        } else if let Some(fan_input) = &output.fan_input {
+            println!("  {}: drives {} ({} RPM at 100%, {} RPM at 40%)",
+                pwm, fan_input, or_unknown(output.rpm_high), or_unknown(output.rpm_low));
+            suggested.insert(format!("{}_{}", output.pwm_name, output.pwm_input), output.suggested);
         } else {
-            println!("Error: {}", response["message"].as_str().unwrap_or("Unknown error"));
+            println!("  {}: no fan responded", pwm);
+        }
+    }
+    if !suggested.is_empty() {
+        println!();
+        println!("Suggested fans (set sensor_name and sensor_input before adding them):");
+        println!("{}", serde_json::to_string_pretty(&suggested).unwrap_or_default());
+    }
+}
+
+// Unix time as local "YYYY-MM-DD HH:MM:SS"
+fn format_timestamp(timestamp: i64) -> String {
+    // SAFETY: tm is plain data and localtime_r fills it in
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&(timestamp as libc::time_t), &mut tm);
+        tm
+    };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+fn print_audit(entries: &[AuditEntry]) {
+    println!("Audit log:");
+    println!("==========");
+    for entry in entries {
+        let token = entry.token.as_ref().map(|token| format!(" ({})", token)).unwrap_or_default();
+        let request_id = entry.request_id.as_ref().map(|id| format!(" [request {}]", id)).unwrap_or_default();
+        println!("{} {}{} {} {} -> {}{}",
+            format_timestamp(entry.timestamp as i64),
+            entry.client,
+            token,
+            entry.method,
+            entry.path,
+            entry.status,
+            request_id);
+        if let Some(request) = &entry.request {
+            println!("  {}", request);
         }
     }
-} 
\ No newline at end of file
+}
+
+// Steps as "temp:power,...", anything else as compact JSON
+fn format_diff_value(value: &Value) -> String {
+    let steps: Option<Vec<String>> = value.as_array().and_then(|steps| steps.iter()
+        .map(|step| Some(format!("{}:{}", step["temp"].as_i64()?, step["power"].as_u64()?)))
+        .collect());
+    match steps {
+        Some(steps) if !steps.is_empty() => steps.join(","),
+        _ => value.to_string(),
+    }
+}
+
+fn print_field_changes(indent: &str, changes: &[FieldChange]) {
+    for change in changes {
+        let format = |value: &Option<Value>| value.as_ref().map_or("(unset)".to_string(), format_diff_value);
+        println!("{}{}: {} -> {}", indent, change.field, format(&change.from), format(&change.to));
+    }
+}
+
+fn print_diff(diff: &ConfigDiff) {
+    if diff.is_empty() {
+        println!("No changes");
+    } else {
+        println!("{} changes", diff.len());
+    }
+    for (section, title) in [("fan", "Fans"), ("zones", "Zones"), ("curves", "Curves"), ("sensors", "Sensors")] {
+        let Some(section) = diff.sections.get(section).filter(|section| !section.is_empty()) else {
+            continue;
+        };
+        println!("{}:", title);
+        for name in &section.added {
+            println!("  + {}", name);
+        }
+        for name in &section.removed {
+            println!("  - {}", name);
+        }
+        for (name, changes) in &section.changed {
+            println!("  ~ {}", name);
+            print_field_changes("      ", changes);
+        }
+    }
+    if !diff.settings.is_empty() {
+        println!("Settings:");
+        print_field_changes("  ~ ", &diff.settings);
+    }
+}
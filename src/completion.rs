@@ -0,0 +1,102 @@
+use clap::{Command, ValueEnum};
+
+/// Shells completion scripts are generated for
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    /// Through zsh's bash completion support
+    Zsh,
+}
+
+/// CLI commands whose first argument is a fan of `config.fan`
+const CONFIGURED_FAN_COMMANDS: [&str; 3] = ["get", "update", "remove"];
+/// CLI commands whose first argument is any controlled fan, zone outputs included
+const CONTROLLED_FAN_COMMANDS: [&str; 4] = ["override", "clear-override", "boost", "preview"];
+
+fn visible_subcommands(command: &Command) -> Vec<String> {
+    command.get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect()
+}
+
+fn long_flags(command: &Command) -> String {
+    let mut flags: Vec<String> = command.get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .filter_map(|arg| arg.get_long().map(|long| format!("--{}", long)))
+        .collect();
+    flags.push("--help".to_string());
+    flags.join(" ")
+}
+
+/// Completion script for `shell`, completing commands and flags from the CLI definition,
+/// and fan names by asking the daemon (`cli fan-names`) when completing
+pub fn script(cli: &Command, shell: Shell) -> String {
+    let cli_command = cli.find_subcommand("cli");
+    let mut flag_cases = String::new();
+    for mode in cli.get_subcommands().filter(|mode| mode.get_name() != "cli") {
+        flag_cases.push_str(&format!("            \"{} \") opts=\"{}\" ;;\n", mode.get_name(), long_flags(mode)));
+    }
+    if let Some(cli_command) = cli_command {
+        for subcommand in cli_command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()) {
+            flag_cases.push_str(&format!("            \"cli {}\") opts=\"{}\" ;;\n", subcommand.get_name(), long_flags(subcommand)));
+        }
+    }
+
+    let mut script = String::new();
+    if let Shell::Zsh = shell {
+        script.push_str("autoload -U +X bashcompinit && bashcompinit\n\n");
+    }
+    script.push_str(&format!(
+        r#"_coold_rs() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local mode="" sub="" positionals=0 context=() i word
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${{COMP_WORDS[i]}}"
+        case "$word" in
+            --context) context=(--context "${{COMP_WORDS[i + 1]}}"); ((i++)) ;;
+            -*) ;;
+            *)
+                if [ -z "$mode" ]; then mode="$word"
+                elif [ "$mode" = cli ] && [ -z "$sub" ]; then sub="$word"
+                else ((positionals++))
+                fi ;;
+        esac
+    done
+
+    if [ -z "$mode" ]; then
+        COMPREPLY=($(compgen -W "{modes} --help --version" -- "$cur"))
+        return
+    fi
+    if [ "$mode" = cli ] && [ -z "$sub" ]; then
+        COMPREPLY=($(compgen -W "{cli_commands} --context --help" -- "$cur"))
+        return
+    fi
+    if [[ "$cur" == -* ]]; then
+        local opts=""
+        case "$mode $sub" in
+{flag_cases}        esac
+        COMPREPLY=($(compgen -W "$opts" -- "$cur"))
+        return
+    fi
+    if [ "$mode" = cli ] && [ "$positionals" -eq 0 ]; then
+        case "$sub" in
+            {configured}) COMPREPLY=($(compgen -W "$("$1" cli "${{context[@]}}" fan-names 2>/dev/null)" -- "$cur")) ;;
+            {controlled}) COMPREPLY=($(compgen -W "$("$1" cli "${{context[@]}}" fan-names --controlled 2>/dev/null)" -- "$cur")) ;;
+            *) COMPREPLY=($(compgen -f -- "$cur")) ;;
+        esac
+        return
+    fi
+    COMPREPLY=($(compgen -f -- "$cur"))
+}}
+
+complete -F _coold_rs coold-rs
+"#,
+        modes = visible_subcommands(cli).join(" "),
+        cli_commands = cli_command.map(visible_subcommands).unwrap_or_default().join(" "),
+        flag_cases = flag_cases,
+        configured = CONFIGURED_FAN_COMMANDS.join("|"),
+        controlled = CONTROLLED_FAN_COMMANDS.join("|"),
+    ));
+    script
+}
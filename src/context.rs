@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+/// Daemon the CLI talks to when no context is selected
+pub const LOCAL_URL: &str = "http://127.0.0.1:8080";
+const API_PATH: &str = "/api/v1";
+
+/// A daemon the CLI can administer
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Context {
+    /// Base URL of the daemon, e.g. http://10.0.0.5:8080
+    pub url: String,
+    /// Sent as a bearer token with every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Context {
+    pub fn local() -> Self {
+        Self { url: LOCAL_URL.to_string(), token: None }
+    }
+
+    /// URL the API endpoints are relative to
+    pub fn api_url(&self) -> String {
+        let url = self.url.trim_end_matches('/');
+        if url.ends_with(API_PATH) {
+            url.to_string()
+        } else {
+            format!("{}{}", url, API_PATH)
+        }
+    }
+}
+
+/// Client configuration file: the known daemons and the one used by default
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Contexts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, Context>,
+}
+
+// $XDG_CONFIG_HOME/coold-rs/contexts.json, or ~/.config/coold-rs/contexts.json
+fn contexts_path() -> io::Result<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Neither XDG_CONFIG_HOME nor HOME is set"))?;
+    Ok(config_dir.join("coold-rs").join("contexts.json"))
+}
+
+impl Contexts {
+    pub fn load() -> io::Result<Self> {
+        let path = contexts_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("Failed to read {}: {}", path.display(), e))),
+        };
+        serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = contexts_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Tokens are stored in there, keep it private
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to write {}: {}", path.display(), e)))?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Context named on the command line, else the current one, else the local daemon
+    pub fn select(&self, name: Option<&str>) -> Result<Context, String> {
+        match name.or(self.current.as_deref()) {
+            Some(name) => self.contexts.get(name).cloned().ok_or_else(|| format!("Unknown context: {}", name)),
+            None => Ok(Context::local()),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+
+pub const DEFAULT_LOG_FILE: &str = "/var/log/coold.log";
+
+fn check(result: libc::c_int, what: &str) -> io::Result<()> {
+    if result < 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(err.kind(), format!("{} failed: {}", what, err)));
+    }
+    Ok(())
+}
+
+// Fork, keeping only the child
+fn fork_child() -> io::Result<()> {
+    // SAFETY: called before any other thread is started, so the child gets a consistent process
+    let pid = unsafe { libc::fork() };
+    check(pid, "fork")?;
+    if pid > 0 {
+        process::exit(0);
+    }
+    Ok(())
+}
+
+/// Detach into the background as a traditional daemon: fork, start a new session, fork
+/// again so the daemon can never reacquire a terminal, and send stdout and stderr to
+/// `log_file`. The working directory is kept, since config.json is read from it.
+/// Must run before any thread is started, the async runtime included.
+pub fn daemonize(log_file: &Path) -> io::Result<()> {
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to open log file {}: {}", log_file.display(), e)))?;
+    let null = File::open("/dev/null")?;
+    println!("Running in the background, logging to {}", log_file.display());
+
+    fork_child()?;
+    // SAFETY: plain system calls on descriptors owned by `null` and `log`
+    unsafe {
+        check(libc::setsid(), "setsid")?;
+    }
+    fork_child()?;
+    unsafe {
+        libc::umask(0o022);
+        check(libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO), "dup2")?;
+        check(libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO), "dup2")?;
+        check(libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO), "dup2")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use coold_core::api_types::FanStatus;
+use coold_core::backend;
+use coold_core::daemon::{enumerate_hwmon_devices, Config, DeviceMatch, FanBackend, FanConfig, HwmonDeviceInfo};
+use crate::pidfile;
+
+/// Outcome of the checks, printed as they run
+#[derive(Default)]
+pub struct Report {
+    problems: usize,
+}
+
+impl Report {
+    pub fn ok(&mut self, message: &str) {
+        println!("✓ {}", message);
+    }
+
+    /// Something wrong, with what to do about it
+    pub fn problem(&mut self, message: &str, fix: &str) {
+        println!("✗ {}", message);
+        println!("    → {}", fix);
+        self.problems += 1;
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        println!();
+        match self.problems {
+            0 => {
+                println!("No problem found");
+                Ok(())
+            }
+            1 => Err("1 problem found".into()),
+            count => Err(format!("{} problems found", count).into()),
+        }
+    }
+}
+
+/// Whether another daemon holds the PID file lock, given whether the API answered
+pub fn check_pid_file(report: &mut Report, path: &Path, daemon_reachable: bool) {
+    match (pidfile::holder(path), daemon_reachable) {
+        (Ok(Some(pid)), true) => report.ok(&format!("Daemon running as pid {} ({})", pid, path.display())),
+        (Ok(Some(pid)), false) => report.problem(
+            &format!("A daemon (pid {}) holds {} but its API doesn't answer", pid, path.display()),
+            "It may be stuck or listening elsewhere: check its logs, the selected context (`cli context list`), or restart it",
+        ),
+        (Ok(None), true) => report.ok(&format!("No daemon holds {}, the one answering uses another PID file", path.display())),
+        (Ok(None), false) => report.ok(&format!("No daemon holds {}", path.display())),
+        (Err(e), _) => report.problem(
+            &format!("Can't check {}: {}", path.display(), e),
+            "Run the doctor as root to check for conflicting daemons",
+        ),
+    }
+}
+
+/// Load the configuration file, reporting why it can't be used
+pub fn check_config(report: &mut Report, path: &Path) -> Option<Config> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            report.problem(
+                &format!("Can't read {}: {}", path.display(), e),
+                "Run the doctor from the daemon's directory, pass --config, or create one with `coold-rs setup`",
+            );
+            return None;
+        }
+    };
+    match serde_json::from_str::<Config>(&content) {
+        Ok(config) => {
+            report.ok(&format!("{} is valid", path.display()));
+            Some(config)
+        }
+        Err(e) => {
+            report.problem(
+                &format!("{} is invalid: {}", path.display(), e),
+                "Fix the file at the reported line, the schema printed by `coold-rs schema` helps editors point out mistakes",
+            );
+            None
+        }
+    }
+}
+
+/// Outputs driven by several fans, which would fight over them
+pub fn check_duplicate_pwms(report: &mut Report, config: &Config) {
+    let mut outputs: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for (name, fan) in config.controlled_fans() {
+        outputs.entry((fan.pwm_name, fan.pwm_input)).or_default().push(name);
+    }
+    let mut duplicates = 0;
+    for ((pwm_name, pwm_input), mut fans) in outputs {
+        if fans.len() > 1 {
+            fans.sort();
+            report.problem(
+                &format!("{}/{} is driven by {}", pwm_name, pwm_input, fans.join(", ")),
+                "Keep a single fan on the output, or make the others follow it with `follows`",
+            );
+            duplicates += 1;
+        }
+    }
+    if duplicates == 0 {
+        report.ok("Every PWM output is driven by a single fan");
+    }
+}
+
+// hwmon device named `name`, the one `device` designates if given, reporting missing and
+// ambiguous names
+fn find_device<'a>(report: &mut Report, devices: &'a [HwmonDeviceInfo], fan: &str, name: &str, device: Option<&DeviceMatch>) -> Option<&'a HwmonDeviceInfo> {
+    let matching: Vec<&HwmonDeviceInfo> = devices.iter()
+        .filter(|info| info.name == name && device.is_none_or(|device| device.matches(Path::new(&info.hwmon_path))))
+        .collect();
+    match matching.as_slice() {
+        [] if device.is_some() => {
+            let wanted = match device {
+                Some(DeviceMatch::Path(path)) => format!("at {}", path),
+                Some(DeviceMatch::Serial(serial)) => format!("with serial {}", serial),
+                None => String::new(),
+            };
+            report.problem(
+                &format!("{}: no hwmon device named '{}' is {}", fan, name, wanted),
+                "Check the bus paths and serials with `cli devices`",
+            );
+            None
+        }
+        [] => {
+            let mut known: Vec<&str> = devices.iter().map(|device| device.name.as_str()).collect();
+            known.sort();
+            known.dedup();
+            report.problem(
+                &format!("{}: no hwmon device named '{}'", fan, name),
+                &format!("Load its driver module (see `sensors-detect`), or use one of: {}", known.join(", ")),
+            );
+            None
+        }
+        [device] => Some(device),
+        [device, ..] => {
+            report.problem(
+                &format!("{}: {} hwmon devices are named '{}', {} is used", fan, matching.len(), name, device.hwmon_path),
+                "Check that it is the right one with `cli devices`, and pick one with sensor_device or pwm_device",
+            );
+            Some(device)
+        }
+    }
+}
+
+fn writable(path: &Path) -> std::io::Result<()> {
+    OpenOptions::new().write(true).open(path).map(|_| ())
+}
+
+/// Every fan's sensor resolves and reads, and its PWM output can be driven
+pub fn check_fans(report: &mut Report, config: &Config, daemon_running: bool) {
+    let devices = enumerate_hwmon_devices();
+    let mut fans: Vec<(String, FanConfig)> = config.controlled_fans().into_iter().collect();
+    fans.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (name, mut fan) in fans {
+        if fan.reads_hwmon_sensor() {
+            let Some(device) = find_device(report, &devices, &name, &fan.sensor_name, fan.sensor_device.as_ref()) else {
+                continue;
+            };
+            // sensor_input may be the label of one of the device's sensors
+            let input = device.sensors.iter()
+                .find(|sensor| sensor.label.as_deref() == Some(fan.sensor_input.as_str()))
+                .map_or(fan.sensor_input.clone(), |sensor| sensor.input.clone());
+            fan.sensor_input = Path::new(&device.hwmon_path).join(input).to_string_lossy().to_string();
+        }
+        if fan.follows.is_none() {
+            match backend::temp_source(&fan).read_temp() {
+                Ok(temp) => report.ok(&format!("{}: sensor reads {}°C", name, temp)),
+                Err(e) => report.problem(
+                    &format!("{}: can't read the sensor: {}", name, e),
+                    "Check the sensor with `cli sensors`, hwmon inputs are named like temp1_input or by their label",
+                ),
+            }
+        }
+
+        if fan.backend != FanBackend::Hwmon {
+            report.ok(&format!("{}: {:?} output not checked, only hwmon outputs are", name, fan.backend));
+            continue;
+        }
+        let Some(device) = find_device(report, &devices, &name, &fan.pwm_name, fan.pwm_device.as_ref()) else {
+            continue;
+        };
+        let pwm = PathBuf::from(&device.hwmon_path).join(&fan.pwm_input);
+        let enable = PathBuf::from(format!("{}_enable", pwm.display()));
+        if !pwm.exists() {
+            report.problem(
+                &format!("{}: {} doesn't exist", name, pwm.display()),
+                &format!("Pick one of the outputs of {}: {}", device.name, device.pwms.join(", ")),
+            );
+            continue;
+        }
+        match writable(&pwm).and_then(|_| writable(&enable)) {
+            Ok(()) => report.ok(&format!("{}: {} is writable", name, pwm.display())),
+            Err(e) => report.problem(
+                &format!("{}: can't write {} or its _enable file: {}", name, pwm.display(), e),
+                "The daemon needs write access: run it as root, or run the doctor as the daemon's user",
+            ),
+        }
+        fan.pwm_input = pwm.to_string_lossy().to_string();
+        // Left in manual mode by a daemon that didn't exit cleanly, the fan stays at its last speed
+        if !daemon_running && backend::pwm_sink(&fan).is_manual() {
+            report.problem(
+                &format!("{}: {} is in manual mode while no daemon runs", name, enable.display()),
+                "The fan stays at its last speed: start the daemon, or hand it back to the firmware by writing 2 (or 5 on some drivers) to the _enable file",
+            );
+        }
+    }
+}
+
+/// Problems the running daemon reported in /status
+pub fn check_status(report: &mut Report, fans: &[FanStatus]) {
+    let mut healthy = true;
+    for fan in fans {
+        if fan.control_reasserts > 0 {
+            healthy = false;
+            report.problem(
+                &format!("{}: the firmware took the fan back {} times", fan.name, fan.control_reasserts),
+                "Disable the BIOS fan control (Smart Fan, Q-Fan...) for this header, or set pwm_enable values matching the driver",
+            );
+        }
+        if fan.write_errors > 0 {
+            healthy = false;
+            report.problem(
+                &format!("{}: writes fail: {}", fan.name, fan.last_write_error.as_deref().unwrap_or("unknown error")),
+                "Check that the PWM output still exists with `cli pwms`, and that the daemon still runs as root",
+            );
+        }
+        if fan.read_errors > 0 {
+            healthy = false;
+            report.problem(&format!("{}: the sensor can't be read", fan.name), "Check the sensor with `cli sensors`");
+        }
+        if let Some(error) = &fan.self_test_error {
+            healthy = false;
+            report.problem(
+                &format!("{}: the startup self-test failed: {}", fan.name, error),
+                "Check the wiring and that pwm_input and the tachometer belong to the same header, `cli detect` matches them",
+            );
+        }
+        if fan.stalled {
+            healthy = false;
+            report.problem(
+                &format!("{}: control loop stuck", fan.name),
+                "A sensor or device read hangs, e.g. a sleeping drive: see the daemon's logs",
+            );
+        }
+    }
+    if healthy {
+        report.ok(&format!("The daemon reports no problem on its {} fans", fans.len()));
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use coold_core::daemon::FanController;
+use coold_core::logging;
+use serde_json::json;
+use coold_core::log;
+
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_dump(_signal: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Dump the daemon state on SIGUSR1, to the log or to `file`. The dump is written
+/// from a thread of its own, so it still works while the API or the async runtime is stuck.
+pub fn install(controller: FanController, file: Option<PathBuf>) {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    let handler = request_dump as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGUSR1, handler) } == libc::SIG_ERR {
+        log!("Failed to install the SIGUSR1 handler, state dumps are unavailable");
+        return;
+    }
+    let running = controller.get_running();
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(200));
+            if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+                write_dump(&controller, file.as_ref());
+            }
+        }
+    });
+}
+
+fn write_dump(controller: &FanController, file: Option<&PathBuf>) {
+    let state = controller.dump_state();
+    if file.is_none() && logging::is_json() {
+        logging::event("state_dump", json!({ "state": state }));
+        return;
+    }
+    let dump = match serde_json::to_string_pretty(&state) {
+        Ok(dump) => dump,
+        Err(e) => {
+            log!("Failed to serialize the state dump: {}", e);
+            return;
+        }
+    };
+    match file {
+        Some(path) => match fs::write(path, dump + "\n") {
+            Ok(()) => log!("State dumped to {}", path.display()),
+            Err(e) => log!("Failed to write the state dump to {}: {}", path.display(), e),
+        },
+        None => log!("State dump:\n{}", dump),
+    }
+}
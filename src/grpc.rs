@@ -0,0 +1,256 @@
+// tonic handlers answer with a Status, which the helpers they call return as is
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{json, Value};
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Request, Response, Status};
+use coold_core::api_types::{self, AuditEntry, FanFilter, REQUEST_ID_HEADER};
+use coold_core::daemon::{ControlCommand, FanConfig, FanController};
+use coold_core::log;
+use crate::api::{fan_statuses, new_request_id, valid_request_id};
+use crate::audit;
+use crate::auth::{ApiToken, ApiTokens, Role};
+
+pub const DEFAULT_GRPC_PORT: u16 = 50051;
+
+mod proto {
+    tonic::include_proto!("coold.v1");
+}
+
+use proto::fan_control_server::{FanControl, FanControlServer};
+use proto::{
+    ClearOverrideRequest, Empty, Fan, FanStatus, FanStep, GetFanRequest, GetStatusRequest, GetStatusResponse,
+    ListFansRequest, ListFansResponse, OverrideFanRequest, RemoveFanRequest, UpdateFanCurveRequest,
+};
+
+/// Serve the FanControl service of proto/coold.proto, next to the REST API
+pub async fn start_grpc(controller: FanController, tokens: ApiTokens, port: u16) -> Result<(), tonic::transport::Error> {
+    log!("Starting gRPC server on port {}", port);
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    tonic::transport::Server::builder()
+        .add_service(FanControlServer::new(GrpcService { controller, tokens }))
+        .serve(address)
+        .await
+}
+
+struct GrpcService {
+    controller: FanController,
+    tokens: ApiTokens,
+}
+
+impl GrpcService {
+    // Same rules as the REST API: with tokens configured, every call needs one in the
+    // `authorization` metadata, and only admin tokens may change anything
+    fn authorize<T>(&self, request: &Request<T>, role: Role) -> Result<(), Status> {
+        if !self.tokens.is_enabled() {
+            return Ok(());
+        }
+        let token = self.token(request).ok_or_else(|| Status::unauthenticated("Missing or invalid API token"))?;
+        if role == Role::Admin && token.role == Role::ReadOnly {
+            return Err(Status::permission_denied(format!("Token '{}' is read-only", token.name)));
+        }
+        Ok(())
+    }
+
+    fn token<T>(&self, request: &Request<T>) -> Option<&ApiToken> {
+        request.metadata().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.tokens.find(token.trim()))
+    }
+
+    // Run a call changing something and record it in the audit log, as the REST API
+    // does. Commands are logged with the request ID the client sent, or a new one.
+    fn audited<T, R>(&self, request: &Request<T>, rpc: &str, body: Value, handle: impl FnOnce(&str) -> Result<R, Status>) -> Result<R, Status> {
+        let request_id = request.metadata().get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| valid_request_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(new_request_id);
+        let result = self.authorize(request, Role::Admin).and_then(|_| handle(&request_id));
+        audit::record(AuditEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            client: request.remote_addr().map_or_else(|| "unknown".to_string(), |address| address.to_string()),
+            token: self.token(request).map(|token| token.name.clone()),
+            request_id: Some(request_id),
+            method: "gRPC".to_string(),
+            path: format!("/coold.v1.FanControl/{}", rpc),
+            request: Some(body),
+            status: http_status(result.as_ref().err().map_or(Code::Ok, Status::code)),
+        });
+        result
+    }
+
+    fn find_fan(&self, name: &str) -> Result<Fan, Status> {
+        self.controller.get_config().fan.get(name)
+            .map(|fan| fan_message(name, fan))
+            .ok_or_else(|| Status::not_found(format!("Fan '{}' not found", name)))
+    }
+
+    fn check_controlled(&self, name: &str) -> Result<(), Status> {
+        if !self.controller.get_config().controlled_fans().contains_key(name) {
+            return Err(Status::not_found(format!("Fan '{}' not found", name)));
+        }
+        Ok(())
+    }
+}
+
+// HTTP status matching a gRPC code, for the audit log shared with the REST API
+fn http_status(code: Code) -> u16 {
+    match code {
+        Code::Ok => 200,
+        Code::InvalidArgument => 400,
+        Code::Unauthenticated => 401,
+        Code::PermissionDenied => 403,
+        Code::NotFound => 404,
+        Code::FailedPrecondition => 409,
+        _ => 500,
+    }
+}
+
+fn steps_message(steps: &[coold_core::daemon::FanStep]) -> Vec<FanStep> {
+    steps.iter().map(|step| FanStep { temp: step.temp, power: step.power as u32 }).collect()
+}
+
+fn steps_json(steps: &[FanStep]) -> Value {
+    steps.iter().map(|step| json!({"temp": step.temp, "power": step.power})).collect()
+}
+
+fn fan_message(name: &str, fan: &FanConfig) -> Fan {
+    Fan {
+        name: name.to_string(),
+        sensor_name: fan.sensor_name.clone(),
+        sensor_input: fan.sensor_input.clone(),
+        pwm_name: fan.pwm_name.clone(),
+        pwm_input: fan.pwm_input.clone(),
+        steps: steps_message(&fan.steps),
+        curve: fan.curve.clone(),
+        group: fan.group.clone(),
+        follows: fan.follows.clone(),
+    }
+}
+
+fn status_message(status: api_types::FanStatus) -> FanStatus {
+    FanStatus {
+        name: status.name,
+        temperature: status.temperature,
+        power: status.power.map(u32::from),
+        steps: steps_message(&status.steps),
+        control_reasserts: status.control_reasserts,
+        override_power: status.override_power.map(u32::from),
+        critical: status.critical,
+        rpm: status.rpm,
+        expected_rpm: status.expected_rpm,
+        failing: status.failing,
+        group: status.group,
+        demand: status.demand.map(u32::from),
+        max_power: status.max_power.map(u32::from),
+        capped: status.capped,
+    }
+}
+
+// Sensor reads may block (e.g. remote sources), keep them off the async workers
+async fn read_status(controller: FanController) -> Result<GetStatusResponse, Status> {
+    let statuses = tokio::task::spawn_blocking(move || fan_statuses(&controller, &FanFilter::default()))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(GetStatusResponse { fans: statuses.into_iter().map(status_message).collect() })
+}
+
+#[tonic::async_trait]
+impl FanControl for GrpcService {
+    async fn get_status(&self, request: Request<GetStatusRequest>) -> Result<Response<GetStatusResponse>, Status> {
+        self.authorize(&request, Role::ReadOnly)?;
+        Ok(Response::new(read_status(self.controller.clone()).await?))
+    }
+
+    type WatchStatusStream = Pin<Box<dyn Stream<Item = Result<GetStatusResponse, Status>> + Send>>;
+
+    async fn watch_status(&self, request: Request<GetStatusRequest>) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.authorize(&request, Role::ReadOnly)?;
+        // The first status is sent right away, then one after each control cycle; cycles
+        // ending while a status is being read are folded into the next one
+        let controller = self.controller.clone();
+        let stream = WatchStream::new(self.controller.watch_cycles())
+            .then(move |_| read_status(controller.clone()));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn list_fans(&self, request: Request<ListFansRequest>) -> Result<Response<ListFansResponse>, Status> {
+        self.authorize(&request, Role::ReadOnly)?;
+        let config = self.controller.get_config();
+        let mut fans: Vec<Fan> = config.fan.iter().map(|(name, fan)| fan_message(name, fan)).collect();
+        fans.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Response::new(ListFansResponse { fans }))
+    }
+
+    async fn get_fan(&self, request: Request<GetFanRequest>) -> Result<Response<Fan>, Status> {
+        self.authorize(&request, Role::ReadOnly)?;
+        Ok(Response::new(self.find_fan(&request.get_ref().name)?))
+    }
+
+    async fn update_fan_curve(&self, request: Request<UpdateFanCurveRequest>) -> Result<Response<Fan>, Status> {
+        let update = request.get_ref();
+        let body = json!({"name": update.name, "steps": steps_json(&update.steps)});
+        self.audited(&request, "UpdateFanCurve", body, |_| {
+            if let Some(step) = update.steps.iter().find(|step| step.power > 100) {
+                return Err(Status::invalid_argument(format!("Power {} at {}°C is above 100", step.power, step.temp)));
+            }
+            let steps = update.steps.iter()
+                .map(|step| coold_core::daemon::FanStep { temp: step.temp, power: step.power as u8 })
+                .collect();
+            if !self.controller.update_fan_steps(&update.name, steps, None) {
+                return Err(Status::not_found(format!("Fan '{}' not found", update.name)));
+            }
+            Ok(Response::new(self.find_fan(&update.name)?))
+        })
+    }
+
+    async fn remove_fan(&self, request: Request<RemoveFanRequest>) -> Result<Response<Empty>, Status> {
+        let name = &request.get_ref().name;
+        self.audited(&request, "RemoveFan", json!({"name": name}), |_| match self.controller.remove_fan(name) {
+            None => Err(Status::failed_precondition(format!("Fan '{}' is followed by other fans", name))),
+            Some(false) => Err(Status::not_found(format!("Fan '{}' not found", name))),
+            Some(true) => Ok(Response::new(Empty {})),
+        })
+    }
+
+    async fn override_fan(&self, request: Request<OverrideFanRequest>) -> Result<Response<Empty>, Status> {
+        let OverrideFanRequest { name, power } = request.get_ref().clone();
+        self.audited(&request, "OverrideFan", json!({"name": name, "power": power}), |request_id| {
+            if power > 100 {
+                return Err(Status::invalid_argument("Power must be between 0 and 100"));
+            }
+            self.check_controlled(&name)?;
+            self.controller.send_from(ControlCommand::Override { fan: name.clone(), power: Some(power as u8) }, request_id);
+            Ok(Response::new(Empty {}))
+        })
+    }
+
+    async fn clear_override(&self, request: Request<ClearOverrideRequest>) -> Result<Response<Empty>, Status> {
+        let name = request.get_ref().name.clone();
+        self.audited(&request, "ClearOverride", json!({"name": name}), |request_id| {
+            self.check_controlled(&name)?;
+            self.controller.send_from(ControlCommand::Override { fan: name.clone(), power: None }, request_id);
+            Ok(Response::new(Empty {}))
+        })
+    }
+
+    async fn pause(&self, request: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.audited(&request, "Pause", json!({}), |request_id| {
+            self.controller.send_from(ControlCommand::Pause(true), request_id);
+            Ok(Response::new(Empty {}))
+        })
+    }
+
+    async fn resume(&self, request: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.audited(&request, "Resume", json!({}), |request_id| {
+            self.controller.send_from(ControlCommand::Pause(false), request_id);
+            Ok(Response::new(Empty {}))
+        })
+    }
+}
@@ -1,13 +1,29 @@
-mod daemon;
 mod api;
 mod cli;
+mod privilege;
+mod pidfile;
+mod context;
+mod setup;
+mod audit;
+mod auth;
+mod doctor;
+mod completion;
+mod dump;
+mod daemonize;
+mod bundle;
+mod grpc;
 
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
-use daemon::{create_config, FanController};
+use std::path::PathBuf;
+use coold_core::{backend, logging, recording, simulation};
+use std::sync::atomic::Ordering;
+use coold_core::daemon::{config_schema, create_config, load_config, FanController, CONFIG_PATH};
 use api::start_api;
-use clap::{Parser, Subcommand};
+use auth::ApiTokens;
+use pidfile::{PidFile, DEFAULT_PID_FILE};
+use daemonize::DEFAULT_LOG_FILE;
+use grpc::DEFAULT_GRPC_PORT;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use coold_core::log;
 
 #[derive(Parser)]
 #[command(name = "coold-rs")]
@@ -21,67 +37,278 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run the daemon with REST API
-    Daemon,
+    Daemon(DaemonArgs),
+    /// Pick sensors, PWM outputs and curves interactively and write an initial config
+    Setup {
+        /// Configuration file to write
+        #[arg(long, default_value = CONFIG_PATH)]
+        output: PathBuf,
+        /// Root of the sysfs tree to find devices in
+        #[arg(long, env = "COOLD_SYSFS_ROOT", value_name = "DIR")]
+        sysfs_root: Option<PathBuf>,
+    },
     /// Use CLI to interact with the daemon
     Cli {
+        /// Named daemon to talk to (see `cli context`), instead of the current one
+        #[arg(long, env = "COOLD_CONTEXT")]
+        context: Option<String>,
         #[command(subcommand)]
         cli_command: cli::CliCommands,
     },
+    /// Print the JSON Schema of the configuration file, for editors to validate it against
+    Schema,
+    /// Print a shell completion script, e.g. `source <(coold-rs completions bash)`
+    Completions {
+        shell: completion::Shell,
+    },
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Args, Default)]
+struct DaemonArgs {
+    /// Switch to this user once the fans are set up, so the API never runs as root
+    #[arg(long)]
+    user: Option<String>,
+    /// Group to switch to (defaults to the user's primary group)
+    #[arg(long, requires = "user")]
+    group: Option<String>,
+    /// Detach into the background as a traditional daemon, for init systems other than systemd
+    #[arg(long, conflicts_with = "foreground")]
+    daemonize: bool,
+    /// Stay in the foreground and log to stdout, as systemd expects (the default)
+    #[arg(long)]
+    foreground: bool,
+    /// Log file of a --daemonize daemon [default: /var/log/coold.log]
+    #[arg(long, value_name = "FILE", requires = "daemonize")]
+    log_file: Option<PathBuf>,
+    /// PID file used to make sure only one daemon controls the fans [default: /run/coold.pid]
+    #[arg(long)]
+    pid_file: Option<PathBuf>,
+    /// Start even if another instance holds the PID file lock
+    #[arg(long)]
+    force: bool,
+    /// Root of the sysfs tree to find devices in, e.g. a bind-mounted fake in a container
+    #[arg(long, env = "COOLD_SYSFS_ROOT", value_name = "DIR")]
+    sysfs_root: Option<PathBuf>,
+    /// Run against simulated sensors and fans scripted by this scenario file, without touching the hardware
+    #[arg(long, value_name = "SCENARIO", conflicts_with = "replay")]
+    simulate: Option<PathBuf>,
+    /// Append every raw sensor reading to this file, for --replay
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Feed a --record file back through the controller instead of reading the hardware
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    /// Read the sensors and report what would be done, but never write to the fans
+    #[arg(long)]
+    observe: bool,
+    /// Pulse every fan at startup and check that its tachometer follows, to catch miswired configurations
+    #[arg(long)]
+    self_test: bool,
+    /// Write the state dumped on SIGUSR1 to this file instead of the log
+    #[arg(long, value_name = "FILE")]
+    dump_file: Option<PathBuf>,
+    /// Format of the daemon's logs: text lines, or JSON lines for log shippers
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Append every API call changing something to this file, for GET /audit
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+    /// Require the bearer tokens listed in this file for the API, with their roles
+    #[arg(long, value_name = "FILE")]
+    tokens_file: Option<PathBuf>,
+    /// Port of the gRPC server (proto/coold.proto), next to the REST API on 8080 [default: 50051]
+    #[arg(long, value_name = "PORT")]
+    grpc_port: Option<u16>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
     
     let cli = Cli::parse();
     
+    // Forking has to happen before the async runtime starts its threads
+    if let Some(Commands::Daemon(args)) = &cli.command {
+        if args.daemonize {
+            let log_file = args.log_file.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_LOG_FILE));
+            daemonize::daemonize(&log_file)?;
+        }
+    }
+    
+    tokio::runtime::Runtime::new()?.block_on(run(cli))
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
     match cli.command {
-        Some(Commands::Cli { cli_command }) => {
+        Some(Commands::Cli { context, cli_command }) => {
             // Run CLI mode
-            cli::run_cli(cli_command).await?;
+            cli::run_cli(cli_command, context).await?;
         }
-        Some(Commands::Daemon) | None => {
+        Some(Commands::Setup { output, sysfs_root }) => {
+            if let Some(root) = sysfs_root {
+                backend::set_sysfs_root(root);
+            }
+            setup::run_setup(&output)?;
+        }
+        Some(Commands::Schema) => {
+            println!("{}", serde_json::to_string_pretty(&config_schema())?);
+        }
+        Some(Commands::Completions { shell }) => {
+            print!("{}", completion::script(&Cli::command(), shell));
+        }
+        Some(Commands::Daemon(args)) => {
+            run_daemon(args).await?;
+        }
+        None => {
             // Run daemon mode (default)
-            run_daemon().await?;
+            run_daemon(DaemonArgs::default()).await?;
         }
     }
     
     Ok(())
 }
 
-async fn run_daemon() -> std::io::Result<()> {
-    println!("Starting coold-rs fan control daemon with REST API...");
+async fn run_daemon(args: DaemonArgs) -> std::io::Result<()> {
+    if let LogFormat::Json = args.log_format {
+        logging::set_json();
+    }
+    log!("Starting coold-rs fan control daemon with REST API...");
+
+    let scenario = match (&args.simulate, &args.replay) {
+        (Some(path), _) => Some((simulation::load(path)?, path)),
+        (None, Some(path)) => Some((recording::load_replay(path)?, path)),
+        (None, None) => None,
+    };
+    let simulated = scenario.is_some();
+
+    if args.observe {
+        log!("Observe mode: sensors are read, fans are left to the firmware");
+        backend::set_observe_only();
+    }
+
+    // A simulation or an observer doesn't touch the fans, so it can run next to a real daemon
+    let pid_file_path = match (&args.pid_file, simulated || args.observe) {
+        (Some(path), _) => Some(path.clone()),
+        (None, false) => Some(PathBuf::from(DEFAULT_PID_FILE)),
+        (None, true) => None,
+    };
+    let _pid_file = match pid_file_path {
+        Some(path) => Some(PidFile::acquire(&path, args.force)?),
+        None => None,
+    };
+
+    if let Some(root) = &args.sysfs_root {
+        log!("Using sysfs root {}", root.display());
+        backend::set_sysfs_root(root.clone());
+    }
+
+    if let Some(path) = &args.record {
+        recording::start(path)?;
+        log!("Recording sensor readings to {}", path.display());
+    }
+
+    if let Some(path) = &args.audit_log {
+        audit::start(path)?;
+        log!("Auditing API changes to {}", path.display());
+    }
+
+    // Read while still root, the file holds secrets
+    let tokens = match &args.tokens_file {
+        Some(path) => {
+            let tokens = ApiTokens::load(path)?;
+            log!("API restricted to the {} tokens of {}", tokens.tokens.len(), path.display());
+            tokens
+        }
+        None => ApiTokens::default(),
+    };
+
+    let config = match scenario {
+        Some((scenario, scenario_path)) => {
+            let config = scenario.config.clone().unwrap_or_else(load_config);
+            simulation::start(scenario);
+            log!("Simulating scenario {}, configuration changes won't be saved", scenario_path.display());
+            config
+        }
+        None => create_config(),
+    };
+    let controller = FanController::new(config.clone());
+    controller.install_panic_hook();
+
+    if let Some(user) = &args.user {
+        // Take manual control while still root, then give up root for good; with a
+        // startup delay the fans stay with the firmware until the delay is over
+        if config.startup_delay_secs.is_none_or(|secs| secs == 0) {
+            controller.init_fans();
+        }
+        privilege::drop_privileges(user, args.group.as_deref(), &config)?;
+    }
+
+    if args.self_test {
+        let tested = controller.clone();
+        let failed = tokio::task::spawn_blocking(move || tested.self_test()).await.unwrap_or(0);
+        if failed > 0 {
+            log!("Self-test failed for {} fans, they are still controlled", failed);
+        }
+    }
 
-    let config = create_config();
-    let controller = FanController::new(config);
     let running = controller.get_running();
     let running_clone = running.clone();
 
     // Set up Ctrl+C handler
     ctrlc::set_handler(move || {
-        println!("\nReceived Ctrl+C, shutting down gracefully...");
+        log!("\nReceived Ctrl+C, shutting down gracefully...");
         running_clone.store(false, Ordering::SeqCst);
     }).expect("Error setting Ctrl+C handler");
 
-    // Start the fan control daemon in a separate thread
+    // Start the fan control daemon, which runs one task per fan
     let controller_clone = controller.clone();
-    let daemon_handle = thread::spawn(move || {
-        controller_clone.run();
+    let daemon_handle = tokio::spawn(async move {
+        controller_clone.run().await;
     });
+    controller.start_watchdog();
+    dump::install(controller.clone(), args.dump_file.clone());
+
+    // Save configuration changes in the background
+    if !simulated {
+        tokio::spawn(controller.clone().run_persistence());
+    }
+    let persistence_controller = controller.clone();
 
-    // Start the REST API server
-    let api_handle = start_api(controller, 8080);
+    // Start the gRPC and REST API servers. The daemon keeps running without gRPC,
+    // e.g. when its port is taken.
+    let grpc_controller = controller.clone();
+    let grpc_tokens = tokens.clone();
+    let grpc_port = args.grpc_port.unwrap_or(DEFAULT_GRPC_PORT);
+    tokio::spawn(async move {
+        match grpc::start_grpc(grpc_controller, grpc_tokens, grpc_port).await {
+            Ok(()) => log!("gRPC server stopped"),
+            Err(e) => log!("gRPC server stopped: {}", e),
+        }
+    });
+    let api_handle = start_api(controller, tokens, 8080);
 
-    // Wait for either the daemon or API to finish
+    // Wait for either the daemon or the API to finish
     tokio::select! {
         _ = api_handle => {
-            println!("API server stopped");
+            log!("API server stopped");
         }
-        _ = tokio::task::spawn_blocking(move || daemon_handle.join()) => {
-            println!("Daemon stopped");
+        _ = daemon_handle => {
+            log!("Daemon stopped");
         }
     }
 
-    println!("Shutdown complete.");
+    // Flush changes still waiting for their quiet period
+    if let Err(e) = persistence_controller.save_config_now() {
+        log!("Failed to save configuration: {}", e);
+    }
+    persistence_controller.run_stop_hook();
+
+    log!("Shutdown complete.");
     Ok(())
 }
@@ -0,0 +1,90 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use coold_core::log;
+
+pub const DEFAULT_PID_FILE: &str = "/run/coold.pid";
+
+/// Exclusive lock on the PID file, held for the lifetime of the daemon so two
+/// instances can't fight over the same PWM outputs
+pub struct PidFile {
+    // Keeps the lock alive; the kernel releases it when the process exits
+    _file: File,
+    path: PathBuf,
+    // Pid of the instance holding the lock when started with --force
+    forced_over: Option<String>,
+}
+
+impl PidFile {
+    pub fn acquire(path: &Path, force: bool) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to open PID file {}: {}", path.display(), e)))?;
+
+        // SAFETY: the descriptor is owned by `file` and stays open while locked
+        let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+        let mut forced_over = None;
+        if !locked {
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            let pid = pid.trim();
+            if !force {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Another coold-rs instance (pid {}) holds {}. Stop it first or use --force to start anyway",
+                        if pid.is_empty() { "unknown" } else { pid },
+                        path.display()
+                    ),
+                ));
+            }
+            log!("Warning: ignoring the lock on {} held by pid {} (--force)", path.display(), pid);
+            forced_over = Some(pid.to_string());
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+
+        Ok(Self { _file: file, path: path.to_path_buf(), forced_over })
+    }
+}
+
+/// Pid written by the daemon holding the lock on `path`, None if nothing holds it
+pub fn holder(path: &Path) -> io::Result<Option<String>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // SAFETY: the descriptor is owned by `file`; a lock we get is released when it closes
+    let free = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } == 0;
+    if free {
+        return Ok(None);
+    }
+    let mut pid = String::new();
+    file.read_to_string(&mut pid)?;
+    Ok(Some(pid.trim().to_string()))
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // The file of a forced instance belongs to the one holding the lock, which is
+        // still running: give it its pid back rather than removing it
+        if let Some(holder) = &self.forced_over {
+            let ours = fs::read_to_string(&self.path).is_ok_and(|pid| pid.trim() == std::process::id().to_string());
+            if ours && !holder.is_empty() {
+                let _ = fs::write(&self.path, format!("{}\n", holder));
+            }
+            return;
+        }
+        // May fail once privileges are dropped; the stale file is harmless since
+        // the lock is what guards the instance
+        let _ = fs::remove_file(&self.path);
+    }
+}
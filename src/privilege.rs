@@ -0,0 +1,100 @@
+use std::ffi::CString;
+use std::io;
+use std::os::unix::fs::chown;
+use std::path::PathBuf;
+use coold_core::backend;
+use coold_core::daemon::{Config, CONFIG_PATH};
+use coold_core::log;
+
+struct Account {
+    name: CString,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+fn lookup_user(user: &str) -> io::Result<Account> {
+    let name = CString::new(user).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid user name"))?;
+    // SAFETY: getpwnam returns a pointer to static storage, which is copied out before any other call
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("User '{}' not found", user)));
+    }
+    let (uid, gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+    Ok(Account { name, uid, gid })
+}
+
+fn lookup_group(group: &str) -> io::Result<libc::gid_t> {
+    let name = CString::new(group).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid group name"))?;
+    // SAFETY: getgrnam returns a pointer to static storage, which is copied out before any other call
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Group '{}' not found", group)));
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+fn check(result: libc::c_int, what: &str) -> io::Result<()> {
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(err.kind(), format!("{} failed: {}", what, err)));
+    }
+    Ok(())
+}
+
+// Every file the daemon writes after startup: fan control files and the config file
+fn files_to_hand_over(config: &Config) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = config.controlled_fans().values()
+        .flat_map(|fan| backend::pwm_sink(fan).control_files())
+        .collect();
+    files.push(PathBuf::from(CONFIG_PATH));
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Hand the fan control files over to `user` and switch the process to it.
+///
+/// Must be called after the fans have been put in manual mode, since setup steps
+/// like exporting a pwmchip channel still need root.
+pub fn drop_privileges(user: &str, group: Option<&str>, config: &Config) -> io::Result<()> {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        log!("Not running as root, ignoring --user {}", user);
+        return Ok(());
+    }
+
+    if let Some((name, _)) = config.controlled_fans().into_iter().find(|(_, fan)| fan.backend.is_sysctl()) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Fan {} is driven through sysctls, which only root can set: run without --user", name),
+        ));
+    }
+
+    let account = lookup_user(user)?;
+    let gid = match group {
+        Some(group) => lookup_group(group)?,
+        None => account.gid,
+    };
+
+    for file in files_to_hand_over(config) {
+        if let Err(e) = chown(&file, Some(account.uid), Some(gid)) {
+            // procfs entries (e.g. /proc/acpi/ibm/fan) can't change owner
+            log!("Failed to hand over {} to {}: {}", file.display(), user, e);
+        }
+    }
+
+    // SAFETY: plain syscalls; glibc applies them to every thread of the process
+    unsafe {
+        check(libc::initgroups(account.name.as_ptr(), gid), "initgroups")?;
+        check(libc::setgid(gid), "setgid")?;
+        check(libc::setuid(account.uid), "setuid")?;
+        // Make sure root can't be regained
+        if libc::setuid(0) == 0 {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Privileges could not be dropped"));
+        }
+    }
+
+    backend::set_unprivileged();
+    log!("Dropped privileges to {} (uid {}, gid {})", user, account.uid, gid);
+    Ok(())
+}
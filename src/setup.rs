@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use coold_core::backend;
+use crate::cli::parse_steps;
+use coold_core::daemon::{enumerate_hwmon_devices, read_hwmon_sensors, Config, FanConfig, FanStep, HwmonSensorReading};
+use coold_core::templates::CURVE_TEMPLATES;
+
+// A PWM output the user can pick
+struct PwmChoice {
+    name: String,
+    pwm: String,
+    value: Option<u8>,
+}
+
+fn prompt(question: &str) -> io::Result<String> {
+    print!("{}", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Aborted"));
+    }
+    Ok(answer.trim().to_string())
+}
+
+/// Ask a yes/no question, `default` being the answer to an empty line
+pub fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        match prompt(&format!("{} {} ", question, hint))?.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+// Ask for a 1-based entry of a list of `len` items
+fn choose(question: &str, len: usize) -> io::Result<usize> {
+    loop {
+        match prompt(question)?.parse::<usize>() {
+            Ok(choice) if (1..=len).contains(&choice) => return Ok(choice - 1),
+            _ => println!("Please enter a number between 1 and {}", len),
+        }
+    }
+}
+
+fn print_sensors(sensors: &[HwmonSensorReading]) {
+    println!("Temperature sensors:");
+    for (index, sensor) in sensors.iter().enumerate() {
+        match &sensor.label {
+            Some(label) => println!("  {}) {}/{} ({}): {}°C", index + 1, sensor.name, sensor.input, label, sensor.temperature),
+            None => println!("  {}) {}/{}: {}°C", index + 1, sensor.name, sensor.input, sensor.temperature),
+        }
+    }
+}
+
+fn pwm_choices() -> Vec<PwmChoice> {
+    enumerate_hwmon_devices().into_iter()
+        .flat_map(|device| {
+            let hwmon_dir = PathBuf::from(&device.hwmon_path);
+            device.pwms.into_iter().map(move |pwm| PwmChoice {
+                value: backend::read_hwmon_pwm_value(&hwmon_dir.join(&pwm)),
+                name: device.name.clone(),
+                pwm,
+            })
+        })
+        .collect()
+}
+
+fn choose_steps() -> io::Result<Vec<FanStep>> {
+    println!("Curves:");
+    for (index, template) in CURVE_TEMPLATES.iter().enumerate() {
+        println!("  {}) {} - {}", index + 1, template.name, template.description);
+    }
+    println!("  {}) custom", CURVE_TEMPLATES.len() + 1);
+    let choice = choose("Curve: ", CURVE_TEMPLATES.len() + 1)?;
+    if let Some(template) = CURVE_TEMPLATES.get(choice) {
+        return Ok(template.steps.to_vec());
+    }
+    loop {
+        match parse_steps(&prompt("Steps (temp:power,temp:power,...): ")?) {
+            Ok(steps) => return Ok(steps),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Walk the user through picking a sensor, a PWM output and a curve for each fan,
+/// then write the resulting configuration to `path`
+pub fn run_setup(path: &Path) -> io::Result<()> {
+    println!("coold-rs setup");
+    println!("==============");
+
+    if path.exists() && !confirm(&format!("{} already exists, overwrite it?", path.display()), false)? {
+        println!("Nothing written");
+        return Ok(());
+    }
+
+    let (sensors, pwms) = (read_hwmon_sensors(), pwm_choices());
+    if sensors.is_empty() || pwms.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Found {} temperature sensors and {} PWM outputs, need at least one of each", sensors.len(), pwms.len()),
+        ));
+    }
+
+    let mut config = Config::default();
+    let mut used_pwms = HashSet::new();
+    loop {
+        // Read again every time, for live values
+        let (sensors, pwms) = (read_hwmon_sensors(), pwm_choices());
+        println!();
+        print_sensors(&sensors);
+        println!("PWM outputs:");
+        for (index, pwm) in pwms.iter().enumerate() {
+            let value = pwm.value.map_or("?".to_string(), |value| value.to_string());
+            let used = if used_pwms.contains(&index) { " (already used)" } else { "" };
+            println!("  {}) {}/{}: value {}{}", index + 1, pwm.name, pwm.pwm, value, used);
+        }
+
+        let pwm_index = choose("PWM output to control: ", pwms.len())?;
+        if used_pwms.contains(&pwm_index) && !confirm("This output is already used, use it again?", false)? {
+            continue;
+        }
+        let sensor = &sensors[choose("Sensor it follows: ", sensors.len())?];
+        let steps = choose_steps()?;
+
+        let pwm = &pwms[pwm_index];
+        let default_name = format!("{}_{}", pwm.name, pwm.pwm);
+        let name = prompt(&format!("Fan name [{}]: ", default_name))?;
+        let name = if name.is_empty() { default_name } else { name };
+
+        let fan = FanConfig {
+            backend: backend::default_backend(&pwm.name),
+            ..FanConfig::hwmon(&sensor.name, &sensor.input, &pwm.name, &pwm.pwm, steps)
+        };
+        config.fan.insert(name.clone(), fan);
+        used_pwms.insert(pwm_index);
+        println!("Added {}", name);
+
+        if !confirm("Add another fan?", used_pwms.len() < pwms.len())? {
+            break;
+        }
+    }
+
+    let content = serde_json::to_string_pretty(&config)?;
+    std::fs::write(path, content)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to write {}: {}", path.display(), e)))?;
+    println!();
+    println!("Wrote {} fans to {}, start the daemon to take control of them", config.fan.len(), path.display());
+    Ok(())
+}